@@ -2,7 +2,7 @@
 extern crate serde_derive;
 
 use argparse::{ArgumentParser, Store};
-use fcm::{Client, MessageBuilder};
+use fcm::{Client, DataMap, MessageBuilder};
 
 #[derive(Serialize)]
 struct CustomData {
@@ -15,6 +15,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     let mut device_token = String::new();
     let mut api_key = String::new();
+    let mut project_id = String::new();
 
     {
         let mut ap = ArgumentParser::new();
@@ -23,14 +24,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             .add_option(&["-t", "--device_token"], Store, "Device token");
         ap.refer(&mut api_key)
             .add_option(&["-k", "--api_key"], Store, "API key");
+        ap.refer(&mut project_id)
+            .add_option(&["-p", "--project_id"], Store, "Firebase project id");
         ap.parse_args_or_exit();
     }
 
-    let client = Client::new();
+    let client = Client::new(project_id);
     let data = CustomData { message: "howdy" };
 
     let mut builder = MessageBuilder::new(&api_key, &device_token);
-    builder.data(&data)?;
+    builder.data(DataMap::from_serialize(&data)?);
 
     let response = client.send(builder.finalize()).await?;
     println!("Sent: {:?}", response);
@@ -1,11 +1,15 @@
 use std::borrow::Cow;
-use crate::{NotificationPriority, Notification};
-use serde_json::Value;
+use crate::NotificationPriority;
+use serde_json::{Map, Value};
+
+use crate::owned::{owned, owned_vec};
+use crate::de::unknown_fields;
+use crate::message::{DataMap, Violations};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Serialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum AndroidMessagePriority {
     Normal,
@@ -15,13 +19,30 @@ pub enum AndroidMessagePriority {
 /// This struct represents a FCM Android notification. Use the
 /// corresponding `AndroidNotificationBuilder` to get an instance. You can then use
 /// this notification instance when sending a FCM message.
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AndroidFcmOptions<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    analytics_label: Option<&'a str>,
+    analytics_label: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+impl<'a> AndroidFcmOptions<'a> {
+    /// Convert into options owning their strings.
+    pub fn into_owned(self) -> AndroidFcmOptions<'static> {
+        AndroidFcmOptions {
+            analytics_label: self.analytics_label.map(owned),
+            unknown: self.unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Color {
     #[serde(skip_serializing_if = "Option::is_none")]
     red: Option<f32>,
@@ -34,30 +55,56 @@ pub struct Color {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     alpha: Option<f32>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct LightSettings<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     color: Option<Color>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    light_on_duration: Option<&'a str>,
+    light_on_duration: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    light_off_duration: Option<&'a str>,
+    light_off_duration: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> LightSettings<'a> {
+    /// Convert into light settings owning their strings.
+    pub fn into_owned(self) -> LightSettings<'static> {
+        LightSettings {
+            color: self.color,
+            light_on_duration: self.light_on_duration.map(owned),
+            light_off_duration: self.light_off_duration.map(owned),
+            unknown: self.unknown,
+        }
+    }
 }
 
 /// This struct represents a FCM Android notification. Use the
 /// corresponding `AndroidNotificationBuilder` to get an instance. You can then use
 /// this notification instance when sending a FCM message.
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AndroidConfig<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    collapse_key: Option<&'a str>,
+    collapse_key: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    data: Option<Value>,
+    data: Option<DataMap>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     direct_boot_ok: Option<bool>,
@@ -66,7 +113,7 @@ pub struct AndroidConfig<'a> {
     fcm_options: Option<AndroidFcmOptions<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    ttl: Option<&'a str>,
+    ttl: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     notification: Option<AndroidNotification<'a>>,
@@ -75,10 +122,45 @@ pub struct AndroidConfig<'a> {
     priority: Option<AndroidMessagePriority>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    restricted_package_name: Option<&'a str>,
+    restricted_package_name: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> AndroidConfig<'a> {
+    /// Convert into a config owning its strings, e.g. to send it from
+    /// another task.
+    pub fn into_owned(self) -> AndroidConfig<'static> {
+        AndroidConfig {
+            collapse_key: self.collapse_key.map(owned),
+            data: self.data,
+            direct_boot_ok: self.direct_boot_ok,
+            fcm_options: self.fcm_options.map(|o| o.into_owned()),
+            ttl: self.ttl.map(owned),
+            notification: self.notification.map(|n| n.into_owned()),
+            priority: self.priority,
+            restricted_package_name: self.restricted_package_name.map(owned),
+            unknown: self.unknown,
+        }
+    }
+
+    pub(crate) fn validate(&self, path: &str, violations: &mut Violations) {
+        if let Some(ref data) = self.data {
+            violations.check_data(&format!("{}.data", path), data);
+        }
+
+        if let Some(ref ttl) = self.ttl {
+            violations.check_ttl(&format!("{}.ttl", path), ttl);
+        }
+    }
 }
 
-#[derive(Serialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Visibility {
     Unspecified,
@@ -90,19 +172,19 @@ pub enum Visibility {
 /// This struct represents a FCM Android notification. Use the
 /// corresponding `AndroidNotificationBuilder` to get an instance. You can then use
 /// this notification instance when sending a FCM message.
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AndroidNotification<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    badge: Option<&'a str>,
+    badge: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    body: Option<&'a str>,1
+    body: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     body_loc_args: Option<Vec<Cow<'a, str>>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    body_loc_key: Option<&'a str>,
+    body_loc_key: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     default_sound: Option<bool>,
@@ -114,22 +196,22 @@ pub struct AndroidNotification<'a> {
     default_light_settings: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    channel_id: Option<&'a str>,
+    channel_id: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    click_action: Option<&'a str>,
+    click_action: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    event_time: Option<&'a str>,
+    event_time: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    color: Option<&'a str>,1
+    color: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    icon: Option<&'a str>,1
+    icon: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    image: Option<&'a str>,
+    image: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     light_settings: Option<LightSettings<'a>>,
@@ -141,28 +223,68 @@ pub struct AndroidNotification<'a> {
     notification_priority: Option<NotificationPriority>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    sound: Option<&'a str>,
+    sound: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    tag: Option<&'a str>,
+    tag: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     ticker: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    title: Option<&'a str>,1
+    title: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     title_loc_args: Option<Vec<Cow<'a, str>>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    title_loc_key: Option<&'a str>,
+    title_loc_key: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     vibrate_timings: Option<Vec<Cow<'a, str>>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     visibility: Option<Visibility>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> AndroidNotification<'a> {
+    /// Convert into a notification owning its strings.
+    pub fn into_owned(self) -> AndroidNotification<'static> {
+        AndroidNotification {
+            badge: self.badge.map(owned),
+            body: self.body.map(owned),
+            body_loc_args: self.body_loc_args.map(owned_vec),
+            body_loc_key: self.body_loc_key.map(owned),
+            default_sound: self.default_sound,
+            default_vibrate_timings: self.default_vibrate_timings,
+            default_light_settings: self.default_light_settings,
+            channel_id: self.channel_id.map(owned),
+            click_action: self.click_action.map(owned),
+            event_time: self.event_time.map(owned),
+            color: self.color.map(owned),
+            icon: self.icon.map(owned),
+            image: self.image.map(owned),
+            light_settings: self.light_settings.map(|l| l.into_owned()),
+            local_only: self.local_only,
+            notification_priority: self.notification_priority,
+            sound: self.sound.map(owned),
+            tag: self.tag.map(owned),
+            ticker: self.ticker,
+            title: self.title.map(owned),
+            title_loc_args: self.title_loc_args.map(owned_vec),
+            title_loc_key: self.title_loc_key.map(owned),
+            vibrate_timings: self.vibrate_timings.map(owned_vec),
+            visibility: self.visibility,
+            unknown: self.unknown,
+        }
+    }
 }
 
 /// A builder to get an `AndroidNotification` instance.
@@ -178,17 +300,18 @@ pub struct AndroidNotification<'a> {
 /// let notification = builder.finalize();
 /// ```
 pub struct AndroidNotificationBuilder<'a> {
-    title: Option<&'a str>,
-    body: Option<&'a str>,
-    icon: Option<&'a str>,
-    sound: Option<&'a str>,
-    badge: Option<&'a str>,
-    tag: Option<&'a str>,
-    color: Option<&'a str>,
-    click_action: Option<&'a str>,
-    body_loc_key: Option<&'a str>,
+    title: Option<Cow<'a, str>>,
+    body: Option<Cow<'a, str>>,
+    icon: Option<Cow<'a, str>>,
+    image: Option<Cow<'a, str>>,
+    sound: Option<Cow<'a, str>>,
+    badge: Option<Cow<'a, str>>,
+    tag: Option<Cow<'a, str>>,
+    color: Option<Cow<'a, str>>,
+    click_action: Option<Cow<'a, str>>,
+    body_loc_key: Option<Cow<'a, str>>,
     body_loc_args: Option<Vec<Cow<'a, str>>>,
-    title_loc_key: Option<&'a str>,
+    title_loc_key: Option<Cow<'a, str>>,
     title_loc_args: Option<Vec<Cow<'a, str>>>,
 }
 
@@ -199,6 +322,7 @@ impl<'a> AndroidNotificationBuilder<'a> {
             title: None,
             body: None,
             icon: None,
+            image: None,
             sound: None,
             badge: None,
             tag: None,
@@ -212,59 +336,65 @@ impl<'a> AndroidNotificationBuilder<'a> {
     }
 
     // Set the title of the notification
-    pub fn title(&mut self, title: &'a str) -> &mut Self {
-        self.title = Some(title);
+    pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
         self
     }
 
     /// Set the body of the notification
-    pub fn body(&mut self, body: &'a str) -> &mut Self {
-        self.body = Some(body);
+    pub fn body(&mut self, body: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body = Some(body.into());
         self
     }
 
     /// Set the notification icon.
-    pub fn icon(&mut self, icon: &'a str) -> &mut Self {
-        self.icon = Some(icon);
+    pub fn icon(&mut self, icon: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// Set the notification image.
+    pub fn image(&mut self, image: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.image = Some(image.into());
         self
     }
 
     /// Set the sound to be played
-    pub fn sound(&mut self, sound: &'a str) -> &mut Self {
-        self.sound = Some(sound);
+    pub fn sound(&mut self, sound: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.sound = Some(sound.into());
         self
     }
 
     /// Set the badge for iOS notifications
-    pub fn badge(&mut self, badge: &'a str) -> &mut Self {
-        self.badge = Some(badge);
+    pub fn badge(&mut self, badge: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.badge = Some(badge.into());
         self
     }
 
     /// Tagging a notification allows you to replace existing notifications
     /// with the same tag with this new notification
-    pub fn tag(&mut self, tag: &'a str) -> &mut Self {
-        self.tag = Some(tag);
+    pub fn tag(&mut self, tag: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.tag = Some(tag.into());
         self
     }
 
     /// The color of the icon, in #rrggbb format
-    pub fn color(&mut self, color: &'a str) -> &mut Self {
-        self.color = Some(color);
+    pub fn color(&mut self, color: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.color = Some(color.into());
         self
     }
 
     /// What happens when the user clicks on the notification. Refer to
     /// https://developers.google.com/cloud-messaging/http-server-ref#table2 for
     /// details.
-    pub fn click_action(&mut self, click_action: &'a str) -> &mut Self {
-        self.click_action = Some(click_action);
+    pub fn click_action(&mut self, click_action: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.click_action = Some(click_action.into());
         self
     }
 
     /// Set the body key string for localization
-    pub fn body_loc_key(&mut self, body_loc_key: &'a str) -> &mut Self {
-        self.body_loc_key = Some(body_loc_key);
+    pub fn body_loc_key(&mut self, body_loc_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body_loc_key = Some(body_loc_key.into());
         self
     }
 
@@ -280,8 +410,8 @@ impl<'a> AndroidNotificationBuilder<'a> {
     }
 
     /// Set the title key string for localization
-    pub fn title_loc_key(&mut self, title_loc_key: &'a str) -> &mut Self {
-        self.title_loc_key = Some(title_loc_key);
+    pub fn title_loc_key(&mut self, title_loc_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title_loc_key = Some(title_loc_key.into());
         self
     }
 
@@ -299,9 +429,37 @@ impl<'a> AndroidNotificationBuilder<'a> {
     /// Complete the build and get a `AndroidNotification` instance
     pub fn finalize(self) -> AndroidNotification<'a> {
         AndroidNotification {
-            title: self.title,
+            badge: self.badge,
             body: self.body,
+            body_loc_args: self.body_loc_args,
+            body_loc_key: self.body_loc_key,
+            default_sound: None,
+            default_vibrate_timings: None,
+            default_light_settings: None,
+            channel_id: None,
+            click_action: self.click_action,
+            event_time: None,
+            color: self.color,
+            icon: self.icon,
             image: self.image,
+            light_settings: None,
+            local_only: None,
+            notification_priority: None,
+            sound: self.sound,
+            tag: self.tag,
+            ticker: None,
+            title: self.title,
+            title_loc_args: self.title_loc_args,
+            title_loc_key: self.title_loc_key,
+            vibrate_timings: None,
+            visibility: None,
+            unknown: Map::new(),
         }
     }
 }
+
+impl<'a> Default for AndroidNotificationBuilder<'a> {
+    fn default() -> Self {
+        AndroidNotificationBuilder::new()
+    }
+}
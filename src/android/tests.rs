@@ -1,15 +1,15 @@
-use serde_json;
 use std::borrow::Cow;
-use crate::NotificationBuilder;
+use super::AndroidNotificationBuilder;
 
 #[test]
 fn should_be_able_to_render_a_full_notification_to_json() {
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
 
     builder
         .title("foo")
         .body("bar")
         .icon("gif")
+        .image("https://example.com/image.png")
         .sound("pling")
         .badge("12")
         .tag("spook")
@@ -20,7 +20,7 @@ fn should_be_able_to_render_a_full_notification_to_json() {
         .title_loc_key("PAUSE")
         .title_loc_args(&["omg", "lol"]);
 
-    let payload = serde_json::to_string(&builder.finalize()).unwrap();
+    let payload = serde_json::to_value(builder.finalize()).unwrap();
 
     let expected_payload = json!({
         "badge": "12",
@@ -30,137 +30,150 @@ fn should_be_able_to_render_a_full_notification_to_json() {
         "click_action": "spam",
         "color": "#666666",
         "icon": "gif",
+        "image": "https://example.com/image.png",
         "sound": "pling",
         "tag": "spook",
         "title": "foo",
         "title_loc_args": ["omg", "lol"],
         "title_loc_key": "PAUSE"
-    })
-    .to_string();
+    });
 
     assert_eq!(expected_payload, payload);
 }
 
 #[test]
 fn should_set_notification_title() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.title, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.title("title");
     let nm = builder.finalize();
 
-    assert_eq!(nm.title, Some("title"));
+    assert_eq!(nm.title.as_deref(), Some("title"));
 }
 
 #[test]
 fn should_set_notification_body() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.body, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.body("body");
     let nm = builder.finalize();
 
-    assert_eq!(nm.body, Some("body"));
+    assert_eq!(nm.body.as_deref(), Some("body"));
 }
 
 #[test]
 fn should_set_notification_icon() {
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.icon("newicon");
     let nm = builder.finalize();
 
-    assert_eq!(nm.icon, Some("newicon"));
+    assert_eq!(nm.icon.as_deref(), Some("newicon"));
+}
+
+#[test]
+fn should_set_notification_image() {
+    let nm = AndroidNotificationBuilder::new().finalize();
+
+    assert_eq!(nm.image, None);
+
+    let mut builder = AndroidNotificationBuilder::new();
+    builder.image("https://example.com/image.png");
+    let nm = builder.finalize();
+
+    assert_eq!(nm.image.as_deref(), Some("https://example.com/image.png"));
 }
 
 #[test]
 fn should_set_notification_sound() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.sound, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.sound("sound.wav");
     let nm = builder.finalize();
 
-    assert_eq!(nm.sound, Some("sound.wav"));
+    assert_eq!(nm.sound.as_deref(), Some("sound.wav"));
 }
 
 #[test]
 fn should_set_notification_badge() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.badge, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.badge("1");
     let nm = builder.finalize();
 
-    assert_eq!(nm.badge, Some("1"));
+    assert_eq!(nm.badge.as_deref(), Some("1"));
 }
 
 #[test]
 fn should_set_notification_tag() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.tag, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.tag("tag");
     let nm = builder.finalize();
 
-    assert_eq!(nm.tag, Some("tag"));
+    assert_eq!(nm.tag.as_deref(), Some("tag"));
 }
 
 #[test]
 fn should_set_notification_color() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.color, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.color("color");
     let nm = builder.finalize();
 
-    assert_eq!(nm.color, Some("color"));
+    assert_eq!(nm.color.as_deref(), Some("color"));
 }
 
 #[test]
 fn should_set_notification_click_action() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.click_action, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.click_action("action");
     let nm = builder.finalize();
 
-    assert_eq!(nm.click_action, Some("action"));
+    assert_eq!(nm.click_action.as_deref(), Some("action"));
 }
 
 #[test]
 fn should_set_notification_body_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.body_loc_key, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.body_loc_key("key");
     let nm = builder.finalize();
 
-    assert_eq!(nm.body_loc_key, Some("key"));
+    assert_eq!(nm.body_loc_key.as_deref(), Some("key"));
 }
 
 #[test]
 fn should_set_notification_body_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.body_loc_args, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.body_loc_args(&["args"]);
     let nm = builder.finalize();
 
@@ -169,24 +182,24 @@ fn should_set_notification_body_loc_args() {
 
 #[test]
 fn should_set_notification_title_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.title_loc_key, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.title_loc_key("key");
     let nm = builder.finalize();
 
-    assert_eq!(nm.title_loc_key, Some("key"));
+    assert_eq!(nm.title_loc_key.as_deref(), Some("key"));
 }
 
 #[test]
 fn should_set_notification_title_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
+    let nm = AndroidNotificationBuilder::new().finalize();
 
     assert_eq!(nm.title_loc_args, None);
 
-    let mut builder = NotificationBuilder::new();
+    let mut builder = AndroidNotificationBuilder::new();
     builder.title_loc_args(&["args"]);
     let nm = builder.finalize();
 
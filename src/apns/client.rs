@@ -0,0 +1,326 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use futures::stream::StreamExt;
+use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use hyper::{
+    client::{Client as HttpClient, HttpConnector},
+    Body, Request, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+use p256::ecdsa::SigningKey;
+use p256::pkcs8::DecodePrivateKey;
+
+use super::ApnsConfig;
+use crate::jwt;
+use crate::RetryAfter;
+
+/// The production APNs host.
+pub const APNS_PRODUCTION_HOST: &str = "https://api.push.apple.com";
+
+/// The development APNs host, for apps signed with a development
+/// provisioning profile.
+pub const APNS_SANDBOX_HOST: &str = "https://api.sandbox.push.apple.com";
+
+/// APNs rejects provider tokens older than an hour and refreshing them more
+/// often than every 20 minutes, so a new one is signed after 50 minutes.
+const PROVIDER_TOKEN_LIFETIME: i64 = 50 * 60;
+
+/// A description of why APNs rejected a notification. Referred from [Apple
+/// documentation](https://developer.apple.com/documentation/usernotifications/setting_up_a_remote_notification_server/handling_notification_responses_from_apns).
+#[derive(Deserialize, Debug, PartialEq, Copy, Clone)]
+pub enum ApnsErrorReason {
+    BadCollapseId,
+    BadDeviceToken,
+    BadExpirationDate,
+    BadMessageId,
+    BadPriority,
+    BadTopic,
+    DeviceTokenNotForTopic,
+    DuplicateHeaders,
+    IdleTimeout,
+    InvalidPushType,
+    MissingDeviceToken,
+    MissingTopic,
+    PayloadEmpty,
+    TopicDisallowed,
+    BadCertificate,
+    BadCertificateEnvironment,
+    ExpiredProviderToken,
+    Forbidden,
+    InvalidProviderToken,
+    MissingProviderToken,
+    BadPath,
+    MethodNotAllowed,
+    ExpiredToken,
+    Unregistered,
+    PayloadTooLarge,
+    TooManyProviderTokenUpdates,
+    TooManyRequests,
+    InternalServerError,
+    ServiceUnavailable,
+    Shutdown,
+
+    /// A reason not known to this crate.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApnsErrorResponse {
+    reason: ApnsErrorReason,
+    timestamp: Option<i64>,
+}
+
+/// A successfully delivered notification.
+#[derive(Debug, PartialEq)]
+pub struct ApnsResponse {
+    /// The `apns-id` APNs assigned to the notification, or the one sent in
+    /// the request headers.
+    pub apns_id: Option<String>,
+}
+
+/// A builder to get an `ApnsClient` instance.
+///
+/// # Examples
+///
+/// ```no_run
+/// use fcm::{ApnsClientBuilder, APNS_SANDBOX_HOST};
+///
+/// # fn main() -> Result<(), fcm::ApnsError> {
+/// let mut builder = ApnsClientBuilder::new("<contents of AuthKey.p8>", "<key id>", "<team id>")?;
+/// builder.host(APNS_SANDBOX_HOST);
+/// let client = builder.finalize();
+/// # Ok(())
+/// # }
+/// ```
+pub struct ApnsClientBuilder {
+    signing_key: SigningKey,
+    key_id: String,
+    team_id: String,
+    host: String,
+}
+
+impl ApnsClientBuilder {
+    /// Get a new `ApnsClientBuilder` authenticating with the PEM encoded
+    /// contents of a `.p8` signing key and its key and team ids.
+    pub fn new(p8_key: &str, key_id: &str, team_id: &str) -> Result<ApnsClientBuilder, ApnsError> {
+        let signing_key = SigningKey::from_pkcs8_pem(p8_key)
+            .map_err(|_| ApnsError::InvalidKey("not a PKCS#8 P-256 private key".to_string()))?;
+
+        Ok(ApnsClientBuilder {
+            signing_key,
+            key_id: key_id.to_string(),
+            team_id: team_id.to_string(),
+            host: APNS_PRODUCTION_HOST.to_string(),
+        })
+    }
+
+    /// The scheme and host to send notifications to, defaults to
+    /// `APNS_PRODUCTION_HOST`.
+    pub fn host(&mut self, host: &str) -> &mut Self {
+        self.host = host.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Complete the build and get an `ApnsClient` instance
+    pub fn finalize(self) -> ApnsClient {
+        let mut http_client = HttpClient::builder();
+        http_client.pool_max_idle_per_host(usize::MAX);
+        http_client.http2_only(true);
+
+        let mut tls = native_tls::TlsConnector::builder();
+        tls.request_alpns(&["h2"]);
+
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+
+        let https = HttpsConnector::from((http, tls.build().unwrap().into()));
+
+        ApnsClient {
+            signing_key: self.signing_key,
+            key_id: self.key_id,
+            team_id: self.team_id,
+            host: self.host,
+            provider_token: Mutex::new(None),
+            http_client: http_client.build(https),
+        }
+    }
+}
+
+/// An async client sending notifications directly to the Apple Push
+/// Notification service over HTTP/2, bypassing FCM. Authenticates with a
+/// token signed by a `.p8` key, which is renewed automatically.
+pub struct ApnsClient {
+    signing_key: SigningKey,
+    key_id: String,
+    team_id: String,
+    host: String,
+    provider_token: Mutex<Option<(i64, String)>>,
+    http_client: HttpClient<HttpsConnector<HttpConnector>>,
+}
+
+impl ApnsClient {
+    /// Try sending the payload and headers of an `ApnsConfig` to a device.
+    /// A device token that isn't hex fails with `BadDeviceToken` without
+    /// contacting APNs.
+    pub async fn send(
+        &self,
+        device_token: &str,
+        config: &ApnsConfig<'_>,
+    ) -> Result<ApnsResponse, ApnsError> {
+        if !is_valid_device_token(device_token) {
+            return Err(ApnsError::BadDeviceToken);
+        }
+
+        let payload = match config.payload {
+            Some(ref payload) => serde_json::to_vec(payload).unwrap(),
+            None => b"{\"aps\":{}}".to_vec(),
+        };
+
+        let mut builder = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                CONTENT_LENGTH,
+                format!("{}", payload.len() as u64).as_bytes(),
+            )
+            .header(AUTHORIZATION, format!("bearer {}", self.provider_token()))
+            .uri(format!("{}/3/device/{}", self.host, device_token));
+
+        if let Some(headers) = config.headers.as_ref().and_then(|h| h.as_object()) {
+            for (name, value) in headers {
+                if let Some(value) = value.as_str() {
+                    builder = builder.header(name.as_str(), value);
+                }
+            }
+        }
+
+        let request = builder.body(Body::from(payload)).unwrap();
+        let response = self.http_client.request(request).await?;
+        let response_status = response.status();
+
+        let apns_id = response
+            .headers()
+            .get("apns-id")
+            .and_then(|id| id.to_str().ok())
+            .map(|id| id.to_string());
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|ra| ra.to_str().ok())
+            .and_then(RetryAfter::from_str);
+
+        let mut body: Vec<u8> = Vec::new();
+        let mut chunks = response.into_body();
+
+        while let Some(chunk) = chunks.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+
+        if response_status == StatusCode::OK {
+            return Ok(ApnsResponse { apns_id });
+        }
+
+        let error: Option<ApnsErrorResponse> = serde_json::from_slice(&body).ok();
+
+        match error.map(|e| (e.reason, e.timestamp)) {
+            Some((ApnsErrorReason::BadDeviceToken, _)) => Err(ApnsError::BadDeviceToken),
+            Some((ApnsErrorReason::Unregistered, timestamp)) => {
+                Err(ApnsError::Unregistered(timestamp))
+            }
+            Some((ApnsErrorReason::TooManyRequests, _)) => {
+                Err(ApnsError::TooManyRequests(retry_after))
+            }
+            Some((ApnsErrorReason::ExpiredProviderToken, _)) => {
+                *self.provider_token.lock().unwrap() = None;
+                Err(ApnsError::Rejected(ApnsErrorReason::ExpiredProviderToken))
+            }
+            Some((reason, _)) if !response_status.is_server_error() => {
+                Err(ApnsError::Rejected(reason))
+            }
+            _ if response_status.is_server_error() => Err(ApnsError::ServerError(retry_after)),
+            _ => Err(ApnsError::Rejected(ApnsErrorReason::Unknown)),
+        }
+    }
+
+    fn provider_token(&self) -> String {
+        let now = Utc::now().timestamp();
+        let mut provider_token = self.provider_token.lock().unwrap();
+
+        match *provider_token {
+            Some((issued_at, ref token)) if now - issued_at < PROVIDER_TOKEN_LIFETIME => {
+                token.clone()
+            }
+            _ => {
+                let header = json!({"alg": "ES256", "kid": self.key_id});
+                let claims = json!({"iss": self.team_id, "iat": now});
+                let token = jwt::sign_es256(&self.signing_key, &header, &claims);
+
+                *provider_token = Some((now, token.clone()));
+                token
+            }
+        }
+    }
+}
+
+/// APNs device tokens are hex encoded, 32 bytes today but documented as
+/// variable length, so any even number of hex digits up to 100 bytes is
+/// accepted.
+fn is_valid_device_token(device_token: &str) -> bool {
+    (64..=200).contains(&device_token.len())
+        && device_token
+            .as_bytes()
+            .chunks(2)
+            .all(|byte| byte.len() == 2 && byte.iter().all(u8::is_ascii_hexdigit))
+}
+
+/// Errors sending a notification directly to APNs.
+#[derive(PartialEq, Debug)]
+pub enum ApnsError {
+    /// The `.p8` signing key couldn't be read.
+    InvalidKey(String),
+
+    /// The device token is malformed, or was issued for the other
+    /// environment (sandbox or production) than the one used.
+    BadDeviceToken,
+
+    /// The device token is no longer active for the topic, since the given
+    /// time in milliseconds since the Unix epoch. Stop sending to it.
+    Unregistered(Option<i64>),
+
+    /// Too many notifications were sent to the same device. Honor the
+    /// [RetryAfter](enum.RetryAfter.html) value if included.
+    TooManyRequests(Option<RetryAfter>),
+
+    /// APNs rejected the notification for the given reason.
+    Rejected(ApnsErrorReason),
+
+    /// APNs couldn't process the request. Retry later, honoring the
+    /// [RetryAfter](enum.RetryAfter.html) value if included.
+    ServerError(Option<RetryAfter>),
+}
+
+impl Error for ApnsError {}
+
+impl fmt::Display for ApnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApnsError::InvalidKey(ref s) => write!(f, "invalid signing key: {}", s),
+            ApnsError::BadDeviceToken => write!(f, "bad device token"),
+            ApnsError::Unregistered(_) => write!(f, "the device token is no longer active"),
+            ApnsError::TooManyRequests(_) => write!(f, "too many requests to the same device"),
+            ApnsError::Rejected(reason) => write!(f, "rejected by APNs: {:?}", reason),
+            ApnsError::ServerError(_) => write!(f, "APNs couldn't process the request"),
+        }
+    }
+}
+
+impl From<hyper::Error> for ApnsError {
+    fn from(_: hyper::Error) -> Self {
+        Self::ServerError(None)
+    }
+}
@@ -1,31 +1,413 @@
 use std::borrow::Cow;
-use serde_json::Value;
+use serde_json::{Map, Value};
+
+use crate::owned::{owned, owned_vec};
+use crate::de::{flattened_object, unknown_fields};
+
+mod client;
+
+pub use self::client::*;
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Serialize, Debug, PartialEq)]
-pub struct Payload<'a> {
+/// The alert shown to the user, part of the `aps` dictionary.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApsAlert<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitle: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_loc_key: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_loc_args: Option<Vec<Cow<'a, str>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loc_key: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loc_args: Option<Vec<Cow<'a, str>>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> ApsAlert<'a> {
+    /// Get a new, empty `ApsAlert`.
+    pub fn new() -> ApsAlert<'a> {
+        ApsAlert {
+            title: None,
+            subtitle: None,
+            body: None,
+            title_loc_key: None,
+            title_loc_args: None,
+            loc_key: None,
+            loc_args: None,
+            unknown: Map::new(),
+        }
+    }
+
+    // Set the title of the alert
+    pub fn title(mut self, title: impl Into<Cow<'a, str>>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Additional information explaining the purpose of the notification.
+    pub fn subtitle(mut self, subtitle: impl Into<Cow<'a, str>>) -> Self {
+        self.subtitle = Some(subtitle.into());
+        self
+    }
+
+    /// Set the body of the alert
+    pub fn body(mut self, body: impl Into<Cow<'a, str>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Set the title key string for localization
+    pub fn title_loc_key(mut self, title_loc_key: impl Into<Cow<'a, str>>) -> Self {
+        self.title_loc_key = Some(title_loc_key.into());
+        self
+    }
+
+    /// String value to replace format specifiers in the title string.
+    pub fn title_loc_args<S>(mut self, title_loc_args: &'a [S]) -> Self
+    where
+        S: Into<Cow<'a, str>> + AsRef<str>,
+    {
+        self.title_loc_args = Some(title_loc_args.iter().map(|a| a.as_ref().into()).collect());
+        self
+    }
+
+    /// Set the body key string for localization
+    pub fn loc_key(mut self, loc_key: impl Into<Cow<'a, str>>) -> Self {
+        self.loc_key = Some(loc_key.into());
+        self
+    }
+
+    /// String value to replace format specifiers in the body string.
+    pub fn loc_args<S>(mut self, loc_args: &'a [S]) -> Self
+    where
+        S: Into<Cow<'a, str>> + AsRef<str>,
+    {
+        self.loc_args = Some(loc_args.iter().map(|a| a.as_ref().into()).collect());
+        self
+    }
+
+    /// Convert into an alert owning its strings.
+    pub fn into_owned(self) -> ApsAlert<'static> {
+        ApsAlert {
+            title: self.title.map(owned),
+            subtitle: self.subtitle.map(owned),
+            body: self.body.map(owned),
+            title_loc_key: self.title_loc_key.map(owned),
+            title_loc_args: self.title_loc_args.map(owned_vec),
+            loc_key: self.loc_key.map(owned),
+            loc_args: self.loc_args.map(owned_vec),
+            unknown: self.unknown,
+        }
+    }
+}
+
+impl<'a> Default for ApsAlert<'a> {
+    fn default() -> Self {
+        ApsAlert::new()
+    }
+}
+
+/// The `aps` dictionary of an APNs payload. Use the corresponding
+/// `ApsBuilder` to get an instance. Referred from [Apple
+/// documentation](https://developer.apple.com/documentation/usernotifications/setting_up_a_remote_notification_server/generating_a_remote_notification).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Aps<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alert: Option<ApsAlert<'a>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    badge: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sound: Option<Cow<'a, str>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
-    title: Option<&'a str>,
+    thread_id: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    body: Option<&'a str>,
+    category: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_available: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mutable_content: Option<u8>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interruption_level: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_state: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dismissal_date: Option<i64>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> Aps<'a> {
+    /// Convert into an `aps` dictionary owning its strings.
+    pub fn into_owned(self) -> Aps<'static> {
+        Aps {
+            alert: self.alert.map(|a| a.into_owned()),
+            badge: self.badge,
+            sound: self.sound.map(owned),
+            thread_id: self.thread_id.map(owned),
+            category: self.category.map(owned),
+            content_available: self.content_available,
+            mutable_content: self.mutable_content,
+            interruption_level: self.interruption_level.map(owned),
+            timestamp: self.timestamp,
+            event: self.event.map(owned),
+            content_state: self.content_state,
+            dismissal_date: self.dismissal_date,
+            unknown: self.unknown,
+        }
+    }
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+/// A builder to get an `Aps` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::{ApsAlert, ApsBuilder};
+///
+/// let mut builder = ApsBuilder::new();
+/// builder.alert(ApsAlert::new().title("Australia vs New Zealand").body("3 runs to win in 1 ball"));
+/// builder.badge(1);
+/// let aps = builder.finalize();
+/// ```
+pub struct ApsBuilder<'a> {
+    alert: Option<ApsAlert<'a>>,
+    badge: Option<u32>,
+    sound: Option<Cow<'a, str>>,
+    thread_id: Option<Cow<'a, str>>,
+    category: Option<Cow<'a, str>>,
+    content_available: Option<u8>,
+    mutable_content: Option<u8>,
+    interruption_level: Option<Cow<'a, str>>,
+    timestamp: Option<i64>,
+    event: Option<Cow<'a, str>>,
+    content_state: Option<Value>,
+    dismissal_date: Option<i64>,
+}
+
+impl<'a> ApsBuilder<'a> {
+    /// Get a new `ApsBuilder` instance.
+    pub fn new() -> ApsBuilder<'a> {
+        ApsBuilder {
+            alert: None,
+            badge: None,
+            sound: None,
+            thread_id: None,
+            category: None,
+            content_available: None,
+            mutable_content: None,
+            interruption_level: None,
+            timestamp: None,
+            event: None,
+            content_state: None,
+            dismissal_date: None,
+        }
+    }
+
+    /// The alert shown to the user.
+    pub fn alert(&mut self, alert: ApsAlert<'a>) -> &mut Self {
+        self.alert = Some(alert);
+        self
+    }
+
+    /// The number to display in the badge of the app icon, `0` removes it.
+    pub fn badge(&mut self, badge: u32) -> &mut Self {
+        self.badge = Some(badge);
+        self
+    }
+
+    /// Set the sound to be played
+    pub fn sound(&mut self, sound: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.sound = Some(sound.into());
+        self
+    }
+
+    /// An identifier to group related notifications.
+    pub fn thread_id(&mut self, thread_id: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.thread_id = Some(thread_id.into());
+        self
+    }
+
+    /// The notification's type, matching a category registered by the app.
+    pub fn category(&mut self, category: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    /// Wake the app in the background to fetch new content.
+    pub fn content_available(&mut self, content_available: bool) -> &mut Self {
+        self.content_available = if content_available { Some(1) } else { None };
+        self
+    }
+
+    /// Let the notification service app extension modify the content.
+    pub fn mutable_content(&mut self, mutable_content: bool) -> &mut Self {
+        self.mutable_content = if mutable_content { Some(1) } else { None };
+        self
+    }
+
+    /// One of `passive`, `active`, `time-sensitive` or `critical`.
+    pub fn interruption_level(&mut self, interruption_level: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.interruption_level = Some(interruption_level.into());
+        self
+    }
+
+    /// The time of a Live Activity update, in seconds since the Unix epoch.
+    pub fn timestamp(&mut self, timestamp: i64) -> &mut Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// The Live Activity event, `update` or `end`.
+    pub fn event(&mut self, event: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// The updated content state of a Live Activity.
+    pub fn content_state(&mut self, content_state: Value) -> &mut Self {
+        self.content_state = Some(content_state);
+        self
+    }
+
+    /// When an ended Live Activity is removed, in seconds since the Unix
+    /// epoch.
+    pub fn dismissal_date(&mut self, dismissal_date: i64) -> &mut Self {
+        self.dismissal_date = Some(dismissal_date);
+        self
+    }
+
+    /// Complete the build and get an `Aps` instance
+    pub fn finalize(self) -> Aps<'a> {
+        Aps {
+            alert: self.alert,
+            badge: self.badge,
+            sound: self.sound,
+            thread_id: self.thread_id,
+            category: self.category,
+            content_available: self.content_available,
+            mutable_content: self.mutable_content,
+            interruption_level: self.interruption_level,
+            timestamp: self.timestamp,
+            event: self.event,
+            content_state: self.content_state,
+            dismissal_date: self.dismissal_date,
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl<'a> Default for ApsBuilder<'a> {
+    fn default() -> Self {
+        ApsBuilder::new()
+    }
+}
+
+/// The APNs payload: the `aps` dictionary and any custom keys for the app.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Payload<'a> {
+    aps: Aps<'a>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "flattened_object",
+        skip_serializing_if = "Option::is_none"
+    )]
+    data: Option<Value>,
+}
+
+impl<'a> Payload<'a> {
+    /// Get a new `Payload` with the given `aps` dictionary.
+    pub fn new(aps: Aps<'a>) -> Payload<'a> {
+        Payload { aps, data: None }
+    }
+
+    /// Custom keys delivered to the app next to `aps`. Must be a JSON object.
+    pub fn data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Convert into a payload owning its strings.
+    pub fn into_owned(self) -> Payload<'static> {
+        Payload {
+            aps: self.aps.into_owned(),
+            data: self.data,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct ApnsFcmOptions<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    analytics_label: Option<&'a str>,
+    analytics_label: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    image: Option<&'a str>,
+    image: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
 }
 
-/// This struct represents a FCM Android notification. Use the
-/// corresponding `AndroidNotificationBuilder` to get an instance. You can then use
-/// this notification instance when sending a FCM message.
-#[derive(Serialize, Debug, PartialEq)]
+impl<'a> ApnsFcmOptions<'a> {
+    /// Convert into options owning their strings.
+    pub fn into_owned(self) -> ApnsFcmOptions<'static> {
+        ApnsFcmOptions {
+            analytics_label: self.analytics_label.map(owned),
+            image: self.image.map(owned),
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// Apple Push Notification Service specific options. Use the corresponding
+/// `ApnsConfigBuilder` to get an instance.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct ApnsConfig<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     headers: Option<Value>,
@@ -35,6 +417,26 @@ pub struct ApnsConfig<'a> {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     fcm_options: Option<ApnsFcmOptions<'a>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> ApnsConfig<'a> {
+    /// Convert into a config owning its strings, e.g. to send it from
+    /// another task.
+    pub fn into_owned(self) -> ApnsConfig<'static> {
+        ApnsConfig {
+            headers: self.headers,
+            payload: self.payload.map(|p| p.into_owned()),
+            fcm_options: self.fcm_options.map(|o| o.into_owned()),
+            unknown: self.unknown,
+        }
+    }
 }
 
 pub struct ApnsConfigBuilder<'a> {
@@ -53,6 +455,8 @@ impl<'a> ApnsConfigBuilder<'a> {
         }
     }
 
+    /// APNs request headers such as `apns-priority`, `apns-push-type` or
+    /// `apns-expiration`, as a JSON object of strings.
     pub fn headers(&mut self, headers: Value) -> &mut Self {
         self.headers = Some(headers);
         self
@@ -74,6 +478,13 @@ impl<'a> ApnsConfigBuilder<'a> {
             headers: self.headers,
             payload: self.payload,
             fcm_options: self.fcm_options,
+            unknown: Map::new(),
         }
     }
 }
+
+impl<'a> Default for ApnsConfigBuilder<'a> {
+    fn default() -> Self {
+        ApnsConfigBuilder::new()
+    }
+}
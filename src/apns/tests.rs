@@ -1,194 +1,237 @@
-use serde_json;
-use std::borrow::Cow;
-use crate::NotificationBuilder;
+use crate::{ApnsClientBuilder, ApnsConfigBuilder, ApnsError, ApnsErrorReason, ApsAlert, ApsBuilder, Payload};
+use crate::testing;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::{Body, Response, StatusCode, Version};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use p256::pkcs8::{EncodePrivateKey, LineEnding};
+use p256::SecretKey;
+use rand::rngs::OsRng;
+use std::sync::{Arc, Mutex};
 
 #[test]
-fn should_be_able_to_render_a_full_notification_to_json() {
-    let mut builder = NotificationBuilder::new();
-
-    builder
-        .title("foo")
-        .body("bar")
-        .icon("gif")
-        .sound("pling")
-        .badge("12")
-        .tag("spook")
-        .color("#666666")
-        .click_action("spam")
-        .body_loc_key("PLAY")
-        .body_loc_args(&["foo", "bar"])
-        .title_loc_key("PAUSE")
-        .title_loc_args(&["omg", "lol"]);
-
-    let payload = serde_json::to_string(&builder.finalize()).unwrap();
+fn should_be_able_to_render_a_full_apns_payload_to_json() {
+    let mut aps = ApsBuilder::new();
+
+    aps.alert(
+        ApsAlert::new()
+            .title("foo")
+            .subtitle("baz")
+            .body("bar")
+            .title_loc_key("PAUSE")
+            .title_loc_args(&["omg"])
+            .loc_key("PLAY")
+            .loc_args(&["lol"]),
+    )
+    .badge(3)
+    .sound("pling")
+    .thread_id("scores")
+    .category("GAME")
+    .content_available(true)
+    .mutable_content(true)
+    .interruption_level("time-sensitive");
+
+    let payload = Payload::new(aps.finalize()).data(json!({"game_id": 42}));
+    let payload = serde_json::to_value(&payload).unwrap();
 
     let expected_payload = json!({
-        "badge": "12",
-        "body": "bar",
-        "body_loc_args": ["foo", "bar"],
-        "body_loc_key": "PLAY",
-        "click_action": "spam",
-        "color": "#666666",
-        "icon": "gif",
-        "sound": "pling",
-        "tag": "spook",
-        "title": "foo",
-        "title_loc_args": ["omg", "lol"],
-        "title_loc_key": "PAUSE"
-    })
-    .to_string();
+        "aps": {
+            "alert": {
+                "title": "foo",
+                "subtitle": "baz",
+                "body": "bar",
+                "title-loc-key": "PAUSE",
+                "title-loc-args": ["omg"],
+                "loc-key": "PLAY",
+                "loc-args": ["lol"]
+            },
+            "badge": 3,
+            "sound": "pling",
+            "thread-id": "scores",
+            "category": "GAME",
+            "content-available": 1,
+            "mutable-content": 1,
+            "interruption-level": "time-sensitive"
+        },
+        "game_id": 42
+    });
 
     assert_eq!(expected_payload, payload);
 }
 
 #[test]
-fn should_set_notification_title() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_render_live_activity_updates() {
+    let mut aps = ApsBuilder::new();
 
-    assert_eq!(nm.title, None);
+    aps.timestamp(1_700_000_000)
+        .event("update")
+        .content_state(json!({"score": "2-1"}));
 
-    let mut builder = NotificationBuilder::new();
-    builder.title("title");
-    let nm = builder.finalize();
+    let payload = serde_json::to_value(Payload::new(aps.finalize())).unwrap();
 
-    assert_eq!(nm.title, Some("title"));
-}
-
-#[test]
-fn should_set_notification_body() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.body, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.body("body");
-    let nm = builder.finalize();
+    let expected_payload = json!({
+        "aps": {
+            "timestamp": 1_700_000_000,
+            "event": "update",
+            "content-state": {"score": "2-1"}
+        }
+    });
 
-    assert_eq!(nm.body, Some("body"));
+    assert_eq!(expected_payload, payload);
 }
 
-#[test]
-fn should_set_notification_icon() {
-    let mut builder = NotificationBuilder::new();
-    builder.icon("newicon");
-    let nm = builder.finalize();
+fn p8_key() -> (String, VerifyingKey) {
+    let secret = SecretKey::random(&mut OsRng);
+    let pem = secret.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
 
-    assert_eq!(nm.icon, Some("newicon"));
+    (pem, VerifyingKey::from(secret.public_key()))
 }
 
-#[test]
-fn should_set_notification_sound() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.sound, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.sound("sound.wav");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.sound, Some("sound.wav"));
+fn verify_provider_token(authorization: &str, verifying_key: &VerifyingKey) -> String {
+    let token = authorization.trim_start_matches("bearer ");
+    let (message, signature) = token.split_at(token.rfind('.').unwrap());
+    let signature =
+        Signature::from_slice(&URL_SAFE_NO_PAD.decode(&signature[1..]).unwrap()).unwrap();
+    verifying_key
+        .verify(message.as_bytes(), &signature)
+        .unwrap();
+
+    let mut parts = message.split('.');
+    let header: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts.next().unwrap()).unwrap()).unwrap();
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts.next().unwrap()).unwrap()).unwrap();
+
+    assert_eq!("KEY123", header["kid"]);
+    assert_eq!("TEAM123", claims["iss"]);
+
+    token.to_string()
 }
 
-#[test]
-fn should_set_notification_badge() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.badge, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.badge("1");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.badge, Some("1"));
+/// A well-formed device token of 32 bytes, repeating the hex digit.
+fn device_token(digit: char) -> String {
+    digit.to_string().repeat(64)
 }
 
-#[test]
-fn should_set_notification_tag() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.tag, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.tag("tag");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.tag, Some("tag"));
+#[tokio::test]
+async fn should_send_a_notification_over_http2() {
+    let (pem, verifying_key) = p8_key();
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+
+    let addr = {
+        let tokens = tokens.clone();
+
+        testing::serve_h2(move |parts, body| {
+            assert_eq!(Version::HTTP_2, parts.version);
+            assert_eq!(format!("/3/device/{}", device_token('a')), parts.uri.path());
+            assert_eq!("voip", parts.headers["apns-push-type"]);
+
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json!({"aps": {"badge": 1}}), body);
+
+            let authorization = parts.headers["authorization"].to_str().unwrap();
+            tokens
+                .lock()
+                .unwrap()
+                .push(verify_provider_token(authorization, &verifying_key));
+
+            Response::builder()
+                .header("apns-id", "EC1BF194-B3B2-424A-89A9-5A918A6E6B5A")
+                .body(Body::empty())
+                .unwrap()
+        })
+    };
+
+    let mut builder = ApnsClientBuilder::new(&pem, "KEY123", "TEAM123").unwrap();
+    builder.host(&format!("http://{}", addr));
+    let client = builder.finalize();
+
+    let mut aps = ApsBuilder::new();
+    aps.badge(1);
+
+    let mut config = ApnsConfigBuilder::new();
+    config
+        .headers(json!({"apns-push-type": "voip"}))
+        .payload(Payload::new(aps.finalize()));
+    let config = config.finalize();
+
+    let response = client.send(&device_token('a'), &config).await.unwrap();
+    assert_eq!(
+        Some("EC1BF194-B3B2-424A-89A9-5A918A6E6B5A".to_string()),
+        response.apns_id
+    );
+
+    client.send(&device_token('a'), &config).await.unwrap();
+
+    let tokens = tokens.lock().unwrap();
+    assert_eq!(2, tokens.len());
+    assert_eq!(tokens[0], tokens[1]);
 }
 
-#[test]
-fn should_set_notification_color() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.color, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.color("color");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.color, Some("color"));
+#[tokio::test]
+async fn should_map_apns_reasons_to_errors() {
+    let (pem, _) = p8_key();
+
+    let addr = testing::serve_h2(|parts, _| {
+        let (status, body) = match &parts.uri.path()["/3/device/".len()..][..1] {
+            "1" => (400, r#"{"reason":"BadDeviceToken"}"#),
+            "2" => (
+                410,
+                r#"{"reason":"Unregistered","timestamp":1700000000000}"#,
+            ),
+            "3" => (429, r#"{"reason":"TooManyRequests"}"#),
+            "4" => (400, r#"{"reason":"DeviceTokenNotForTopic"}"#),
+            _ => (503, r#"{"reason":"ServiceUnavailable"}"#),
+        };
+
+        Response::builder()
+            .status(StatusCode::from_u16(status).unwrap())
+            .body(Body::from(body))
+            .unwrap()
+    });
+
+    let mut builder = ApnsClientBuilder::new(&pem, "KEY123", "TEAM123").unwrap();
+    builder.host(&format!("http://{}", addr));
+    let client = builder.finalize();
+    let config = ApnsConfigBuilder::new().finalize();
+
+    assert_eq!(
+        Err(ApnsError::BadDeviceToken),
+        client.send(&device_token('1'), &config).await
+    );
+    assert_eq!(
+        Err(ApnsError::Unregistered(Some(1_700_000_000_000))),
+        client.send(&device_token('2'), &config).await
+    );
+    assert_eq!(
+        Err(ApnsError::TooManyRequests(None)),
+        client.send(&device_token('3'), &config).await
+    );
+    assert_eq!(
+        Err(ApnsError::Rejected(ApnsErrorReason::DeviceTokenNotForTopic)),
+        client.send(&device_token('4'), &config).await
+    );
+    assert_eq!(
+        Err(ApnsError::ServerError(None)),
+        client.send(&device_token('5'), &config).await
+    );
 }
 
-#[test]
-fn should_set_notification_click_action() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.click_action, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.click_action("action");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.click_action, Some("action"));
-}
-
-#[test]
-fn should_set_notification_body_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.body_loc_key, None);
+#[tokio::test]
+async fn should_reject_malformed_device_tokens_without_sending() {
+    let (pem, _) = p8_key();
 
-    let mut builder = NotificationBuilder::new();
-    builder.body_loc_key("key");
-    let nm = builder.finalize();
+    let mut builder = ApnsClientBuilder::new(&pem, "KEY123", "TEAM123").unwrap();
+    builder.host("http://127.0.0.1:1");
+    let client = builder.finalize();
+    let config = ApnsConfigBuilder::new().finalize();
 
-    assert_eq!(nm.body_loc_key, Some("key"));
+    for token in &["", "abcdef", &device_token('g'), &format!("{}/..", device_token('a'))] {
+        assert_eq!(Err(ApnsError::BadDeviceToken), client.send(token, &config).await);
+    }
 }
 
 #[test]
-fn should_set_notification_body_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.body_loc_args, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.body_loc_args(&["args"]);
-    let nm = builder.finalize();
-
-    assert_eq!(nm.body_loc_args, Some(vec![Cow::from("args")]));
-}
-
-#[test]
-fn should_set_notification_title_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.title_loc_key, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.title_loc_key("key");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.title_loc_key, Some("key"));
-}
-
-#[test]
-fn should_set_notification_title_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.title_loc_args, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.title_loc_args(&["args"]);
-    let nm = builder.finalize();
-
-    assert_eq!(nm.title_loc_args, Some(vec![Cow::from("args")]));
+fn should_reject_invalid_signing_keys() {
+    assert!(ApnsClientBuilder::new("not a key", "KEY123", "TEAM123").is_err());
 }
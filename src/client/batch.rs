@@ -0,0 +1,353 @@
+use futures::future;
+use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, StatusCode};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+#[cfg(feature = "metrics")]
+use crate::client::instrument;
+#[cfg(feature = "tracing")]
+use crate::client::trace;
+use crate::client::response::{FcmError, FcmResponse, RetryAfter};
+use crate::client::{error_code, read_response, Client, SendRequest, Sent};
+use crate::message::Message;
+
+/// The batch endpoint accepts at most this many sub-requests per call.
+pub const BATCH_SIZE: usize = 500;
+
+/// A sub-response of a batch call: its status, `Retry-After` header and
+/// body.
+type Part = (StatusCode, Option<RetryAfter>, Vec<u8>);
+
+impl Client {
+    /// Send the messages in `multipart/mixed` batch calls of up to
+    /// `BATCH_SIZE` messages, returning a result for each message in the
+    /// same order. Each message is validated, rate limited and passed
+    /// through the circuit breaker as with `send`.
+    ///
+    /// The batch endpoint is deprecated. Where it's gone, answering with
+    /// `404 Not Found`, `410 Gone` or `501 Not Implemented`, the messages of
+    /// the batch are sent concurrently one by one instead. Messages without
+    /// a readable sub-response in an accepted batch fail with
+    /// `FcmError::InvalidMessage` rather than being sent again, as FCM may
+    /// have delivered them.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::{ClientBuilder, MessageBuilder};
+    ///
+    /// # async fn run() -> Result<(), fcm::FcmError> {
+    /// let client = ClientBuilder::new("<project id>".to_string()).finalize();
+    ///
+    /// let messages = vec![
+    ///     MessageBuilder::new("<access token>", "<registration id 1>").finalize(),
+    ///     MessageBuilder::new("<access token>", "<registration id 2>").finalize(),
+    /// ];
+    ///
+    /// for result in client.send_all(messages).await {
+    ///     if let Err(e) = result {
+    ///         println!("not sent: {}", e);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_all(&self, messages: Vec<Message<'_>>) -> Vec<Result<FcmResponse, FcmError>> {
+        #[cfg(feature = "tracing")]
+        let span = trace::send_all_span(&self.app_id, messages.len());
+
+        let sending = self.deliver_all(&messages);
+
+        #[cfg(feature = "tracing")]
+        let sending = tracing::Instrument::instrument(sending, span.clone());
+
+        let results = sending.await;
+
+        #[cfg(feature = "tracing")]
+        for (message, (result, error_code)) in messages.iter().zip(&results) {
+            trace::record_batched(&span, &self.app_id, &message.body, result, error_code.as_deref());
+        }
+
+        #[cfg(feature = "metrics")]
+        for (message, (result, error_code)) in messages.iter().zip(&results) {
+            let target = message.body.target_kind();
+            instrument::record_send(&self.app_id, target, result, error_code.as_deref());
+        }
+
+        results.into_iter().map(|(result, _)| result).collect()
+    }
+
+    /// Admit the messages as `deliver` does, then send them in batches.
+    async fn deliver_all(&self, messages: &[Message<'_>]) -> Vec<Sent> {
+        let mut results: Vec<Option<Sent>> = Vec::with_capacity(messages.len());
+        let mut admitted = Vec::with_capacity(messages.len());
+
+        for (i, message) in messages.iter().enumerate() {
+            match self.admit(message).await {
+                Ok(()) => {
+                    results.push(None);
+                    admitted.push(i);
+                }
+                Err(e) => results.push(Some((Err(e), None))),
+            }
+        }
+
+        let batches = admitted.chunks(BATCH_SIZE).map(|batch| {
+            let batch: Vec<&Message<'_>> = batch.iter().map(|&i| &messages[i]).collect();
+            async move { self.send_batch(&batch).await }
+        });
+
+        let sent = future::join_all(batches).await.into_iter().flatten();
+
+        for (i, sent) in admitted.into_iter().zip(sent) {
+            if let Some(ref circuit_breaker) = self.circuit_breaker {
+                circuit_breaker.record(&sent.0);
+            }
+
+            results[i] = Some(sent);
+        }
+
+        results
+            .into_iter()
+            .map(|sent| sent.unwrap_or((Err(FcmError::ServerError(None)), None)))
+            .collect()
+    }
+
+    /// Send up to `BATCH_SIZE` messages in a single batch call, falling back
+    /// to sending them one by one if the batch endpoint isn't available.
+    async fn send_batch(&self, messages: &[&Message<'_>]) -> Vec<Sent> {
+        let boundary: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+
+        let payload = self.batch_payload(&boundary, messages);
+
+        let mut builder = Request::builder()
+            .method("POST")
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/mixed; boundary={}", boundary).as_bytes(),
+            )
+            .header(
+                CONTENT_LENGTH,
+                format!("{}", payload.len() as u64).as_bytes(),
+            )
+            .uri(format!("{}/batch", self.fcm_url));
+
+        if let Some(message) = messages.first() {
+            builder = builder.header(
+                AUTHORIZATION,
+                format!("Bearer {}", message.access_token).as_bytes(),
+            );
+        }
+
+        #[cfg(feature = "metrics")]
+        let (payload_size, started, in_flight) = (
+            payload.len(),
+            std::time::Instant::now(),
+            instrument::InFlight::start(&self.app_id),
+        );
+
+        let request = builder.body(Body::from(payload)).unwrap();
+        let executed = self.execute_batch(request).await;
+
+        #[cfg(feature = "metrics")]
+        {
+            drop(in_flight);
+            instrument::record_request(&self.app_id, "batch", payload_size, started.elapsed());
+        }
+
+        let (status, retry_after, content_type, body) = match executed {
+            Ok(response) => response,
+            Err(e) => return messages.iter().map(|_| (Err(e.clone()), None)).collect(),
+        };
+
+        let parts_by_index = match status {
+            StatusCode::NOT_FOUND | StatusCode::GONE | StatusCode::NOT_IMPLEMENTED => {
+                return future::join_all(
+                    messages
+                        .iter()
+                        .map(|message| self.post_message(message, self.validate_only)),
+                )
+                .await
+            }
+            StatusCode::OK => content_type
+                .as_deref()
+                .and_then(response_boundary)
+                .and_then(|boundary| parse_parts(&boundary, &body))
+                .unwrap_or_default(),
+            status => {
+                return messages
+                    .iter()
+                    .map(|message| {
+                        let result = self.send_result(message, status, retry_after.clone(), &body);
+                        (result, error_code(status, &body))
+                    })
+                    .collect()
+            }
+        };
+
+        let mut parts: Vec<Option<Part>> = vec![None; messages.len()];
+
+        for (index, part) in parts_by_index {
+            if let Some(slot) = parts.get_mut(index) {
+                *slot = Some(part);
+            }
+        }
+
+        messages
+            .iter()
+            .zip(parts)
+            .map(|(message, part)| match part {
+                Some((status, retry_after, body)) => {
+                    let result = self.send_result(message, status, retry_after, &body);
+                    (result, error_code(status, &body))
+                }
+                // FCM accepted the batch, so the message may well have been
+                // delivered and mustn't be sent again.
+                None => (Err(unreadable()), None),
+            })
+            .collect()
+    }
+
+    /// The multipart body of a batch call, with a `messages:send` request for
+    /// each message, identified by its index.
+    fn batch_payload(&self, boundary: &str, messages: &[&Message<'_>]) -> Vec<u8> {
+        let mut payload = Vec::new();
+
+        for (i, message) in messages.iter().enumerate() {
+            let body = serde_json::to_string(&SendRequest::new(&message.body, self.validate_only))
+                .unwrap();
+
+            payload.extend_from_slice(
+                format!(
+                    "--{boundary}\r\n\
+                     Content-Type: application/http\r\n\
+                     Content-Transfer-Encoding: binary\r\n\
+                     Content-ID: <item{i}>\r\n\
+                     \r\n\
+                     POST /v1/projects/{app_id}/messages:send HTTP/1.1\r\n\
+                     Content-Type: application/json; charset=UTF-8\r\n\
+                     Authorization: Bearer {access_token}\r\n\
+                     Content-Length: {length}\r\n\
+                     \r\n\
+                     {body}\r\n",
+                    boundary = boundary,
+                    i = i,
+                    app_id = self.app_id,
+                    access_token = message.access_token,
+                    length = body.len(),
+                    body = body,
+                )
+                .as_bytes(),
+            );
+        }
+
+        payload.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        payload
+    }
+
+    /// Like `execute`, also returning the `Content-Type` of the response.
+    async fn execute_batch(
+        &self,
+        request: Request<Body>,
+    ) -> Result<(StatusCode, Option<RetryAfter>, Option<String>, Vec<u8>), FcmError> {
+        let response = self.transport.request(request).await?;
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .map(str::to_string);
+
+        let (status, retry_after, body) = read_response(response).await?;
+
+        Ok((status, retry_after, content_type, body))
+    }
+}
+
+/// The boundary of a `multipart/mixed` content type.
+fn response_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+
+    if !params.next()?.eq_ignore_ascii_case("multipart/mixed") {
+        return None;
+    }
+
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+/// Split the head of an HTTP message or multipart part from its body.
+fn split_head(part: &str) -> Option<(&str, &str)> {
+    part.split_once("\r\n\r\n")
+        .or_else(|| part.split_once("\n\n"))
+}
+
+/// The error of a message without a readable sub-response in the response
+/// to a batch call.
+fn unreadable() -> FcmError {
+    FcmError::InvalidMessage("unreadable batch response".to_string())
+}
+
+/// The sub-responses of a batch call along with the index of their request.
+/// They are matched up by their `Content-ID` of `<response-item{index}>`, or
+/// by their order if it's missing. Malformed parts are left out. `None` if
+/// the body isn't a multipart response.
+fn parse_parts(boundary: &str, body: &[u8]) -> Option<Vec<(usize, Part)>> {
+    let body = std::str::from_utf8(body).ok()?;
+    let delimiter = format!("--{}", boundary);
+
+    let mut segments = body.split(delimiter.as_str());
+    segments.next()?;
+
+    let parts = segments
+        .take_while(|segment| !segment.starts_with("--"))
+        .enumerate()
+        .filter_map(|(position, segment)| parse_part(position, segment))
+        .collect();
+
+    Some(parts)
+}
+
+/// A sub-response of a batch call and the index of its request, `None` if
+/// it's malformed.
+fn parse_part(position: usize, segment: &str) -> Option<(usize, Part)> {
+    let (part_headers, http) = split_head(segment.trim_start_matches(&['\r', '\n'][..]))?;
+
+    let index = part_headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-id"))
+        .and_then(|(_, id)| {
+            id.trim()
+                .trim_start_matches("<response-item")
+                .trim_end_matches('>')
+                .parse()
+                .ok()
+        })
+        .unwrap_or(position);
+
+    let (head, body) = split_head(http)?;
+    let mut lines = head.lines();
+
+    let status = lines
+        .next()?
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok())?;
+
+    let retry_after = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| RetryAfter::from_str(value.trim()));
+
+    let body = body.trim_end_matches(&['\r', '\n'][..]).as_bytes().to_vec();
+
+    Some((index, (status, retry_after, body)))
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use crate::client::{FcmError, FcmResponse, RetryAfter};
+
+/// Whether a `CircuitBreaker` lets sends through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    /// Sends go through, their outcomes are counted.
+    Closed,
+    /// Sends fail with `FcmError::CircuitOpen` without reaching FCM.
+    Open,
+    /// A few probing sends go through to find out if FCM has recovered.
+    HalfOpen,
+}
+
+/// A builder to get a `CircuitBreaker`.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{CircuitBreakerBuilder, CircuitState, ClientBuilder};
+/// use std::time::Duration;
+///
+/// let mut breaker = CircuitBreakerBuilder::new();
+/// breaker.failure_rate(0.25);
+/// breaker.open_duration(Duration::from_secs(60));
+/// let breaker = breaker.finalize();
+///
+/// let mut builder = ClientBuilder::new("<project id>".to_string());
+/// builder.circuit_breaker(breaker.clone());
+/// let client = builder.finalize();
+///
+/// assert_eq!(CircuitState::Closed, breaker.state());
+/// ```
+#[derive(Debug)]
+pub struct CircuitBreakerBuilder {
+    failure_rate: f64,
+    minimum_requests: usize,
+    window: Duration,
+    open_duration: Duration,
+    half_open_requests: u32,
+}
+
+impl CircuitBreakerBuilder {
+    pub fn new() -> CircuitBreakerBuilder {
+        CircuitBreakerBuilder {
+            failure_rate: 0.5,
+            minimum_requests: 20,
+            window: Duration::from_secs(60),
+            open_duration: Duration::from_secs(30),
+            half_open_requests: 1,
+        }
+    }
+
+    /// The share of failed sends in the window that opens the circuit,
+    /// defaults to 0.5. Server errors and failures to reach FCM count as
+    /// failed.
+    pub fn failure_rate(&mut self, failure_rate: f64) -> &mut Self {
+        self.failure_rate = failure_rate;
+        self
+    }
+
+    /// How many sends the window needs before the circuit can open,
+    /// defaults to 20.
+    pub fn minimum_requests(&mut self, minimum_requests: usize) -> &mut Self {
+        self.minimum_requests = minimum_requests;
+        self
+    }
+
+    /// How far back sends are counted, defaults to a minute.
+    pub fn window(&mut self, window: Duration) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    /// How long the circuit stays open before probing, defaults to 30
+    /// seconds. A longer `RetryAfter` from FCM keeps it open longer.
+    pub fn open_duration(&mut self, open_duration: Duration) -> &mut Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    /// How many probing sends must succeed to close the circuit again,
+    /// defaults to one. Any failed probe opens it again.
+    pub fn half_open_requests(&mut self, half_open_requests: u32) -> &mut Self {
+        self.half_open_requests = half_open_requests.max(1);
+        self
+    }
+
+    /// Complete the build and get a `CircuitBreaker` instance
+    pub fn finalize(self) -> CircuitBreaker {
+        let now = Instant::now();
+
+        CircuitBreaker {
+            config: Arc::new(self),
+            state: Arc::new(Mutex::new(State {
+                circuit: CircuitState::Closed,
+                outcomes: VecDeque::new(),
+                open_until: now,
+                retry_until: None,
+                probes: 0,
+                probe_successes: 0,
+            })),
+        }
+    }
+}
+
+impl Default for CircuitBreakerBuilder {
+    fn default() -> Self {
+        CircuitBreakerBuilder::new()
+    }
+}
+
+struct State {
+    circuit: CircuitState,
+    /// When each send in the window finished and whether it failed.
+    outcomes: VecDeque<(Instant, bool)>,
+    open_until: Instant,
+    /// The end of the longest `RetryAfter` seen.
+    retry_until: Option<Instant>,
+    probes: u32,
+    probe_successes: u32,
+}
+
+impl State {
+    fn open(&mut self, config: &CircuitBreakerBuilder, now: Instant) {
+        let open_until = now + config.open_duration;
+
+        self.circuit = CircuitState::Open;
+        self.open_until = self.retry_until.map_or(open_until, |r| r.max(open_until));
+        self.outcomes.clear();
+    }
+
+    fn close(&mut self) {
+        self.circuit = CircuitState::Closed;
+        self.retry_until = None;
+        self.outcomes.clear();
+    }
+}
+
+/// Stops sending to FCM during an outage. Opens when the failure rate of
+/// the sends in the window reaches the configured one, fails the sends
+/// while open and lets probing sends through when the open duration and
+/// any `RetryAfter` have passed. Clones share their state.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    config: Arc<CircuitBreakerBuilder>,
+    state: Arc<Mutex<State>>,
+}
+
+impl CircuitBreaker {
+    pub fn state(&self) -> CircuitState {
+        self.state.lock().unwrap().circuit
+    }
+
+    /// Take a turn for a send, failing with how long until the circuit
+    /// half-opens if it's open.
+    pub(crate) fn acquire(&self) -> Result<(), FcmError> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        if state.circuit == CircuitState::Open {
+            if now < state.open_until {
+                let retry_after = chrono::Duration::from_std(state.open_until - now)
+                    .ok()
+                    .map(RetryAfter::Delay);
+
+                return Err(FcmError::CircuitOpen(retry_after));
+            }
+
+            state.circuit = CircuitState::HalfOpen;
+            state.probes = 0;
+            state.probe_successes = 0;
+        }
+
+        if state.circuit == CircuitState::HalfOpen {
+            // Probes never answered, e.g. of dropped sends, are given up on
+            // after another open duration.
+            if state.probes >= self.config.half_open_requests {
+                if now < state.open_until + self.config.open_duration {
+                    return Err(FcmError::CircuitOpen(None));
+                }
+
+                state.probes = state.probe_successes;
+            }
+
+            state.probes += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Count the outcome of a send let through by `acquire`.
+    pub(crate) fn record(&self, result: &Result<FcmResponse, FcmError>) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let failed = match result {
+            Err(FcmError::ServerError(retry_after)) => {
+                let retry_until = retry_after
+                    .as_ref()
+                    .and_then(retry_duration)
+                    .map(|d| now + d);
+
+                if retry_until > state.retry_until {
+                    state.retry_until = retry_until;
+                }
+
+                true
+            }
+            Err(FcmError::Transport(_)) => true,
+            _ => false,
+        };
+
+        match state.circuit {
+            CircuitState::Closed => {
+                let window = self.config.window;
+
+                state.outcomes.push_back((now, failed));
+                while let Some(&(finished, _)) = state.outcomes.front() {
+                    if now.duration_since(finished) <= window {
+                        break;
+                    }
+
+                    state.outcomes.pop_front();
+                }
+
+                let total = state.outcomes.len();
+                let failures = state.outcomes.iter().filter(|(_, failed)| *failed).count();
+
+                if total >= self.config.minimum_requests
+                    && failures as f64 >= total as f64 * self.config.failure_rate
+                    && failures > 0
+                {
+                    state.open(&self.config, now);
+                }
+            }
+            CircuitState::HalfOpen if failed => state.open(&self.config, now),
+            CircuitState::HalfOpen => {
+                state.probe_successes += 1;
+
+                if state.probe_successes >= self.config.half_open_requests {
+                    state.close();
+                }
+            }
+            CircuitState::Open => (),
+        }
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("config", &self.config)
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+fn retry_duration(retry_after: &RetryAfter) -> Option<Duration> {
+    match retry_after {
+        RetryAfter::Delay(delay) => delay.to_std().ok(),
+        RetryAfter::DateTime(date_time) => {
+            date_time.signed_duration_since(Utc::now()).to_std().ok()
+        }
+    }
+}
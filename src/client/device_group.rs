@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::fmt;
+
+use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, StatusCode};
+
+use crate::client::response::FcmError;
+use crate::client::Client;
+
+/// Why a device group operation was rejected. Referred from [Firebase
+/// documentation](https://firebase.google.com/docs/cloud-messaging/android/device-group).
+#[derive(Debug, PartialEq, Clone)]
+pub enum DeviceGroupError {
+    /// A device group with the notification key name already exists.
+    AlreadyExists,
+
+    /// No device group exists for the notification key or name.
+    NotFound,
+
+    /// The device group would exceed the maximum of 20 members.
+    TooManyMembers,
+
+    /// None of the registration tokens were valid.
+    NoValidRegistrationIds,
+
+    /// Any other error returned by FCM.
+    Other(String),
+}
+
+impl DeviceGroupError {
+    fn from_message(message: &str) -> DeviceGroupError {
+        let lowercase = message.to_lowercase();
+
+        if lowercase.contains("already exists") {
+            DeviceGroupError::AlreadyExists
+        } else if lowercase.contains("not found") {
+            DeviceGroupError::NotFound
+        } else if lowercase.contains("maximum") || lowercase.contains("too many") {
+            DeviceGroupError::TooManyMembers
+        } else if lowercase.contains("no valid registration") {
+            DeviceGroupError::NoValidRegistrationIds
+        } else {
+            DeviceGroupError::Other(message.to_string())
+        }
+    }
+}
+
+impl Error for DeviceGroupError {}
+
+impl fmt::Display for DeviceGroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceGroupError::AlreadyExists => {
+                write!(f, "the notification key name already exists")
+            }
+            DeviceGroupError::NotFound => write!(f, "the notification key was not found"),
+            DeviceGroupError::TooManyMembers => write!(f, "the device group is full"),
+            DeviceGroupError::NoValidRegistrationIds => write!(f, "no valid registration ids"),
+            DeviceGroupError::Other(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeviceGroupRequest<'a> {
+    operation: &'a str,
+    notification_key_name: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification_key: Option<&'a str>,
+
+    registration_ids: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct DeviceGroupResponse {
+    notification_key: Option<String>,
+    error: Option<String>,
+}
+
+impl Client {
+    /// Create a device group of the registration tokens, returning its
+    /// notification key. The `sender_id` is the numeric sender id of the
+    /// Firebase project.
+    ///
+    /// # Examples:
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let client = fcm::Client::new("<project id>".to_string());
+    ///
+    /// let notification_key = client
+    ///     .create_device_group("<FCM access token>", "<sender id>", "user-42", &["<registration id>"])
+    ///     .await?;
+    ///
+    /// let mut builder = fcm::MessageBuilder::new("<FCM access token>", "");
+    /// builder.notification_key(&notification_key);
+    /// client.send(builder.finalize()).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_device_group<S>(
+        &self,
+        access_token: &str,
+        sender_id: &str,
+        notification_key_name: &str,
+        tokens: &[S],
+    ) -> Result<String, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        let request = DeviceGroupRequest {
+            operation: "create",
+            notification_key_name,
+            notification_key: None,
+            registration_ids: tokens.iter().map(|t| t.as_ref()).collect(),
+        };
+
+        self.manage_device_group(access_token, sender_id, &request)
+            .await
+    }
+
+    /// Add the registration tokens to an existing device group, returning
+    /// its notification key.
+    pub async fn add_to_device_group<S>(
+        &self,
+        access_token: &str,
+        sender_id: &str,
+        notification_key_name: &str,
+        notification_key: &str,
+        tokens: &[S],
+    ) -> Result<String, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        let request = DeviceGroupRequest {
+            operation: "add",
+            notification_key_name,
+            notification_key: Some(notification_key),
+            registration_ids: tokens.iter().map(|t| t.as_ref()).collect(),
+        };
+
+        self.manage_device_group(access_token, sender_id, &request)
+            .await
+    }
+
+    /// Remove the registration tokens from a device group, returning its
+    /// notification key. The group is deleted once all tokens are removed.
+    pub async fn remove_from_device_group<S>(
+        &self,
+        access_token: &str,
+        sender_id: &str,
+        notification_key_name: &str,
+        notification_key: &str,
+        tokens: &[S],
+    ) -> Result<String, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        let request = DeviceGroupRequest {
+            operation: "remove",
+            notification_key_name,
+            notification_key: Some(notification_key),
+            registration_ids: tokens.iter().map(|t| t.as_ref()).collect(),
+        };
+
+        self.manage_device_group(access_token, sender_id, &request)
+            .await
+    }
+
+    /// Look up the notification key of a device group by its name.
+    pub async fn device_group_key(
+        &self,
+        access_token: &str,
+        sender_id: &str,
+        notification_key_name: &str,
+    ) -> Result<String, FcmError> {
+        let request = self
+            .device_group_request(
+                "GET",
+                &format!(
+                    "?notification_key_name={}",
+                    percent_encode(notification_key_name)
+                ),
+                access_token,
+                sender_id,
+            )
+            .body(Body::empty())
+            .map_err(|e| FcmError::InvalidMessage(e.to_string()))?;
+
+        self.execute_device_group(request).await
+    }
+
+    async fn manage_device_group(
+        &self,
+        access_token: &str,
+        sender_id: &str,
+        request: &DeviceGroupRequest<'_>,
+    ) -> Result<String, FcmError> {
+        let payload = serde_json::to_vec(request).unwrap();
+
+        let request = self
+            .device_group_request("POST", "", access_token, sender_id)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                CONTENT_LENGTH,
+                format!("{}", payload.len() as u64).as_bytes(),
+            )
+            .body(Body::from(payload))
+            .unwrap();
+
+        self.execute_device_group(request).await
+    }
+
+    fn device_group_request(
+        &self,
+        method: &str,
+        query: &str,
+        access_token: &str,
+        sender_id: &str,
+    ) -> http::request::Builder {
+        Request::builder()
+            .method(method)
+            .header(AUTHORIZATION, format!("Bearer {}", access_token).as_bytes())
+            .header("access_token_auth", "true")
+            .header("project_id", sender_id)
+            .uri(format!("{}/fcm/notification{}", self.fcm_url, query))
+    }
+
+    async fn execute_device_group(&self, request: Request<Body>) -> Result<String, FcmError> {
+        let (response_status, retry_after, body) = self.execute(request).await?;
+        let response: Option<DeviceGroupResponse> = serde_json::from_slice(&body).ok();
+
+        match (response_status, response) {
+            (
+                StatusCode::OK,
+                Some(DeviceGroupResponse {
+                    notification_key: Some(key),
+                    ..
+                }),
+            ) => Ok(key),
+            (StatusCode::UNAUTHORIZED, _) | (StatusCode::FORBIDDEN, _) => {
+                Err(FcmError::Unauthorized)
+            }
+            (StatusCode::NOT_FOUND, _) => Err(FcmError::DeviceGroup(DeviceGroupError::NotFound)),
+            (status, _) if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
+            (
+                _,
+                Some(DeviceGroupResponse {
+                    error: Some(error), ..
+                }),
+            ) => Err(FcmError::DeviceGroup(DeviceGroupError::from_message(
+                &error,
+            ))),
+            _ => Err(FcmError::InvalidMessage("Unknown Error".to_string())),
+        }
+    }
+}
+
+/// Percent-encode everything but the unreserved characters of RFC 3986, for
+/// use as a query parameter value.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
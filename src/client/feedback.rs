@@ -0,0 +1,146 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::client::response::{ErrorReason, FcmResponse};
+use crate::redact::Token;
+use crate::store::{self, TokenStore};
+
+/// Why a registration token should be removed from the token database.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RemovalReason {
+    /// The app instance was uninstalled or its token expired. Reported as
+    /// `NotRegistered` by the legacy API and `UNREGISTERED` by v1.
+    Unregistered,
+
+    /// The token is malformed. Reported as `InvalidRegistration` by the
+    /// legacy API.
+    InvalidRegistration,
+
+    /// The token belongs to a different sender. Reported as
+    /// `MismatchSenderId` by the legacy API and `SENDER_ID_MISMATCH` by v1.
+    SenderIdMismatch,
+}
+
+/// A change to the registration tokens learned from a send response.
+#[derive(PartialEq, Clone)]
+pub enum TokenEvent {
+    /// FCM returned a canonical registration id, the first token should be
+    /// replaced with the second one.
+    Replace(String, String),
+
+    /// The token is no longer valid and should be removed.
+    Remove(String, RemovalReason),
+}
+
+/// Cuts down the tokens, see `Unredacted`.
+impl fmt::Debug for TokenEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenEvent::Replace(old, new) => f
+                .debug_tuple("Replace")
+                .field(&Token(old))
+                .field(&Token(new))
+                .finish(),
+            TokenEvent::Remove(token, reason) => f
+                .debug_tuple("Remove")
+                .field(&Token(token))
+                .field(reason)
+                .finish(),
+        }
+    }
+}
+
+/// A hook invoked with the token changes found in the responses of a
+/// `Client` or a `LegacyClient`, after each send. Closures taking a
+/// `TokenEvent` implement it as well.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{ClientBuilder, TokenEvent};
+///
+/// let mut builder = ClientBuilder::new("<project id>".to_string());
+/// builder.token_feedback(|event: TokenEvent| match event {
+///     TokenEvent::Replace(old, new) => println!("{} is now {}", old, new),
+///     TokenEvent::Remove(token, reason) => println!("{} removed: {:?}", token, reason),
+/// });
+/// let client = builder.finalize();
+/// ```
+pub trait TokenFeedback: Send + Sync {
+    fn on_event(&self, event: TokenEvent);
+}
+
+impl<F> TokenFeedback for F
+where
+    F: Fn(TokenEvent) + Send + Sync,
+{
+    fn on_event(&self, event: TokenEvent) {
+        self(event)
+    }
+}
+
+impl<T> TokenFeedback for Arc<T>
+where
+    T: TokenFeedback + ?Sized,
+{
+    fn on_event(&self, event: TokenEvent) {
+        (**self).on_event(event)
+    }
+}
+
+impl RemovalReason {
+    fn from_error_reason(reason: ErrorReason) -> Option<RemovalReason> {
+        match reason {
+            ErrorReason::NotRegistered => Some(RemovalReason::Unregistered),
+            ErrorReason::InvalidRegistration => Some(RemovalReason::InvalidRegistration),
+            ErrorReason::MismatchSenderId => Some(RemovalReason::SenderIdMismatch),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_error_code(code: &str) -> Option<RemovalReason> {
+        match code {
+            "UNREGISTERED" => Some(RemovalReason::Unregistered),
+            "SENDER_ID_MISMATCH" => Some(RemovalReason::SenderIdMismatch),
+            _ => None,
+        }
+    }
+}
+
+/// The token events of a legacy API response, whose results are in the
+/// order of the tokens the message was sent to.
+pub(crate) fn legacy_events(tokens: &[&str], response: &FcmResponse) -> Vec<TokenEvent> {
+    let results = match response.results {
+        Some(ref results) => results,
+        None => return Vec::new(),
+    };
+
+    tokens
+        .iter()
+        .zip(results)
+        .filter_map(|(token, result)| {
+            if let Some(ref canonical) = result.registration_id {
+                Some(TokenEvent::Replace(token.to_string(), canonical.clone()))
+            } else {
+                result
+                    .error
+                    .and_then(RemovalReason::from_error_reason)
+                    .map(|reason| TokenEvent::Remove(token.to_string(), reason))
+            }
+        })
+        .collect()
+}
+
+/// Tell the hook about the event and apply it to the token store.
+pub(crate) fn dispatch(
+    feedback: Option<&dyn TokenFeedback>,
+    token_store: Option<&dyn TokenStore>,
+    event: TokenEvent,
+) {
+    if let Some(token_store) = token_store {
+        store::prune(token_store, &event);
+    }
+
+    if let Some(feedback) = feedback {
+        feedback.on_event(event);
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, StatusCode};
+
+use crate::client::response::FcmError;
+use crate::client::Client;
+use crate::redact::{self, Token};
+
+/// The Instance ID API accepts at most this many tokens per batch request.
+const TOPIC_BATCH_SIZE: usize = 1000;
+
+/// The Instance ID API imports at most this many APNs tokens per request.
+const IMPORT_BATCH_SIZE: usize = 100;
+
+/// Why a token couldn't be subscribed to or unsubscribed from a topic.
+/// Referred from [Instance ID
+/// documentation](https://developers.google.com/instance-id/reference/server#manage_relationship_maps_for_multiple_app_instances).
+#[derive(Deserialize, Debug, PartialEq, Copy, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TopicManagementError {
+    /// The registration token has been deleted or the app has been
+    /// uninstalled.
+    NotFound,
+
+    /// The registration token provided is not valid for the sender id.
+    InvalidArgument,
+
+    /// The backend server failed for unknown reasons. Retry the request.
+    Internal,
+
+    /// Excessive number of topics per app instance.
+    TooManyTopics,
+
+    /// An error not known to this crate.
+    #[serde(other)]
+    Unknown,
+}
+
+/// The outcome of subscribing or unsubscribing a single token.
+#[derive(PartialEq)]
+pub struct TopicManagementResult {
+    pub token: String,
+    pub error: Option<TopicManagementError>,
+}
+
+/// Cuts down the token, see `Unredacted`.
+impl fmt::Debug for TopicManagementResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TopicManagementResult")
+            .field("token", &Token(&self.token))
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// The platform an app instance runs on.
+#[derive(Deserialize, Debug, PartialEq, Copy, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Platform {
+    Android,
+    Ios,
+    Chrome,
+
+    /// A platform not known to this crate.
+    #[serde(other)]
+    Unknown,
+}
+
+/// A topic an app instance is subscribed to.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicSubscription {
+    /// The date of the subscription, formatted as `YYYY-MM-DD`.
+    pub add_date: Option<String>,
+}
+
+/// Relations of an app instance, such as its topic subscriptions.
+#[derive(Deserialize, Debug, PartialEq, Default)]
+pub struct TokenRelations {
+    #[serde(default)]
+    pub topics: HashMap<String, TopicSubscription>,
+}
+
+/// What the Instance ID service knows about a registration token. Referred
+/// from [Instance ID
+/// documentation](https://developers.google.com/instance-id/reference/server#get_information_about_app_instances).
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    /// The package name or bundle id of the app.
+    pub application: Option<String>,
+
+    pub application_version: Option<String>,
+
+    /// The project or sender id allowed to send messages to the token.
+    pub authorized_entity: Option<String>,
+
+    pub platform: Option<Platform>,
+
+    pub app_signer: Option<String>,
+
+    pub attest_status: Option<String>,
+
+    pub connection_type: Option<String>,
+
+    pub connect_date: Option<String>,
+
+    #[serde(default)]
+    pub rel: TokenRelations,
+}
+
+/// Why an APNs token couldn't be imported.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ApnsImportError {
+    /// The APNs token is malformed or not valid for the application.
+    InvalidToken,
+
+    /// The backend server failed for unknown reasons. Retry the import.
+    Internal,
+
+    /// Any other status returned by the Instance ID service.
+    Other(String),
+}
+
+impl ApnsImportError {
+    /// The error for the status of a result, matching the documented
+    /// statuses exactly.
+    fn from_status(status: &str) -> Option<ApnsImportError> {
+        match status {
+            "OK" => None,
+            "Invalid apns token" => Some(ApnsImportError::InvalidToken),
+            "Internal Server Error" => Some(ApnsImportError::Internal),
+            _ => Some(ApnsImportError::Other(status.to_string())),
+        }
+    }
+}
+
+/// The outcome of importing a single APNs token.
+#[derive(PartialEq)]
+pub struct ApnsTokenImportResult {
+    pub apns_token: String,
+
+    /// The FCM registration token to use instead of the APNs token.
+    pub registration_token: Option<String>,
+
+    pub error: Option<ApnsImportError>,
+}
+
+/// Cuts down the tokens, see `Unredacted`.
+impl fmt::Debug for ApnsTokenImportResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApnsTokenImportResult")
+            .field("apns_token", &Token(&self.apns_token))
+            .field("registration_token", &redact::token(&self.registration_token))
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct ApnsImportRequest<'a> {
+    application: &'a str,
+    sandbox: bool,
+    apns_tokens: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct ApnsImportResponse {
+    results: Vec<ApnsImportResponseResult>,
+}
+
+#[derive(Deserialize)]
+struct ApnsImportResponseResult {
+    status: String,
+    registration_token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TopicManagementRequest<'a> {
+    to: &'a str,
+    registration_tokens: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct TopicManagementResponse {
+    results: Vec<TopicManagementResponseResult>,
+}
+
+#[derive(Deserialize)]
+struct TopicManagementResponseResult {
+    error: Option<TopicManagementError>,
+}
+
+impl Client {
+    /// Subscribe the registration tokens to a topic, in batches of 1000.
+    /// Returns a result per token, in the order of `tokens`.
+    ///
+    /// # Examples:
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let client = fcm::Client::new("<project id>".to_string());
+    ///
+    /// let results = client
+    ///     .subscribe_to_topic("<FCM access token>", "news", &["<registration id>"])
+    ///     .await?;
+    ///
+    /// for result in results.iter().filter(|r| r.error.is_some()) {
+    ///     println!("Couldn't subscribe {}: {:?}", result.token, result.error);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_to_topic<S>(
+        &self,
+        access_token: &str,
+        topic: &str,
+        tokens: &[S],
+    ) -> Result<Vec<TopicManagementResult>, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        self.manage_topic("batchAdd", access_token, topic, tokens)
+            .await
+    }
+
+    /// Unsubscribe the registration tokens from a topic, in batches of 1000.
+    /// Returns a result per token, in the order of `tokens`.
+    pub async fn unsubscribe_from_topic<S>(
+        &self,
+        access_token: &str,
+        topic: &str,
+        tokens: &[S],
+    ) -> Result<Vec<TopicManagementResult>, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        self.manage_topic("batchRemove", access_token, topic, tokens)
+            .await
+    }
+
+    /// Look up the app, platform and topic subscriptions of a registration
+    /// token. Fails with `FcmError::NotFound` if the token is not known.
+    ///
+    /// # Examples:
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let client = fcm::Client::new("<project id>".to_string());
+    ///
+    /// match client.token_info("<FCM access token>", "<registration id>").await {
+    ///     Ok(info) => println!("{:?} subscribed to {:?}", info.platform, info.rel.topics.keys()),
+    ///     Err(fcm::Error::NotFound) => println!("The token is not registered"),
+    ///     Err(e) => return Err(e.into()),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn token_info(&self, access_token: &str, token: &str) -> Result<TokenInfo, FcmError> {
+        let request = self
+            .iid_request(
+                "GET",
+                &format!("/iid/info/{}?details=true", token),
+                access_token,
+            )
+            .body(Body::empty())
+            .unwrap();
+
+        let body = self.execute_iid(request).await?;
+
+        serde_json::from_slice(&body).map_err(|e| FcmError::InvalidMessage(e.to_string()))
+    }
+
+    /// Convert APNs device tokens of an iOS app to FCM registration tokens,
+    /// in batches of 100. Set `sandbox` for tokens of development builds.
+    /// Returns a result per token, in the order of `apns_tokens`.
+    ///
+    /// # Examples:
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let client = fcm::Client::new("<project id>".to_string());
+    ///
+    /// let results = client
+    ///     .import_apns_tokens("<FCM access token>", "com.example.app", false, &["<APNs token>"])
+    ///     .await?;
+    ///
+    /// for result in results {
+    ///     println!("{} is now {:?}", result.apns_token, result.registration_token);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn import_apns_tokens<S>(
+        &self,
+        access_token: &str,
+        bundle_id: &str,
+        sandbox: bool,
+        apns_tokens: &[S],
+    ) -> Result<Vec<ApnsTokenImportResult>, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        let mut results = Vec::with_capacity(apns_tokens.len());
+
+        for chunk in apns_tokens.chunks(IMPORT_BATCH_SIZE) {
+            let payload = serde_json::to_vec(&ApnsImportRequest {
+                application: bundle_id,
+                sandbox,
+                apns_tokens: chunk.iter().map(|t| t.as_ref()).collect(),
+            })
+            .unwrap();
+
+            let request = self
+                .iid_request("POST", "/iid/v1:batchImport", access_token)
+                .header(CONTENT_TYPE, "application/json")
+                .header(
+                    CONTENT_LENGTH,
+                    format!("{}", payload.len() as u64).as_bytes(),
+                )
+                .body(Body::from(payload))
+                .unwrap();
+
+            let body = self.execute_iid(request).await?;
+
+            let response: ApnsImportResponse = serde_json::from_slice(&body)
+                .map_err(|e| FcmError::InvalidMessage(e.to_string()))?;
+
+            expect_results(chunk.len(), response.results.len())?;
+
+            results.extend(chunk.iter().zip(response.results).map(|(token, result)| {
+                ApnsTokenImportResult {
+                    apns_token: token.as_ref().to_string(),
+                    error: ApnsImportError::from_status(&result.status),
+                    registration_token: result.registration_token,
+                }
+            }));
+        }
+
+        Ok(results)
+    }
+
+    async fn manage_topic<S>(
+        &self,
+        operation: &str,
+        access_token: &str,
+        topic: &str,
+        tokens: &[S],
+    ) -> Result<Vec<TopicManagementResult>, FcmError>
+    where
+        S: AsRef<str>,
+    {
+        let topic = format!("/topics/{}", topic.trim_start_matches("/topics/"));
+        let mut results = Vec::with_capacity(tokens.len());
+
+        for chunk in tokens.chunks(TOPIC_BATCH_SIZE) {
+            let payload = serde_json::to_vec(&TopicManagementRequest {
+                to: &topic,
+                registration_tokens: chunk.iter().map(|t| t.as_ref()).collect(),
+            })
+            .unwrap();
+
+            let request = self
+                .iid_request("POST", &format!("/iid/v1:{}", operation), access_token)
+                .header(CONTENT_TYPE, "application/json")
+                .header(
+                    CONTENT_LENGTH,
+                    format!("{}", payload.len() as u64).as_bytes(),
+                )
+                .body(Body::from(payload))
+                .unwrap();
+
+            let body = self.execute_iid(request).await?;
+
+            let response: TopicManagementResponse = serde_json::from_slice(&body)
+                .map_err(|e| FcmError::InvalidMessage(e.to_string()))?;
+
+            expect_results(chunk.len(), response.results.len())?;
+
+            results.extend(chunk.iter().zip(response.results).map(|(token, result)| {
+                TopicManagementResult {
+                    token: token.as_ref().to_string(),
+                    error: result.error,
+                }
+            }));
+        }
+
+        Ok(results)
+    }
+
+    fn iid_request(&self, method: &str, path: &str, access_token: &str) -> http::request::Builder {
+        Request::builder()
+            .method(method)
+            .header(AUTHORIZATION, format!("Bearer {}", access_token).as_bytes())
+            .header("access_token_auth", "true")
+            .uri(format!("{}{}", self.iid_url, path))
+    }
+
+    /// Send a request to the Instance ID API, returning the body of a
+    /// successful response.
+    async fn execute_iid(&self, request: Request<Body>) -> Result<Vec<u8>, FcmError> {
+        let (response_status, retry_after, body) = self.execute(request).await?;
+
+        match response_status {
+            StatusCode::OK => Ok(body),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(FcmError::Unauthorized),
+            StatusCode::NOT_FOUND => Err(FcmError::NotFound),
+            StatusCode::TOO_MANY_REQUESTS => Err(FcmError::RateLimited(retry_after)),
+            StatusCode::BAD_REQUEST => Err(FcmError::InvalidMessage(
+                String::from_utf8_lossy(&body).into_owned(),
+            )),
+            status if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
+            _ => Err(FcmError::InvalidMessage("Unknown Error".to_string())),
+        }
+    }
+}
+
+/// Fail unless a batch response has a result for each token sent, as they
+/// are matched up by their position.
+fn expect_results(sent: usize, received: usize) -> Result<(), FcmError> {
+    if sent == received {
+        Ok(())
+    } else {
+        Err(FcmError::InvalidMessage(format!(
+            "expected {} results, got {}",
+            sent, received
+        )))
+    }
+}
@@ -0,0 +1,110 @@
+//! Metrics of the sends of a `Client`, recorded with the `metrics` crate:
+//!
+//! * `fcm_messages_sent_total`, a counter labeled by `project_id`,
+//!   `target`, `outcome` (`success` or `error`) and `error_code`, the FCM
+//!   error code of the response such as `UNREGISTERED`, or the kind of
+//!   `FcmError` if FCM didn't give one
+//! * `fcm_request_duration_seconds`, a histogram of the time from sending a
+//!   request to having read its response
+//! * `fcm_payload_size_bytes`, a histogram of the request body sizes
+//! * `fcm_requests_in_flight`, a gauge of the requests awaiting a response
+//! * `fcm_messages_throttled_total`, a counter of the sends the
+//!   `RateLimiter` delayed or rejected, labeled by `action`
+//!
+//! The `target` is `token`, `topic`, `condition` or `none`, or `batch` for
+//! the requests of `send_all` packing several messages.
+
+use std::time::Duration;
+
+use metrics::{counter, gauge, histogram};
+
+use crate::client::{FcmError, FcmResponse};
+
+fn error_code(error: &FcmError) -> &'static str {
+    match error {
+        FcmError::Unauthorized => "unauthorized",
+        FcmError::InvalidMessage(_) => "invalid_message",
+        FcmError::ServerError(_) => "server_error",
+        FcmError::NotFound => "not_found",
+        FcmError::DeviceGroup(_) => "device_group",
+        FcmError::TokenStore(_) => "token_store",
+        FcmError::ValidationFailed(_) => "validation_failed",
+        FcmError::RateLimited(_) => "rate_limited",
+        FcmError::CircuitOpen(_) => "circuit_open",
+        FcmError::Transport(_) => "transport",
+    }
+}
+
+pub(crate) fn record_send(
+    project_id: &str,
+    target: &'static str,
+    result: &Result<FcmResponse, FcmError>,
+    fcm_error_code: Option<&str>,
+) {
+    let (outcome, error_code) = match (result, fcm_error_code) {
+        (Ok(_), _) => ("success", "none".to_string()),
+        (Err(_), Some(code)) => ("error", code.to_string()),
+        (Err(e), None) => ("error", error_code(e).to_string()),
+    };
+
+    counter!(
+        "fcm_messages_sent_total",
+        "project_id" => project_id.to_string(),
+        "target" => target,
+        "outcome" => outcome,
+        "error_code" => error_code,
+    )
+    .increment(1);
+}
+
+pub(crate) fn record_request(
+    project_id: &str,
+    target: &'static str,
+    payload_size: usize,
+    duration: Duration,
+) {
+    histogram!(
+        "fcm_payload_size_bytes",
+        "project_id" => project_id.to_string(),
+        "target" => target,
+    )
+    .record(payload_size as f64);
+
+    histogram!(
+        "fcm_request_duration_seconds",
+        "project_id" => project_id.to_string(),
+        "target" => target,
+    )
+    .record(duration.as_secs_f64());
+}
+
+pub(crate) fn record_throttled(project_id: &str, action: &'static str) {
+    counter!(
+        "fcm_messages_throttled_total",
+        "project_id" => project_id.to_string(),
+        "action" => action,
+    )
+    .increment(1);
+}
+
+/// Counts a request in `fcm_requests_in_flight` until dropped, so requests
+/// of cancelled sends are no longer counted either.
+pub(crate) struct InFlight {
+    project_id: String,
+}
+
+impl InFlight {
+    pub(crate) fn start(project_id: &str) -> InFlight {
+        gauge!("fcm_requests_in_flight", "project_id" => project_id.to_string()).increment(1.0);
+
+        InFlight {
+            project_id: project_id.to_string(),
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        gauge!("fcm_requests_in_flight", "project_id" => self.project_id.clone()).decrement(1.0);
+    }
+}
@@ -1,38 +1,517 @@
+#[cfg(feature = "batch")]
+mod batch;
+mod circuit_breaker;
+mod device_group;
+mod feedback;
+mod iid;
+#[cfg(feature = "metrics")]
+mod instrument;
+mod rate_limit;
 pub mod response;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "tracing")]
+mod trace;
+mod transport;
+mod user;
+
+#[cfg(feature = "batch")]
+pub use crate::client::batch::*;
+pub use crate::client::circuit_breaker::*;
+pub use crate::client::device_group::*;
+pub use crate::client::feedback::*;
+pub use crate::client::iid::*;
+pub use crate::client::rate_limit::*;
+#[cfg(feature = "tower")]
+pub use crate::client::service::*;
+pub use crate::client::transport::*;
+pub use crate::client::user::*;
+
+#[cfg(test)]
+mod tests;
 
 pub use crate::client::response::*;
 
 use futures::stream::StreamExt;
 use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
-use hyper::{
-    client::{Client as HttpClient, HttpConnector},
-    Body, Request, StatusCode,
-};
-use hyper_tls::{self, HttpsConnector};
-use crate::message::Message;
-use serde_json;
-
-/// An async client for sending the notification payload.
+use hyper::{Body, Request, StatusCode};
+use crate::message::{Message, MessageBody};
+use crate::store::TokenStore;
+use std::sync::Arc;
+
+/// The request body of a v1 send.
+#[derive(Serialize)]
+struct SendRequest<'a, 'b> {
+    #[serde(serialize_with = "without_dry_run")]
+    message: &'b MessageBody<'a>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validate_only: Option<bool>,
+}
+
+impl<'a, 'b> SendRequest<'a, 'b> {
+    /// A request only validating the message if asked to or if the message
+    /// is a dry run.
+    fn new(message: &'b MessageBody<'a>, validate_only: bool) -> SendRequest<'a, 'b> {
+        let validate_only = validate_only || message.dry_run == Some(true);
+
+        SendRequest {
+            message,
+            validate_only: if validate_only { Some(true) } else { None },
+        }
+    }
+}
+
+/// Serialize the message without its `dry_run`, which isn't a field of the
+/// v1 message but sent as `validate_only` of the request.
+fn without_dry_run<S>(message: &&MessageBody<'_>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let mut message = serde_json::to_value(message).map_err(serde::ser::Error::custom)?;
+
+    if let Some(fields) = message.as_object_mut() {
+        fields.remove("dry_run");
+    }
+
+    serde::Serialize::serialize(&message, serializer)
+}
+
+/// The error body of the v1 API, of which the message, the FCM error code
+/// and the field violations in the details are needed.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorStatus,
+}
+
+#[derive(Deserialize)]
+struct ErrorStatus {
+    #[serde(default)]
+    message: String,
+
+    #[serde(default)]
+    details: Vec<ErrorDetail>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorDetail {
+    error_code: Option<String>,
+
+    /// Of a `google.rpc.BadRequest` detail.
+    #[serde(default)]
+    field_violations: Vec<FieldViolation>,
+}
+
+#[derive(Deserialize)]
+struct FieldViolation {
+    field: String,
+    description: String,
+}
+
+/// Why FCM rejected the message, from the error message and the field
+/// violations of a `400 Bad Request` response.
+fn rejection(body: &[u8]) -> String {
+    let response: ErrorResponse = match serde_json::from_slice(body) {
+        Ok(response) => response,
+        Err(_) if body.is_empty() => return "Bad Request".to_string(),
+        Err(_) => return String::from_utf8_lossy(body).into_owned(),
+    };
+
+    let violations: Vec<String> = response
+        .error
+        .details
+        .iter()
+        .flat_map(|detail| &detail.field_violations)
+        .map(|v| format!("{}: {}", v.field, v.description))
+        .collect();
+
+    if violations.is_empty() {
+        response.error.message
+    } else {
+        format!("{} ({})", response.error.message, violations.join("; "))
+    }
+}
+
+/// The FCM error code of a failed v1 send, if its response has one.
+fn error_code(response_status: StatusCode, body: &[u8]) -> Option<String> {
+    if response_status.is_success() {
+        return None;
+    }
+
+    let response: ErrorResponse = serde_json::from_slice(body).ok()?;
+
+    response
+        .error
+        .details
+        .into_iter()
+        .find_map(|detail| detail.error_code)
+}
+
+/// The result of a send, with the FCM error code of its response if any.
+type Sent = (Result<FcmResponse, FcmError>, Option<String>);
+
+/// The event for the error response of a v1 send, if it means the token
+/// should be removed.
+fn removal_event(token: Option<&str>, body: &[u8]) -> Option<TokenEvent> {
+    let response: ErrorResponse = serde_json::from_slice(body).ok()?;
+
+    let reason = response
+        .error
+        .details
+        .iter()
+        .filter_map(|detail| detail.error_code.as_ref())
+        .find_map(|code| RemovalReason::from_error_code(code))?;
+
+    Some(TokenEvent::Remove(token?.to_string(), reason))
+}
+
+/// Read the status, `Retry-After` header and full body of a response.
+async fn read_response(
+    response: hyper::Response<Body>,
+) -> Result<(StatusCode, Option<RetryAfter>, Vec<u8>), FcmError> {
+    let response_status = response.status();
+
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|ra| ra.to_str().ok())
+        .and_then(RetryAfter::from_str);
+
+    let content_length: usize = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|s| s.to_str().ok())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    let mut body: Vec<u8> = Vec::with_capacity(content_length);
+    let mut chunks = response.into_body();
+
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+
+    Ok((response_status, retry_after, body))
+}
+
+/// The default FCM endpoint, used for sending messages and managing device
+/// groups.
+pub const FCM_URL: &str = "https://fcm.googleapis.com";
+
+/// The default Instance ID API endpoint, used for topic management.
+pub const IID_URL: &str = "https://iid.googleapis.com";
+
+/// A builder to get a `Client` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::ClientBuilder;
+///
+/// let mut builder = ClientBuilder::new("<project id>".to_string());
+/// builder.iid_url("http://localhost:8080");
+/// let client = builder.finalize();
+/// ```
+pub struct ClientBuilder {
+    app_id: String,
+    fcm_url: String,
+    iid_url: String,
+    token_feedback: Option<Box<dyn TokenFeedback>>,
+    token_store: Option<Box<dyn TokenStore>>,
+    transport: Option<Box<dyn Transport>>,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    validate: bool,
+    validate_only: bool,
+}
+
+impl ClientBuilder {
+    /// Get a new `ClientBuilder` for the given Firebase project id.
+    pub fn new(app_id: String) -> ClientBuilder {
+        ClientBuilder {
+            app_id,
+            fcm_url: FCM_URL.to_string(),
+            iid_url: IID_URL.to_string(),
+            token_feedback: None,
+            token_store: None,
+            transport: None,
+            rate_limiter: None,
+            circuit_breaker: None,
+            validate: false,
+            validate_only: false,
+        }
+    }
+
+    /// The scheme and host of the FCM API, defaults to `FCM_URL`.
+    pub fn fcm_url(&mut self, fcm_url: &str) -> &mut Self {
+        self.fcm_url = fcm_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The scheme and host of the Instance ID API, defaults to `IID_URL`.
+    pub fn iid_url(&mut self, iid_url: &str) -> &mut Self {
+        self.iid_url = iid_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// A hook to be told about registration tokens FCM reported as no
+    /// longer valid.
+    pub fn token_feedback<F>(&mut self, token_feedback: F) -> &mut Self
+    where
+        F: TokenFeedback + 'static,
+    {
+        self.token_feedback = Some(Box::new(token_feedback));
+        self
+    }
+
+    /// The store to resolve the tokens of `send_to_user` from. Tokens FCM
+    /// reports as unregistered are removed from it after any send.
+    pub fn token_store<S>(&mut self, token_store: S) -> &mut Self
+    where
+        S: TokenStore + 'static,
+    {
+        self.token_store = Some(Box::new(token_store));
+        self
+    }
+
+    /// The HTTP layer to send requests through, defaults to a
+    /// `HyperTransport`.
+    pub fn transport<T>(&mut self, transport: T) -> &mut Self
+    where
+        T: Transport + 'static,
+    {
+        self.transport = Some(Box::new(transport));
+        self
+    }
+
+    /// Wrap the transport in a tower `Layer`, e.g. for timeouts or to sign
+    /// requests. Layers added later see the requests first.
+    #[cfg(feature = "tower")]
+    pub fn layer<L>(&mut self, layer: L) -> &mut Self
+    where
+        L: tower::Layer<TransportService>,
+        L::Service: tower::Service<Request<Body>, Response = hyper::Response<Body>>
+            + Clone
+            + Send
+            + 'static,
+        <L::Service as tower::Service<Request<Body>>>::Error: Into<tower::BoxError>,
+        <L::Service as tower::Service<Request<Body>>>::Future: Send + 'static,
+    {
+        let transport = self
+            .transport
+            .take()
+            .unwrap_or_else(|| Box::new(HyperTransport::new()));
+
+        let service = layer.layer(TransportService::new(transport));
+        self.transport = Some(Box::new(ServiceTransport::new(service)));
+        self
+    }
+
+    /// Limit the rate of sends, see `RateLimiterBuilder`. Unlimited by
+    /// default.
+    pub fn rate_limiter(&mut self, rate_limiter: RateLimiter) -> &mut Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Stop sending during FCM outages, see `CircuitBreaker`. Off by
+    /// default.
+    pub fn circuit_breaker(&mut self, circuit_breaker: CircuitBreaker) -> &mut Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Run `Message::validate` before each send, failing with
+    /// `FcmError::ValidationFailed` instead of sending an invalid message.
+    /// Off by default.
+    pub fn validate(&mut self, validate: bool) -> &mut Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Only have FCM validate the messages instead of delivering them, as
+    /// if every message was a dry run. For checking a whole campaign against
+    /// the API before launching it. Off by default.
+    pub fn validate_only(&mut self, validate_only: bool) -> &mut Self {
+        self.validate_only = validate_only;
+        self
+    }
+
+    /// Complete the build and get a `Client` instance
+    pub fn finalize(self) -> Client {
+        Client {
+            app_id: self.app_id,
+            fcm_url: self.fcm_url,
+            iid_url: self.iid_url,
+            token_feedback: self.token_feedback.map(Arc::from),
+            token_store: self.token_store.map(Arc::from),
+            rate_limiter: self.rate_limiter,
+            circuit_breaker: self.circuit_breaker,
+            validate: self.validate,
+            validate_only: self.validate_only,
+            transport: self
+                .transport
+                .map(Arc::from)
+                .unwrap_or_else(|| Arc::new(HyperTransport::new()) as Arc<dyn Transport>),
+        }
+    }
+}
+
+/// An async client for sending the notification payload. Clones share the
+/// transport, token feedback and token store.
+///
+/// With the `metrics` feature, sends are recorded with the `metrics` crate
+/// as `fcm_messages_sent_total`, `fcm_request_duration_seconds`,
+/// `fcm_payload_size_bytes`, `fcm_requests_in_flight` and
+/// `fcm_messages_throttled_total`, labeled by project id and message
+/// target.
+#[derive(Clone)]
 pub struct Client {
     app_id: String,
-    http_client: HttpClient<HttpsConnector<HttpConnector>>,
+    fcm_url: String,
+    iid_url: String,
+    token_feedback: Option<Arc<dyn TokenFeedback>>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    validate: bool,
+    validate_only: bool,
+    transport: Arc<dyn Transport>,
 }
 
 impl Client {
     /// Get a new instance of Client.
     pub fn new(app_id: String) -> Client {
-        let mut http_client = HttpClient::builder();
-        http_client.pool_max_idle_per_host(std::usize::MAX);
-
-        Client {
-            app_id,
-            http_client: http_client.build(HttpsConnector::new()),
-        }
+        ClientBuilder::new(app_id).finalize()
     }
 
     /// Try sending a `Message` to FCM.
     pub async fn send(&self, message: Message<'_>) -> Result<FcmResponse, FcmError> {
-        let payload = serde_json::to_vec(&message.body).unwrap();
+        self.send_message(message, self.validate_only).await
+    }
+
+    /// Have FCM validate the message without delivering it, failing as
+    /// `send` would if FCM rejects it. The error then has the reason FCM
+    /// gave, including the fields it found invalid, and the response has
+    /// the `name` of the fake message FCM answered with.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::{ClientBuilder, MessageBuilder, MockTransport};
+    /// use hyper::StatusCode;
+    /// use std::sync::Arc;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let transport = Arc::new(MockTransport::new());
+    /// transport.push_json(StatusCode::OK, serde_json::json!({"name": "projects/p/messages/fake_message_id"}));
+    ///
+    /// let mut builder = ClientBuilder::new("p".to_string());
+    /// builder.transport(transport.clone());
+    /// let client = builder.finalize();
+    ///
+    /// client.validate(MessageBuilder::new("<access token>", "<registration id>").finalize()).await.unwrap();
+    ///
+    /// assert_eq!(true, transport.requests()[0].json()["validate_only"]);
+    /// # }
+    /// ```
+    pub async fn validate(&self, message: Message<'_>) -> Result<FcmResponse, FcmError> {
+        self.send_message(message, true).await
+    }
+
+    /// Deliver the message within its tracing span, recording its metrics.
+    async fn send_message(
+        &self,
+        message: Message<'_>,
+        validate_only: bool,
+    ) -> Result<FcmResponse, FcmError> {
+        #[cfg(feature = "metrics")]
+        let target = message.body.target_kind();
+
+        #[cfg(feature = "tracing")]
+        let span = trace::send_span(&self.app_id, &message.body);
+
+        let sending = self.deliver(message, validate_only);
+
+        #[cfg(feature = "tracing")]
+        let sending = tracing::Instrument::instrument(sending, span.clone());
+
+        let sent = sending.await;
+
+        #[cfg(feature = "tracing")]
+        trace::record_outcome(&span, &sent.0);
+
+        #[cfg(feature = "metrics")]
+        instrument::record_send(&self.app_id, target, &sent.0, sent.1.as_deref());
+
+        sent.0
+    }
+
+    /// Validate the message and apply the rate limiter and circuit breaker
+    /// before sending it.
+    async fn deliver(
+        &self,
+        message: Message<'_>,
+        validate_only: bool,
+    ) -> Sent {
+        if let Err(e) = self.admit(&message).await {
+            return (Err(e), None);
+        }
+
+        let sent = self.post_message(&message, validate_only).await;
+
+        if let Some(ref circuit_breaker) = self.circuit_breaker {
+            circuit_breaker.record(&sent.0);
+        }
+
+        sent
+    }
+
+    /// Validate the message if configured to, then wait for its turn with the
+    /// circuit breaker and rate limiter.
+    async fn admit(&self, message: &Message<'_>) -> Result<(), FcmError> {
+        if self.validate {
+            message.validate().map_err(FcmError::ValidationFailed)?;
+        }
+
+        // Fail fast while the circuit is open, without taking the rate
+        // limit or waiting for it.
+        if let Some(ref circuit_breaker) = self.circuit_breaker {
+            circuit_breaker.acquire()?;
+        }
+
+        if let Some(ref rate_limiter) = self.rate_limiter {
+            let token = message.body.token.as_deref();
+            let topic = message.body.topic.as_deref();
+
+            match rate_limiter.acquire(token, topic) {
+                Ok(wait) if wait > std::time::Duration::from_secs(0) => {
+                    #[cfg(feature = "metrics")]
+                    instrument::record_throttled(&self.app_id, "delayed");
+
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(delay = ?wait, "delaying the send for the rate limit");
+
+                    tokio::time::sleep(wait).await
+                }
+                Ok(_) => (),
+                Err(wait) => {
+                    #[cfg(feature = "metrics")]
+                    instrument::record_throttled(&self.app_id, "rejected");
+
+                    let retry_after = Duration::from_std(wait).ok().map(RetryAfter::Delay);
+                    return Err(FcmError::RateLimited(retry_after));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send the message to the v1 API and handle the response.
+    async fn post_message(&self, message: &Message<'_>, validate_only: bool) -> Sent {
+        let payload = serde_json::to_vec(&SendRequest::new(&message.body, validate_only)).unwrap();
 
         let builder = Request::builder()
             .method("POST")
@@ -42,37 +521,64 @@ impl Client {
                 format!("{}", payload.len() as u64).as_bytes(),
             )
             .header(AUTHORIZATION, format!("Bearer {}", message.access_token).as_bytes())
-            .uri(format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", self.app_id));
+            .uri(format!("{}/v1/projects/{}/messages:send", self.fcm_url, self.app_id));
+
+        #[cfg(feature = "metrics")]
+        let (payload_size, started, in_flight) = (
+            payload.len(),
+            std::time::Instant::now(),
+            instrument::InFlight::start(&self.app_id),
+        );
 
         let request = builder.body(Body::from(payload)).unwrap();
-        let requesting = self.http_client.request(request);
+        let executed = self.execute(request).await;
 
-        let response = requesting.await?;
-        let response_status = response.status();
-
-        let retry_after = response
-            .headers()
-            .get(RETRY_AFTER)
-            .and_then(|ra| ra.to_str().ok())
-            .and_then(|ra| RetryAfter::from_str(ra));
-
-        let content_length: usize = response
-            .headers()
-            .get(CONTENT_LENGTH)
-            .and_then(|s| s.to_str().ok())
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
-
-        let mut body: Vec<u8> = Vec::with_capacity(content_length);
-        let mut chunks = response.into_body();
-
-        while let Some(chunk) = chunks.next().await {
-            body.extend_from_slice(&chunk?);
+        #[cfg(feature = "metrics")]
+        {
+            drop(in_flight);
+
+            let target = message.body.target_kind();
+            instrument::record_request(&self.app_id, target, payload_size, started.elapsed());
+        }
+
+        let (response_status, retry_after, body) = match executed {
+            Ok(response) => response,
+            Err(e) => return (Err(e), None),
+        };
+
+        let error_code = error_code(response_status, &body);
+
+        #[cfg(feature = "tracing")]
+        trace::record_response(response_status, error_code.as_deref());
+
+        let result = self.send_result(message, response_status, retry_after, &body);
+
+        (result, error_code)
+    }
+
+    /// The result of sending the message, given the response FCM answered
+    /// with. Reports tokens FCM no longer accepts.
+    fn send_result(
+        &self,
+        message: &Message<'_>,
+        response_status: StatusCode,
+        retry_after: Option<RetryAfter>,
+        body: &[u8],
+    ) -> Result<FcmResponse, FcmError> {
+        if !response_status.is_success() {
+            if let Some(event) = removal_event(message.body.token.as_deref(), body) {
+                dispatch(
+                    self.token_feedback.as_deref(),
+                    self.token_store.as_deref(),
+                    event,
+                );
+            }
         }
 
         match response_status {
             StatusCode::OK => {
-                let fcm_response: FcmResponse = serde_json::from_slice(&body).unwrap();
+                let fcm_response: FcmResponse = serde_json::from_slice(body)
+                    .map_err(|e| FcmError::InvalidMessage(e.to_string()))?;
 
                 match fcm_response.error {
                     Some(ErrorReason::Unavailable) => {
@@ -85,9 +591,9 @@ impl Client {
                 }
             }
             StatusCode::UNAUTHORIZED => Err(response::FcmError::Unauthorized),
-            StatusCode::BAD_REQUEST => Err(response::FcmError::InvalidMessage(
-                "Bad Request".to_string(),
-            )),
+            StatusCode::NOT_FOUND => Err(response::FcmError::NotFound),
+            StatusCode::BAD_REQUEST => Err(response::FcmError::InvalidMessage(rejection(body))),
+            StatusCode::TOO_MANY_REQUESTS => Err(response::FcmError::RateLimited(retry_after)),
             status if status.is_server_error() => {
                 Err(response::FcmError::ServerError(retry_after))
             }
@@ -96,4 +602,16 @@ impl Client {
             )),
         }
     }
+
+    /// Send the request, returning the response status, its `Retry-After`
+    /// header and the full body.
+    async fn execute(
+        &self,
+        request: Request<Body>,
+    ) -> Result<(StatusCode, Option<RetryAfter>, Vec<u8>), FcmError> {
+        let requesting = self.transport.request(request);
+
+        let response = requesting.await?;
+        read_response(response).await
+    }
 }
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Above this many per-token or per-topic buckets, the ones that have
+/// filled up again are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// A token bucket allowing `per_second` sends a second on average, with
+/// bursts of up to `burst` sends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Panics if `per_second` isn't positive or `burst` is zero.
+    pub fn new(per_second: f64, burst: u32) -> RateLimit {
+        assert!(per_second > 0.0, "the rate must be positive");
+        assert!(burst > 0, "the burst must be at least one");
+
+        RateLimit { per_second, burst }
+    }
+}
+
+/// Counts of the sends that went through a `RateLimiter`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ThrottleStats {
+    /// Sends allowed, including delayed ones.
+    pub allowed: u64,
+    /// Sends that had to wait before being allowed.
+    pub delayed: u64,
+    /// Sends rejected with `FcmError::RateLimited`.
+    pub rejected: u64,
+    /// The total time delayed sends waited.
+    pub total_delay: Duration,
+}
+
+/// A builder to get a `RateLimiter`, limiting all sends of a `Client`,
+/// the sends to each registration token and the sends to each topic.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{ClientBuilder, RateLimit, RateLimiterBuilder};
+/// use std::time::Duration;
+///
+/// let mut limiter = RateLimiterBuilder::new();
+/// limiter.global(RateLimit::new(500.0, 100));
+/// limiter.per_token(RateLimit::new(1.0, 20));
+/// limiter.max_delay(Duration::from_secs(2));
+/// let limiter = limiter.finalize();
+///
+/// let mut builder = ClientBuilder::new("<project id>".to_string());
+/// builder.rate_limiter(limiter.clone());
+/// let client = builder.finalize();
+///
+/// assert_eq!(0, limiter.stats().rejected);
+/// ```
+#[derive(Debug, Default)]
+pub struct RateLimiterBuilder {
+    global: Option<RateLimit>,
+    per_token: Option<RateLimit>,
+    per_topic: Option<RateLimit>,
+    max_delay: Duration,
+}
+
+impl RateLimiterBuilder {
+    pub fn new() -> RateLimiterBuilder {
+        RateLimiterBuilder::default()
+    }
+
+    /// The limit of all sends.
+    pub fn global(&mut self, limit: RateLimit) -> &mut Self {
+        self.global = Some(limit);
+        self
+    }
+
+    /// The limit of sends to each registration token.
+    pub fn per_token(&mut self, limit: RateLimit) -> &mut Self {
+        self.per_token = Some(limit);
+        self
+    }
+
+    /// The limit of sends to each topic.
+    pub fn per_topic(&mut self, limit: RateLimit) -> &mut Self {
+        self.per_topic = Some(limit);
+        self
+    }
+
+    /// How long a send over the limit may wait for its turn. Sends that
+    /// would have to wait longer are rejected. Defaults to zero, rejecting
+    /// every send over the limit.
+    pub fn max_delay(&mut self, max_delay: Duration) -> &mut Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Complete the build and get a `RateLimiter` instance
+    pub fn finalize(self) -> RateLimiter {
+        RateLimiter {
+            global: self.global,
+            per_token: self.per_token,
+            per_topic: self.per_topic,
+            max_delay: self.max_delay,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Bucket {
+        Bucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.tokens >= f64::from(limit.burst)
+    }
+
+    /// How long until a token is available.
+    fn wait(&self, limit: &RateLimit) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second)
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    global: Option<Bucket>,
+    tokens: HashMap<String, Bucket>,
+    topics: HashMap<String, Bucket>,
+    stats: ThrottleStats,
+}
+
+/// Limits the rate of sends before they reach FCM, delaying them for up to
+/// the configured maximum and rejecting them with `FcmError::RateLimited`
+/// when that's not enough. Clones share their buckets and stats.
+#[derive(Clone)]
+pub struct RateLimiter {
+    global: Option<RateLimit>,
+    per_token: Option<RateLimit>,
+    per_topic: Option<RateLimit>,
+    max_delay: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimiter {
+    /// The counts of sends so far.
+    pub fn stats(&self) -> ThrottleStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Take a turn for a send to the token or topic, returning how long to
+    /// wait before sending or, if that's over the maximum delay, how long
+    /// it would have been.
+    pub(crate) fn acquire(
+        &self,
+        token: Option<&str>,
+        topic: Option<&str>,
+    ) -> Result<Duration, Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let mut buckets = Vec::with_capacity(3);
+
+        if let Some(ref limit) = self.global {
+            let bucket = state.global.get_or_insert_with(|| Bucket::full(limit, now));
+
+            buckets.push((limit, bucket));
+        }
+
+        if let (Some(limit), Some(token)) = (&self.per_token, token) {
+            buckets.push((limit, keyed_bucket(&mut state.tokens, limit, token, now)));
+        }
+
+        if let (Some(limit), Some(topic)) = (&self.per_topic, topic) {
+            let topic = topic.trim_start_matches("/topics/");
+            buckets.push((limit, keyed_bucket(&mut state.topics, limit, topic, now)));
+        }
+
+        let mut wait = Duration::from_secs(0);
+
+        for (limit, bucket) in buckets.iter_mut() {
+            bucket.refill(limit, now);
+            wait = wait.max(bucket.wait(limit));
+        }
+
+        if wait > self.max_delay {
+            state.stats.rejected += 1;
+            return Err(wait);
+        }
+
+        // Taking a token from an empty bucket reserves the next one, so
+        // sends waiting at the same time go in turn.
+        for (_, bucket) in buckets {
+            bucket.tokens -= 1.0;
+        }
+
+        state.stats.allowed += 1;
+
+        if wait > Duration::from_secs(0) {
+            state.stats.delayed += 1;
+            state.stats.total_delay += wait;
+        }
+
+        Ok(wait)
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("global", &self.global)
+            .field("per_token", &self.per_token)
+            .field("per_topic", &self.per_topic)
+            .field("max_delay", &self.max_delay)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+fn keyed_bucket<'a>(
+    buckets: &'a mut HashMap<String, Bucket>,
+    limit: &RateLimit,
+    key: &str,
+    now: Instant,
+) -> &'a mut Bucket {
+    if buckets.len() > PRUNE_THRESHOLD {
+        buckets.retain(|_, bucket| {
+            bucket.refill(limit, now);
+            !bucket.is_full(limit)
+        });
+    }
+
+    buckets
+        .entry(key.to_string())
+        .or_insert_with(|| Bucket::full(limit, now))
+}
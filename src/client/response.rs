@@ -1,4 +1,7 @@
 pub use chrono::{DateTime, Duration, FixedOffset};
+use crate::client::{DeviceGroupError, TransportError};
+use crate::message::Violation;
+use crate::store::TokenStoreError;
 use std::error::Error;
 use std::fmt;
 
@@ -93,6 +96,10 @@ pub enum ErrorReason {
 
 #[derive(Deserialize, Debug)]
 pub struct FcmResponse {
+    /// The resource name of the message the v1 API answers with, e.g.
+    /// `projects/<project id>/messages/<message id>`.
+    pub name: Option<String>,
+
     pub message_id: Option<u64>,
     pub error: Option<ErrorReason>,
     pub multicast_id: Option<i64>,
@@ -111,7 +118,7 @@ pub struct MessageResult {
 
 /// Fatal errors. Referred from [Firebase
 /// documentation](https://firebase.google.com/docs/cloud-messaging/http-server-ref#table9)
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum FcmError {
     /// The sender account used to send a message couldn't be authenticated. Possible causes are:
     ///
@@ -144,9 +151,43 @@ pub enum FcmError {
     ///
     /// Senders that cause problems risk being blacklisted.
     ServerError(Option<RetryAfter>),
+
+    /// The registration token is not known to FCM. It was either never valid,
+    /// or the app instance it belonged to has been deleted.
+    NotFound,
+
+    /// A device group operation was rejected.
+    DeviceGroup(DeviceGroupError),
+
+    /// The `TokenStore` couldn't be read, or none was configured.
+    TokenStore(TokenStoreError),
+
+    /// The message failed `Message::validate` and wasn't sent.
+    ValidationFailed(Vec<Violation>),
+
+    /// Too many messages were sent to the project, device or topic, either
+    /// according to FCM or to the `RateLimiter` of the client. Retry after
+    /// the [RetryAfter](enum.RetryAfter.html) value if included.
+    RateLimited(Option<RetryAfter>),
+
+    /// The `CircuitBreaker` of the client is open after repeated server
+    /// errors, so the message wasn't sent. Includes how long until it lets
+    /// a send through again, if known.
+    CircuitOpen(Option<RetryAfter>),
+
+    /// The request couldn't be sent or its response not received, e.g. the
+    /// connection was refused or reset. Safe to retry with back-off.
+    Transport(TransportError),
 }
 
-impl Error for FcmError {}
+impl Error for FcmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FcmError::Transport(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for FcmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -157,17 +198,42 @@ impl fmt::Display for FcmError {
             ),
             FcmError::InvalidMessage(ref s) => write!(f, "invalid message {}", s),
             FcmError::ServerError(_) => write!(f, "the server couldn't process the request"),
+            FcmError::NotFound => write!(f, "the registration token was not found"),
+            FcmError::DeviceGroup(ref e) => write!(f, "device group operation failed: {}", e),
+            FcmError::TokenStore(ref e) => write!(f, "{}", e),
+            FcmError::ValidationFailed(ref violations) => {
+                write!(f, "invalid message")?;
+
+                for (i, violation) in violations.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, violation)?;
+                }
+
+                Ok(())
+            }
+            FcmError::RateLimited(_) => write!(f, "the message rate limit was exceeded"),
+            FcmError::CircuitOpen(_) => {
+                write!(f, "not sending while FCM is failing, the circuit is open")
+            }
+            FcmError::Transport(_) => {
+                write!(f, "the request couldn't be sent or its response not received")
+            }
         }
     }
 }
 
 impl From<hyper::Error> for FcmError {
-    fn from(_: hyper::Error) -> Self {
-        Self::ServerError(None)
+    fn from(e: hyper::Error) -> Self {
+        Self::Transport(e.into())
+    }
+}
+
+impl From<TransportError> for FcmError {
+    fn from(e: TransportError) -> Self {
+        Self::Transport(e)
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum RetryAfter {
     /// Amount of time to wait until retrying the message is allowed.
     Delay(Duration),
@@ -177,12 +243,13 @@ pub enum RetryAfter {
 }
 
 impl RetryAfter {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(header_value: &str) -> Option<RetryAfter> {
         if let Ok(seconds) = header_value.parse::<i64>() {
             Some(RetryAfter::Delay(Duration::seconds(seconds)))
         } else {
             DateTime::parse_from_rfc2822(header_value)
-                .map(|date_time| RetryAfter::DateTime(date_time))
+                .map(RetryAfter::DateTime)
                 .ok()
         }
     }
@@ -231,7 +298,7 @@ mod tests {
             let fcm_response: FcmResponse = serde_json::from_str(&response_string).unwrap();
 
             assert_eq!(
-                Some(error_enum.clone()),
+                Some(error_enum),
                 fcm_response.results.unwrap()[0].error,
             );
 
@@ -0,0 +1,142 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::future::{poll_fn, BoxFuture, FutureExt};
+use hyper::{Body, Request, Response};
+use tower::{BoxError, Service};
+
+use crate::client::{Client, FcmError, FcmResponse, Transport, TransportError};
+use crate::message::Message;
+
+/// Sends messages, so a `Client` can be wrapped in tower middleware. Only
+/// owned messages can be sent, see `Message::into_owned`.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{ClientBuilder, MessageBuilder, MockTransport};
+/// use hyper::StatusCode;
+/// use std::sync::Arc;
+/// use tower::ServiceExt;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let transport = Arc::new(MockTransport::new());
+/// transport.push_json(StatusCode::OK, serde_json::json!({"name": "projects/p/messages/1"}));
+///
+/// let mut builder = ClientBuilder::new("p".to_string());
+/// builder.transport(transport);
+/// let client = builder.finalize();
+///
+/// let message = MessageBuilder::new("<access token>", "<registration id>").finalize();
+/// let response = client.oneshot(message.into_owned()).await.unwrap();
+///
+/// assert!(response.error.is_none());
+/// # }
+/// ```
+impl Service<Message<'static>> for Client {
+    type Response = FcmResponse;
+    type Error = FcmError;
+    type Future = BoxFuture<'static, Result<FcmResponse, FcmError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), FcmError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: Message<'static>) -> Self::Future {
+        let client = self.clone();
+        async move { client.send(message).await }.boxed()
+    }
+}
+
+/// A `Transport` as a tower `Service`, the innermost service of the stack
+/// `ClientBuilder::layer` builds.
+#[derive(Clone)]
+pub struct TransportService {
+    transport: Arc<dyn Transport>,
+}
+
+impl TransportService {
+    pub fn new<T>(transport: T) -> TransportService
+    where
+        T: Transport + 'static,
+    {
+        TransportService {
+            transport: Arc::new(transport),
+        }
+    }
+}
+
+impl Service<Request<Body>> for TransportService {
+    type Response = Response<Body>;
+    type Error = TransportError;
+    type Future = BoxFuture<'static, Result<Response<Body>, TransportError>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), TransportError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let transport = self.transport.clone();
+        async move { transport.request(request).await }.boxed()
+    }
+}
+
+/// A tower `Service` of HTTP requests as a `Transport`. Each request is
+/// sent through a clone of the service. Its errors become a
+/// `TransportError`.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{ClientBuilder, HyperTransport, ServiceTransport, TransportService};
+/// use std::time::Duration;
+/// use tower::ServiceBuilder;
+///
+/// let service = ServiceBuilder::new()
+///     .map_request(|mut request: hyper::Request<hyper::Body>| {
+///         request.headers_mut().insert("x-goog-user-project", "my-project".parse().unwrap());
+///         request
+///     })
+///     .service(TransportService::new(HyperTransport::new()));
+///
+/// let mut builder = ClientBuilder::new("my-project".to_string());
+/// builder.transport(ServiceTransport::new(service));
+/// let client = builder.finalize();
+/// ```
+pub struct ServiceTransport<S> {
+    service: Mutex<S>,
+}
+
+impl<S> ServiceTransport<S> {
+    pub fn new(service: S) -> ServiceTransport<S> {
+        ServiceTransport {
+            service: Mutex::new(service),
+        }
+    }
+}
+
+impl<S> Transport for ServiceTransport<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>> {
+        let mut service = self.service.lock().unwrap().clone();
+
+        async move {
+            poll_fn(|cx| service.poll_ready(cx))
+                .await
+                .map_err(transport_error)?;
+
+            service.call(request).await.map_err(transport_error)
+        }
+        .boxed()
+    }
+}
+
+fn transport_error<E: Into<BoxError>>(e: E) -> TransportError {
+    TransportError::new(e.into())
+}
@@ -0,0 +1,1368 @@
+use crate::testing;
+use crate::{
+    ApnsImportError, ApnsTokenImportResult, CircuitBreakerBuilder, CircuitState, Client,
+    ClientBuilder, DeviceGroupError, FcmError, MemoryTokenStore, MessageBuilder, MockTransport,
+    Platform, RateLimit, RateLimiterBuilder, RemovalReason, RetryAfter, ThrottleStats, TokenEvent,
+    TokenStore, TopicManagementError, TopicManagementResult, TopicSubscription, TransportError,
+};
+use hyper::{Body, Response, StatusCode};
+use std::sync::{Arc, Mutex};
+
+fn client(addr: std::net::SocketAddr) -> Client {
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.iid_url(&format!("http://{}", addr));
+    builder.finalize()
+}
+
+#[tokio::test]
+async fn should_subscribe_tokens_to_a_topic_in_batches() {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let addr = {
+        let requests = requests.clone();
+
+        testing::serve(move |parts, body| {
+            assert_eq!("/iid/v1:batchAdd", parts.uri.path());
+            assert_eq!("Bearer token", parts.headers["authorization"]);
+            assert_eq!("true", parts.headers["access_token_auth"]);
+
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let tokens = body["registration_tokens"].as_array().unwrap();
+
+            let results: Vec<_> = tokens
+                .iter()
+                .map(|t| match t.as_str().unwrap() {
+                    "gone" => json!({"error": "NOT_FOUND"}),
+                    "full" => json!({"error": "TOO_MANY_TOPICS"}),
+                    _ => json!({}),
+                })
+                .collect();
+
+            requests
+                .lock()
+                .unwrap()
+                .push((body["to"].clone(), tokens.len()));
+
+            Response::new(Body::from(json!({ "results": results }).to_string()))
+        })
+    };
+
+    let mut tokens: Vec<String> = (0..1500).map(|i| format!("token{}", i)).collect();
+    tokens[3] = "gone".to_string();
+    tokens[1200] = "full".to_string();
+
+    let results = client(addr)
+        .subscribe_to_topic("token", "news", &tokens)
+        .await
+        .unwrap();
+
+    assert_eq!(1500, results.len());
+    assert_eq!(
+        TopicManagementResult {
+            token: "gone".to_string(),
+            error: Some(TopicManagementError::NotFound),
+        },
+        results[3]
+    );
+    assert_eq!(
+        Some(TopicManagementError::TooManyTopics),
+        results[1200].error
+    );
+    assert_eq!(2, results.iter().filter(|r| r.error.is_some()).count());
+
+    assert_eq!(
+        vec![(json!("/topics/news"), 1000), (json!("/topics/news"), 500)],
+        *requests.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn should_unsubscribe_tokens_from_a_topic() {
+    let addr = testing::serve(|parts, _| {
+        assert_eq!("/iid/v1:batchRemove", parts.uri.path());
+        Response::new(Body::from(r#"{"results":[{"error":"INVALID_ARGUMENT"}]}"#))
+    });
+
+    let results = client(addr)
+        .unsubscribe_from_topic("token", "/topics/news", &["bad"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Some(TopicManagementError::InvalidArgument),
+        results[0].error
+    );
+}
+
+#[tokio::test]
+async fn should_fail_topic_management_when_unauthorized() {
+    let addr = testing::serve(|_, _| {
+        Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap()
+    });
+
+    let result = client(addr)
+        .subscribe_to_topic("token", "news", &["token"])
+        .await;
+
+    assert_eq!(Err(FcmError::Unauthorized), result);
+}
+
+#[tokio::test]
+async fn should_fail_topic_management_without_a_result_per_token() {
+    let addr = testing::serve(|_, _| Response::new(Body::from(r#"{"results":[{}]}"#)));
+
+    let result = client(addr)
+        .subscribe_to_topic("token", "news", &["token1", "token2"])
+        .await;
+
+    assert_eq!(
+        Err(FcmError::InvalidMessage("expected 2 results, got 1".to_string())),
+        result
+    );
+}
+
+#[tokio::test]
+async fn should_report_throttled_topic_management_as_rate_limited() {
+    let addr = testing::serve(|_, _| {
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header("retry-after", "30")
+            .body(Body::empty())
+            .unwrap()
+    });
+
+    let result = client(addr)
+        .subscribe_to_topic("token", "news", &["token"])
+        .await;
+
+    assert_eq!(
+        Err(FcmError::RateLimited(Some(RetryAfter::Delay(
+            chrono::Duration::seconds(30)
+        )))),
+        result
+    );
+}
+
+#[tokio::test]
+async fn should_look_up_token_info() {
+    let addr = testing::serve(|parts, _| {
+        assert_eq!("GET", parts.method);
+        assert_eq!("/iid/info/token1", parts.uri.path());
+        assert_eq!(Some("details=true"), parts.uri.query());
+
+        Response::new(Body::from(
+            json!({
+                "application": "com.example.app",
+                "subtype": "wp:https://example.com/",
+                "applicationVersion": "42",
+                "authorizedEntity": "123456789",
+                "platform": "ANDROID",
+                "rel": {
+                    "topics": {
+                        "news": {"addDate": "2019-02-15"}
+                    }
+                }
+            })
+            .to_string(),
+        ))
+    });
+
+    let info = client(addr).token_info("token", "token1").await.unwrap();
+
+    assert_eq!(Some("com.example.app".to_string()), info.application);
+    assert_eq!(Some("42".to_string()), info.application_version);
+    assert_eq!(Some("123456789".to_string()), info.authorized_entity);
+    assert_eq!(Some(Platform::Android), info.platform);
+    assert_eq!(
+        Some(&TopicSubscription {
+            add_date: Some("2019-02-15".to_string())
+        }),
+        info.rel.topics.get("news")
+    );
+}
+
+#[tokio::test]
+async fn should_distinguish_unknown_tokens_from_auth_errors() {
+    let addr = testing::serve(|parts, _| {
+        let status = match parts.uri.path() {
+            "/iid/info/unknown" => StatusCode::NOT_FOUND,
+            _ => StatusCode::FORBIDDEN,
+        };
+
+        Response::builder()
+            .status(status)
+            .body(Body::from(
+                r#"{"error":"No information found about this instance id."}"#,
+            ))
+            .unwrap()
+    });
+
+    let client = client(addr);
+
+    assert_eq!(
+        Err(FcmError::NotFound),
+        client.token_info("token", "unknown").await
+    );
+    assert_eq!(
+        Err(FcmError::Unauthorized),
+        client.token_info("bad", "token1").await
+    );
+}
+
+#[tokio::test]
+async fn should_import_apns_tokens_in_batches() {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let addr = {
+        let requests = requests.clone();
+
+        testing::serve(move |parts, body| {
+            assert_eq!("/iid/v1:batchImport", parts.uri.path());
+
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!("com.example.app", body["application"]);
+            assert_eq!(true, body["sandbox"]);
+
+            let tokens = body["apns_tokens"].as_array().unwrap();
+            let results: Vec<_> = tokens
+                .iter()
+                .map(|t| match t.as_str().unwrap() {
+                    "bad" => json!({"apns_token": t, "status": "Invalid apns token"}),
+                    _ => json!({
+                        "apns_token": t,
+                        "status": "OK",
+                        "registration_token": format!("fcm-{}", t.as_str().unwrap())
+                    }),
+                })
+                .collect();
+
+            requests.lock().unwrap().push(tokens.len());
+
+            Response::new(Body::from(json!({ "results": results }).to_string()))
+        })
+    };
+
+    let mut tokens: Vec<String> = (0..150).map(|i| format!("apns{}", i)).collect();
+    tokens[120] = "bad".to_string();
+
+    let results = client(addr)
+        .import_apns_tokens("token", "com.example.app", true, &tokens)
+        .await
+        .unwrap();
+
+    assert_eq!(vec![100, 50], *requests.lock().unwrap());
+    assert_eq!(150, results.len());
+    assert_eq!(
+        ApnsTokenImportResult {
+            apns_token: "apns0".to_string(),
+            registration_token: Some("fcm-apns0".to_string()),
+            error: None,
+        },
+        results[0]
+    );
+    assert_eq!(None, results[120].registration_token);
+    assert_eq!(Some(ApnsImportError::InvalidToken), results[120].error);
+}
+
+#[tokio::test]
+async fn should_keep_unknown_apns_import_statuses() {
+    let addr = testing::serve(|_, _| {
+        Response::new(Body::from(
+            json!({"results": [
+                {"apns_token": "apns0", "status": "Internal Server Error"},
+                {"apns_token": "apns1", "status": "Not an invalid token"}
+            ]})
+            .to_string(),
+        ))
+    });
+
+    let client = client(addr);
+
+    let results = client
+        .import_apns_tokens("token", "com.example.app", false, &["apns0", "apns1"])
+        .await
+        .unwrap();
+
+    assert_eq!(Some(ApnsImportError::Internal), results[0].error);
+    assert_eq!(
+        Some(ApnsImportError::Other("Not an invalid token".to_string())),
+        results[1].error
+    );
+
+    let result = client
+        .import_apns_tokens("token", "com.example.app", false, &["apns0", "apns1", "apns2"])
+        .await;
+
+    assert_eq!(
+        Err(FcmError::InvalidMessage("expected 3 results, got 2".to_string())),
+        result
+    );
+}
+
+#[tokio::test]
+async fn should_manage_device_groups() {
+    let addr = testing::serve(|parts, body| {
+        assert_eq!("/fcm/notification", parts.uri.path());
+        assert_eq!("123456789", parts.headers["project_id"]);
+
+        let (status, response) = if parts.method == "GET" {
+            match parts.uri.query() {
+                Some("notification_key_name=user-42") => {
+                    (200, json!({"notification_key": "APA91"}))
+                }
+                Some("notification_key_name=Tom%20%26%20Jerry") => {
+                    (200, json!({"notification_key": "APA92"}))
+                }
+                _ => (400, json!({"error": "notification_key not found"})),
+            }
+        } else {
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let members = body["registration_ids"].as_array().unwrap().len();
+
+            match (
+                body["operation"].as_str().unwrap(),
+                body["notification_key_name"].as_str().unwrap(),
+            ) {
+                ("create", "taken") => (400, json!({"error": "notification_key already exists"})),
+                (_, _) if members > 20 => {
+                    (400, json!({"error": "maximum number of members reached"}))
+                }
+                ("add", _) | ("remove", _) => {
+                    assert_eq!("APA91", body["notification_key"]);
+                    (200, json!({"notification_key": "APA91"}))
+                }
+                _ => (200, json!({"notification_key": "APA91"})),
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .body(Body::from(response.to_string()))
+            .unwrap()
+    });
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.fcm_url(&format!("http://{}", addr));
+    let client = builder.finalize();
+
+    assert_eq!(
+        Ok("APA91".to_string()),
+        client
+            .create_device_group("token", "123456789", "user-42", &["a", "b"])
+            .await
+    );
+    assert_eq!(
+        Ok("APA91".to_string()),
+        client
+            .add_to_device_group("token", "123456789", "user-42", "APA91", &["c"])
+            .await
+    );
+    assert_eq!(
+        Ok("APA91".to_string()),
+        client
+            .remove_from_device_group("token", "123456789", "user-42", "APA91", &["a"])
+            .await
+    );
+    assert_eq!(
+        Ok("APA91".to_string()),
+        client
+            .device_group_key("token", "123456789", "user-42")
+            .await
+    );
+    assert_eq!(
+        Ok("APA92".to_string()),
+        client
+            .device_group_key("token", "123456789", "Tom & Jerry")
+            .await
+    );
+
+    assert_eq!(
+        Err(FcmError::DeviceGroup(DeviceGroupError::AlreadyExists)),
+        client
+            .create_device_group("token", "123456789", "taken", &["a"])
+            .await
+    );
+    assert_eq!(
+        Err(FcmError::DeviceGroup(DeviceGroupError::NotFound)),
+        client
+            .device_group_key("token", "123456789", "nobody")
+            .await
+    );
+
+    let members: Vec<String> = (0..21).map(|i| format!("token{}", i)).collect();
+    assert_eq!(
+        Err(FcmError::DeviceGroup(DeviceGroupError::TooManyMembers)),
+        client
+            .add_to_device_group("token", "123456789", "user-42", "APA91", &members)
+            .await
+    );
+}
+
+#[tokio::test]
+async fn should_send_a_message_to_a_device_group() {
+    let addr = testing::serve(|parts, body| {
+        assert_eq!("/v1/projects/test-project/messages:send", parts.uri.path());
+
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("APA91", body["message"]["token"]);
+
+        Response::new(Body::from(r#"{"name":"projects/test-project/messages/1"}"#))
+    });
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.fcm_url(&format!("http://{}", addr));
+    let client = builder.finalize();
+
+    let mut message = MessageBuilder::new("token", "device");
+    message.notification_key("APA91");
+
+    assert!(client.send(message.finalize()).await.is_ok());
+}
+
+#[tokio::test]
+async fn should_report_unregistered_tokens() {
+    let addr = testing::serve(|_, body| {
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let error_code = match body["message"]["token"].as_str().unwrap() {
+            "dead" => "UNREGISTERED",
+            _ => "QUOTA_EXCEEDED",
+        };
+
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from(
+                json!({
+                    "error": {
+                        "code": 404,
+                        "message": "Requested entity was not found.",
+                        "status": "NOT_FOUND",
+                        "details": [{
+                            "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                            "errorCode": error_code
+                        }]
+                    }
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    });
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.fcm_url(&format!("http://{}", addr));
+    builder.token_feedback(move |event| sink.lock().unwrap().push(event));
+    let client = builder.finalize();
+
+    for token in &["dead", "alive"] {
+        let result = client
+            .send(MessageBuilder::new("token", *token).finalize())
+            .await;
+
+        assert_eq!(Err(FcmError::NotFound), result.map(|_| ()));
+    }
+
+    assert_eq!(
+        vec![TokenEvent::Remove(
+            "dead".to_string(),
+            RemovalReason::Unregistered
+        )],
+        *events.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn should_send_to_all_tokens_of_a_user_and_prune_unregistered_ones() {
+    let addr = testing::serve(|_, body| {
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(None, body["message"].get("topic"));
+
+        match body["message"]["token"].as_str().unwrap() {
+            "dead" => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(
+                    json!({
+                        "error": {
+                            "code": 404,
+                            "status": "NOT_FOUND",
+                            "details": [{"errorCode": "UNREGISTERED"}]
+                        }
+                    })
+                    .to_string(),
+                ))
+                .unwrap(),
+            _ => Response::new(Body::from(r#"{"name":"projects/test-project/messages/1"}"#)),
+        }
+    });
+
+    let store = Arc::new(MemoryTokenStore::new());
+    store.add_token("alice", "alive").unwrap();
+    store.add_token("alice", "dead").unwrap();
+    store.add_token("bob", "other").unwrap();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.fcm_url(&format!("http://{}", addr));
+    builder.token_store(store.clone());
+    let client = builder.finalize();
+
+    let mut message = MessageBuilder::new("token", "ignored");
+    message.topic("news");
+
+    let results = client
+        .send_to_user("alice", message.finalize())
+        .await
+        .unwrap();
+
+    assert_eq!(2, results.len());
+    assert_eq!("alive", results[0].token);
+    assert!(results[0].result.is_ok());
+    assert_eq!("dead", results[1].token);
+    assert!(matches!(results[1].result, Err(FcmError::NotFound)));
+
+    assert_eq!(vec!["alive"], store.tokens("alice").unwrap());
+    assert!(store.last_success("alive").unwrap().is_some());
+    assert_eq!(vec!["other"], store.tokens("bob").unwrap());
+}
+
+#[tokio::test]
+async fn should_require_a_token_store_to_send_to_a_user() {
+    let client = Client::new("test-project".to_string());
+    let message = MessageBuilder::new("token", "").finalize();
+
+    assert!(matches!(
+        client.send_to_user("alice", message).await,
+        Err(FcmError::TokenStore(_))
+    ));
+}
+
+#[tokio::test]
+async fn should_not_send_invalid_messages_when_validating() {
+    let addr = testing::serve(|_, _| panic!("an invalid message was sent"));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.fcm_url(&format!("http://{}", addr)).validate(true);
+    let client = builder.finalize();
+
+    let mut message = MessageBuilder::new("token", "device");
+    message.topic("bad topic");
+
+    match client.send(message.finalize()).await {
+        Err(FcmError::ValidationFailed(violations)) => {
+            assert_eq!(1, violations.len());
+            assert_eq!("message.topic", violations[0].path);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn should_send_through_a_mock_transport() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(
+        StatusCode::OK,
+        json!({"name": "projects/test-project/messages/1"}),
+    );
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("retry-after", "120")
+            .body(Body::empty())
+            .unwrap(),
+    );
+    transport.push_error(TransportError::new("connection refused"));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    let client = builder.finalize();
+
+    let send = || client.send(MessageBuilder::new("token", "device").finalize());
+
+    assert!(send().await.is_ok());
+    assert_eq!(
+        Err(FcmError::ServerError(Some(RetryAfter::Delay(
+            chrono::Duration::seconds(120)
+        )))),
+        send().await.map(|_| ())
+    );
+    assert_eq!(
+        Err(FcmError::Transport(TransportError::new("connection refused"))),
+        send().await.map(|_| ())
+    );
+    assert_eq!(
+        Err(FcmError::Transport(TransportError::new("no response queued"))),
+        send().await.map(|_| ())
+    );
+
+    let requests = transport.requests();
+
+    assert_eq!(4, requests.len());
+    assert_eq!(hyper::Method::POST, requests[0].method);
+    assert_eq!(
+        "https://fcm.googleapis.com/v1/projects/test-project/messages:send",
+        requests[0].uri.to_string()
+    );
+    assert_eq!("Bearer token", requests[0].headers["authorization"]);
+    assert_eq!(json!({"message": {"token": "device"}}), requests[0].json());
+}
+
+#[tokio::test]
+async fn should_redact_captured_requests() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    let client = builder.finalize();
+
+    client
+        .send(MessageBuilder::new("secret-access-token", "abcdefghijklmnopqrstuvwxyz").finalize())
+        .await
+        .unwrap();
+
+    let output = format!("{:?}", transport);
+
+    assert!(output.contains("\"authorization\": <redacted>"), "{}", output);
+    assert!(!output.contains("secret-access-token"), "{}", output);
+    assert!(!output.contains("abcdefghijklmnopqrstuvwxyz"), "{}", output);
+
+    let output = format!("{:?}", crate::Unredacted(&transport.requests()[0]));
+
+    assert!(output.contains("secret-access-token"), "{}", output);
+    assert!(output.contains("abcdefghijklmnopqrstuvwxyz"), "{}", output);
+}
+
+#[test]
+fn should_keep_transport_errors_as_the_source() {
+    use std::error::Error;
+    use std::io;
+
+    let error = FcmError::from(TransportError::new(io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        "connection refused",
+    )));
+
+    let transport_error = error.source().unwrap();
+    let cause = transport_error.source().unwrap();
+
+    assert!(!error.to_string().contains("connection refused"));
+    assert_eq!("transport error", transport_error.to_string());
+    assert_eq!(
+        Some(io::ErrorKind::ConnectionRefused),
+        cause.downcast_ref::<io::Error>().map(io::Error::kind)
+    );
+    assert!(FcmError::NotFound.source().is_none());
+}
+
+#[tokio::test]
+async fn should_reject_sends_over_the_rate_limit() {
+    let transport = Arc::new(MockTransport::new());
+    for _ in 0..4 {
+        transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+    }
+
+    let mut limiter = RateLimiterBuilder::new();
+    limiter.per_token(RateLimit::new(0.001, 2));
+    limiter.per_topic(RateLimit::new(0.001, 1));
+    let limiter = limiter.finalize();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    builder.rate_limiter(limiter.clone());
+    let client = builder.finalize();
+
+    let send = |token: &'static str| client.send(MessageBuilder::new("token", token).finalize());
+
+    assert!(send("a").await.is_ok());
+    assert!(send("a").await.is_ok());
+    match send("a").await {
+        Err(FcmError::RateLimited(Some(RetryAfter::Delay(delay)))) => {
+            assert!(delay > chrono::Duration::seconds(900))
+        }
+        result => panic!("expected a rate limit error, got {:?}", result),
+    }
+    assert!(send("b").await.is_ok());
+
+    let mut message = MessageBuilder::new("token", "b");
+    message.topic("news");
+    assert!(client.send(message.finalize()).await.is_ok());
+
+    let mut message = MessageBuilder::new("token", "b");
+    message.topic("/topics/news");
+    assert!(client.send(message.finalize()).await.is_err());
+
+    assert_eq!(4, transport.requests().len());
+    assert_eq!(
+        ThrottleStats {
+            allowed: 4,
+            delayed: 0,
+            rejected: 2,
+            total_delay: std::time::Duration::from_secs(0),
+        },
+        limiter.stats()
+    );
+}
+
+#[tokio::test]
+async fn should_delay_sends_within_the_max_delay() {
+    let transport = Arc::new(MockTransport::new());
+    for _ in 0..2 {
+        transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+    }
+
+    let mut limiter = RateLimiterBuilder::new();
+    limiter.global(RateLimit::new(20.0, 1));
+    limiter.max_delay(std::time::Duration::from_secs(1));
+    let limiter = limiter.finalize();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    builder.rate_limiter(limiter.clone());
+    let client = builder.finalize();
+
+    let started = std::time::Instant::now();
+
+    for _ in 0..2 {
+        client
+            .send(MessageBuilder::new("token", "device").finalize())
+            .await
+            .unwrap();
+    }
+
+    let stats = limiter.stats();
+
+    assert!(started.elapsed() >= std::time::Duration::from_millis(40));
+    assert_eq!(2, stats.allowed);
+    assert_eq!(1, stats.delayed);
+    assert!(stats.total_delay > std::time::Duration::from_millis(40));
+}
+
+#[tokio::test]
+async fn should_report_quota_errors_as_rate_limited() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header("retry-after", "10")
+            .body(Body::empty())
+            .unwrap(),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    let client = builder.finalize();
+
+    assert_eq!(
+        Err(FcmError::RateLimited(Some(RetryAfter::Delay(
+            chrono::Duration::seconds(10)
+        )))),
+        client
+            .send(MessageBuilder::new("token", "device").finalize())
+            .await
+            .map(|_| ())
+    );
+}
+
+fn unavailable(retry_after: Option<&str>) -> Response<Body> {
+    let mut response = Response::builder().status(StatusCode::SERVICE_UNAVAILABLE);
+
+    if let Some(retry_after) = retry_after {
+        response = response.header("retry-after", retry_after);
+    }
+
+    response.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn should_open_the_circuit_on_server_errors() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+    transport.push_response(unavailable(None));
+    transport.push_error(TransportError::new("connection reset"));
+
+    let mut breaker = CircuitBreakerBuilder::new();
+    breaker.minimum_requests(3);
+    breaker.failure_rate(0.6);
+    breaker.open_duration(std::time::Duration::from_millis(50));
+    let breaker = breaker.finalize();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    builder.circuit_breaker(breaker.clone());
+    let client = builder.finalize();
+
+    let send = || client.send(MessageBuilder::new("token", "device").finalize());
+
+    assert!(send().await.is_ok());
+    assert!(send().await.is_err());
+    assert_eq!(CircuitState::Closed, breaker.state());
+    assert_eq!(
+        Err(FcmError::Transport(TransportError::new("connection reset"))),
+        send().await.map(|_| ())
+    );
+    assert_eq!(CircuitState::Open, breaker.state());
+
+    match send().await {
+        Err(FcmError::CircuitOpen(Some(RetryAfter::Delay(_)))) => (),
+        result => panic!("expected an open circuit, got {:?}", result),
+    }
+    assert_eq!(3, transport.requests().len());
+
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    transport.push_response(unavailable(None));
+
+    assert!(send().await.is_err());
+    assert_eq!(CircuitState::Open, breaker.state());
+
+    tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/2"}));
+
+    assert!(send().await.is_ok());
+    assert_eq!(CircuitState::Closed, breaker.state());
+    assert_eq!(5, transport.requests().len());
+}
+
+#[tokio::test]
+async fn should_not_take_the_rate_limit_while_the_circuit_is_open() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(unavailable(None));
+
+    let mut breaker = CircuitBreakerBuilder::new();
+    breaker.minimum_requests(1);
+    let breaker = breaker.finalize();
+
+    let mut limiter = RateLimiterBuilder::new();
+    limiter.global(RateLimit::new(0.001, 2));
+    let limiter = limiter.finalize();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    builder.circuit_breaker(breaker.clone());
+    builder.rate_limiter(limiter.clone());
+    let client = builder.finalize();
+
+    let send = || client.send(MessageBuilder::new("token", "device").finalize());
+
+    assert!(send().await.is_err());
+    assert_eq!(CircuitState::Open, breaker.state());
+
+    for _ in 0..3 {
+        assert!(matches!(send().await, Err(FcmError::CircuitOpen(_))));
+    }
+
+    assert_eq!(1, limiter.stats().allowed);
+    assert_eq!(0, limiter.stats().rejected);
+}
+
+#[tokio::test]
+async fn should_keep_the_circuit_open_for_the_longest_retry_after() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(unavailable(Some("3600")));
+    transport.push_response(unavailable(Some("60")));
+
+    let mut breaker = CircuitBreakerBuilder::new();
+    breaker.minimum_requests(2);
+    breaker.open_duration(std::time::Duration::from_millis(10));
+    let breaker = breaker.finalize();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    builder.circuit_breaker(breaker);
+    let client = builder.finalize();
+
+    let send = || client.send(MessageBuilder::new("token", "device").finalize());
+
+    assert!(send().await.is_err());
+    assert!(send().await.is_err());
+
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+    match send().await {
+        Err(FcmError::CircuitOpen(Some(RetryAfter::Delay(delay)))) => {
+            assert!(delay > chrono::Duration::seconds(3500))
+        }
+        result => panic!("expected an open circuit, got {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn should_only_validate_messages_in_validate_only_mode() {
+    let transport = Arc::new(MockTransport::new());
+    for _ in 0..3 {
+        transport.push_json(
+            StatusCode::OK,
+            json!({"name": "projects/test-project/messages/fake_message_id"}),
+        );
+    }
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    builder.validate_only(true);
+    let client = builder.finalize();
+
+    client
+        .send(MessageBuilder::new("token", "device").finalize())
+        .await
+        .unwrap();
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    let client = builder.finalize();
+
+    client
+        .validate(MessageBuilder::new("token", "device").finalize())
+        .await
+        .unwrap();
+
+    let mut message = MessageBuilder::new("token", "device");
+    message.dry_run(true);
+    client.send(message.finalize()).await.unwrap();
+
+    for request in transport.requests() {
+        assert_eq!(
+            json!({"message": {"token": "device"}, "validate_only": true}),
+            request.json()
+        );
+    }
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn should_send_through_tower_layers() {
+    use tower::ServiceExt;
+
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(
+        StatusCode::OK,
+        json!({"name": "projects/test-project/messages/1"}),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    builder.layer(tower::util::MapRequestLayer::new(
+        |mut request: hyper::Request<Body>| {
+            request
+                .headers_mut()
+                .insert("x-signature", "signed".parse().unwrap());
+            request
+        },
+    ));
+    let client = builder.finalize();
+
+    let message = MessageBuilder::new("token", "device").finalize();
+    let response = client.oneshot(message.into_owned()).await.unwrap();
+
+    assert!(response.error.is_none());
+    assert_eq!("signed", transport.requests()[0].headers["x-signature"]);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn should_record_send_metrics() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+    transport.push_response(unavailable(None));
+    transport.push_json(
+        StatusCode::NOT_FOUND,
+        json!({"error": {"code": 404, "details": [{"errorCode": "UNREGISTERED"}]}}),
+    );
+    transport.push_json(
+        StatusCode::OK,
+        json!({"name": "projects/test-project/messages/fake_message_id"}),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    let client = builder.finalize();
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    metrics::with_local_recorder(&recorder, || {
+        runtime.block_on(async {
+            let mut message = MessageBuilder::new("token", "device");
+            assert!(client.send(message.finalize()).await.is_ok());
+
+            message = MessageBuilder::new("token", "device");
+            message.topic("news");
+            assert!(client.send(message.finalize()).await.is_err());
+
+            message = MessageBuilder::new("token", "device");
+            assert!(client.send(message.finalize()).await.is_err());
+
+            message = MessageBuilder::new("token", "device");
+            assert!(client.validate(message.finalize()).await.is_ok());
+        })
+    });
+
+    let metrics: Vec<_> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| {
+            let key = key.key();
+            let mut labels: Vec<_> = key
+                .labels()
+                .filter(|label| label.key() != "project_id")
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            labels.sort();
+
+            (format!("{}{{{}}}", key.name(), labels.join(",")), value)
+        })
+        .collect();
+
+    let value = |name: &str| {
+        metrics
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    };
+
+    assert_eq!(
+        Some(&DebugValue::Counter(2)),
+        value("fcm_messages_sent_total{error_code=none,outcome=success,target=token}")
+    );
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value("fcm_messages_sent_total{error_code=server_error,outcome=error,target=topic}")
+    );
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value("fcm_messages_sent_total{error_code=UNREGISTERED,outcome=error,target=token}")
+    );
+    assert_eq!(
+        Some(&DebugValue::Gauge(0.0.into())),
+        value("fcm_requests_in_flight{}")
+    );
+
+    match value("fcm_payload_size_bytes{target=token}") {
+        Some(DebugValue::Histogram(sizes)) => assert_eq!(3, sizes.len()),
+        other => panic!("expected a payload size histogram, got {:?}", other),
+    }
+}
+
+/// Collects what a tracing subscriber writes.
+#[cfg(feature = "tracing")]
+#[derive(Clone)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(feature = "tracing")]
+impl std::io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn should_trace_sends() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(
+        StatusCode::NOT_FOUND,
+        json!({"error": {
+            "code": 404,
+            "status": "NOT_FOUND",
+            "details": [{
+                "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                "errorCode": "UNREGISTERED"
+            }]
+        }}),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    let client = builder.finalize();
+
+    let output = Output(Arc::new(Mutex::new(Vec::new())));
+    let subscriber = {
+        let output = output.clone();
+
+        tracing_subscriber::fmt()
+            .with_writer(move || output.clone())
+            .with_ansi(false)
+            .without_time()
+            .finish()
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let message = MessageBuilder::new("token", "abcdefghijklmnopqrstuvwxyz").finalize();
+        assert_eq!(
+            Err(FcmError::NotFound),
+            runtime.block_on(client.send(message)).map(|_| ())
+        );
+    });
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+    assert!(output.contains("sending the message failed"), "{}", output);
+    assert!(output.contains("project_id=\"test-project\""), "{}", output);
+    assert!(output.contains("target=\"token\""), "{}", output);
+    assert!(output.contains("token=abcdefgh..."), "{}", output);
+    assert!(output.contains("http.status=404"), "{}", output);
+    assert!(output.contains("error_code=\"UNREGISTERED\""), "{}", output);
+    assert!(!output.contains("ijklmnop"), "{}", output);
+}
+
+#[tokio::test]
+async fn should_fail_on_an_unreadable_send_response() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(Response::new(Body::from("<html>not json</html>")));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    let client = builder.finalize();
+
+    let result = client
+        .send(MessageBuilder::new("token", "device").finalize())
+        .await;
+
+    assert!(matches!(result, Err(FcmError::InvalidMessage(_))), "{:?}", result);
+}
+
+#[cfg(feature = "batch")]
+#[tokio::test]
+async fn should_send_batches_as_multipart() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "multipart/mixed; boundary=batch_xyz")
+            .body(Body::from(
+                "--batch_xyz\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-ID: <response-item1>\r\n\
+                 \r\n\
+                 HTTP/1.1 404 Not Found\r\n\
+                 Content-Type: application/json; charset=UTF-8\r\n\
+                 \r\n\
+                 {\"error\": {\"code\": 404, \"status\": \"NOT_FOUND\", \"details\": [{\"errorCode\": \"UNREGISTERED\"}]}}\r\n\
+                 --batch_xyz\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-ID: <response-item0>\r\n\
+                 \r\n\
+                 HTTP/1.1 200 OK\r\n\
+                 Content-Type: application/json; charset=UTF-8\r\n\
+                 \r\n\
+                 {\"name\": \"projects/test-project/messages/1\"}\r\n\
+                 --batch_xyz--\r\n",
+            ))
+            .unwrap(),
+    );
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    builder.validate(true);
+    {
+        let events = events.clone();
+        builder.token_feedback(move |event| events.lock().unwrap().push(event));
+    }
+    let client = builder.finalize();
+
+    let mut invalid = MessageBuilder::new("token", "c");
+    invalid.topic("not a topic");
+
+    let results = client
+        .send_all(vec![
+            MessageBuilder::new("token", "a").finalize(),
+            invalid.finalize(),
+            MessageBuilder::new("token", "b").finalize(),
+        ])
+        .await;
+
+    assert_eq!(3, results.len());
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(FcmError::ValidationFailed(_))));
+    assert_eq!(Some(&FcmError::NotFound), results[2].as_ref().err());
+    assert_eq!(
+        vec![TokenEvent::Remove(
+            "b".to_string(),
+            RemovalReason::Unregistered
+        )],
+        *events.lock().unwrap()
+    );
+
+    let requests = transport.requests();
+    let body = String::from_utf8(requests[0].body.clone()).unwrap();
+
+    assert_eq!(1, requests.len());
+    assert_eq!("/batch", requests[0].uri.path());
+    assert!(requests[0].headers["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("multipart/mixed; boundary="));
+    assert_eq!(2, body.matches("POST /v1/projects/test-project/messages:send HTTP/1.1").count());
+    assert!(body.contains("Content-ID: <item1>"));
+    assert!(body.contains(r#"{"message":{"token":"b"}}"#));
+}
+
+#[cfg(feature = "batch")]
+#[tokio::test]
+async fn should_fall_back_to_single_sends_without_the_batch_endpoint() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    );
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/1"}));
+    transport.push_json(StatusCode::OK, json!({"name": "projects/test-project/messages/2"}));
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    let client = builder.finalize();
+
+    let results = client
+        .send_all(vec![
+            MessageBuilder::new("token", "a").finalize(),
+            MessageBuilder::new("token", "b").finalize(),
+        ])
+        .await;
+
+    assert!(results.iter().all(Result::is_ok));
+
+    let paths: Vec<_> = transport
+        .requests()
+        .iter()
+        .map(|request| request.uri.path().to_string())
+        .collect();
+
+    assert_eq!(
+        vec![
+            "/batch",
+            "/v1/projects/test-project/messages:send",
+            "/v1/projects/test-project/messages:send"
+        ],
+        paths
+    );
+}
+
+#[cfg(feature = "batch")]
+#[tokio::test]
+async fn should_not_resend_a_batch_with_an_unreadable_response() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_json(StatusCode::OK, json!({"status": "done"}));
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "multipart/mixed; boundary=batch_xyz")
+            .body(Body::from(
+                "--batch_xyz\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-ID: <response-item0>\r\n\
+                 \r\n\
+                 garbage\r\n\
+                 --batch_xyz\r\n\
+                 Content-Type: application/http\r\n\
+                 Content-ID: <response-item1>\r\n\
+                 \r\n\
+                 HTTP/1.1 200 OK\r\n\
+                 \r\n\
+                 {\"name\": \"projects/test-project/messages/2\"}\r\n\
+                 --batch_xyz--\r\n",
+            ))
+            .unwrap(),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport.clone());
+    let client = builder.finalize();
+
+    let messages = || {
+        vec![
+            MessageBuilder::new("token", "a").finalize(),
+            MessageBuilder::new("token", "b").finalize(),
+        ]
+    };
+    let unreadable = FcmError::InvalidMessage("unreadable batch response".to_string());
+
+    let results = client.send_all(messages()).await;
+
+    assert_eq!(Some(&unreadable), results[0].as_ref().err());
+    assert_eq!(Some(&unreadable), results[1].as_ref().err());
+    assert_eq!(1, transport.requests().len());
+
+    let results = client.send_all(messages()).await;
+
+    assert_eq!(Some(&unreadable), results[0].as_ref().err());
+    assert!(results[1].is_ok());
+    assert_eq!(2, transport.requests().len());
+}
+
+#[cfg(all(feature = "batch", feature = "tracing"))]
+#[test]
+fn should_trace_each_message_of_a_batch() {
+    let transport = Arc::new(MockTransport::new());
+    transport.push_response(
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "multipart/mixed; boundary=batch_xyz")
+            .body(Body::from(
+                "--batch_xyz\r\n\
+                 Content-Type: application/http\r\n\
+                 \r\n\
+                 HTTP/1.1 404 Not Found\r\n\
+                 \r\n\
+                 {\"error\": {\"code\": 404, \"details\": [{\"errorCode\": \"UNREGISTERED\"}]}}\r\n\
+                 --batch_xyz--\r\n",
+            ))
+            .unwrap(),
+    );
+
+    let mut builder = ClientBuilder::new("test-project".to_string());
+    builder.transport(transport);
+    let client = builder.finalize();
+
+    let output = Output(Arc::new(Mutex::new(Vec::new())));
+    let subscriber = {
+        let output = output.clone();
+
+        tracing_subscriber::fmt()
+            .with_writer(move || output.clone())
+            .with_ansi(false)
+            .without_time()
+            .finish()
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let messages = vec![MessageBuilder::new("token", "abcdefghijklmnopqrstuvwxyz").finalize()];
+        let results = runtime.block_on(client.send_all(messages));
+
+        assert_eq!(Some(&FcmError::NotFound), results[0].as_ref().err());
+    });
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+    assert!(output.contains("fcm.send_all{project_id=\"test-project\" messages=1}"), "{}", output);
+    assert!(output.contains("sending the message failed"), "{}", output);
+    assert!(output.contains("token=abcdefgh..."), "{}", output);
+    assert!(output.contains("error_code=\"UNREGISTERED\""), "{}", output);
+}
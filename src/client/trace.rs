@@ -0,0 +1,71 @@
+use hyper::StatusCode;
+use tracing::{field, Span};
+
+use crate::client::{FcmError, FcmResponse};
+use crate::message::MessageBody;
+use crate::redact;
+
+/// The span of a send, with the project id, the kind of target and the
+/// start of the registration token. The HTTP status and FCM error code are
+/// recorded once the response arrives.
+pub(crate) fn send_span(project_id: &str, body: &MessageBody<'_>) -> Span {
+    let span = tracing::info_span!(
+        "fcm.send",
+        project_id,
+        target = body.target_kind(),
+        token = field::Empty,
+        http.status = field::Empty,
+        error_code = field::Empty,
+    );
+
+    if let Some(token) = redact::token(&body.token) {
+        span.record("token", field::display(token));
+    }
+
+    span
+}
+
+/// The span of a `send_all`, with the project id and the number of
+/// messages. Each message gets a `fcm.send` span within it once sent.
+#[cfg(feature = "batch")]
+pub(crate) fn send_all_span(project_id: &str, messages: usize) -> Span {
+    tracing::info_span!("fcm.send_all", project_id, messages)
+}
+
+/// Record the outcome of a message of a `send_all` in its own span, within
+/// the one of the batch.
+#[cfg(feature = "batch")]
+pub(crate) fn record_batched(
+    batch: &Span,
+    project_id: &str,
+    body: &MessageBody<'_>,
+    result: &Result<FcmResponse, FcmError>,
+    error_code: Option<&str>,
+) {
+    batch.in_scope(|| {
+        let span = send_span(project_id, body);
+
+        if let Some(error_code) = error_code {
+            span.record("error_code", error_code);
+        }
+
+        record_outcome(&span, result);
+    });
+}
+
+/// Record the response on the current span, which is the one of the send.
+pub(crate) fn record_response(status: StatusCode, error_code: Option<&str>) {
+    let span = Span::current();
+    span.record("http.status", status.as_u16());
+
+    if let Some(error_code) = error_code {
+        span.record("error_code", error_code);
+    }
+}
+
+pub(crate) fn record_outcome(span: &Span, result: &Result<FcmResponse, FcmError>) {
+    span.in_scope(|| match result {
+        Ok(_) => tracing::debug!("message sent"),
+        Err(e) => tracing::warn!(error = %e, "sending the message failed"),
+    });
+}
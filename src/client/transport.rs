@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{BoxFuture, FutureExt};
+use http::header::AUTHORIZATION;
+use http::{HeaderMap, Method, Uri};
+use hyper::{
+    client::{Client as HttpClient, HttpConnector},
+    Body, Request, Response, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+use serde_json::Value;
+
+use crate::redact::{Secret, Token};
+
+/// The HTTP layer a `Client` sends its requests through. `HyperTransport`
+/// is used by default, `MockTransport` answers with canned responses in
+/// tests.
+pub trait Transport: Send + Sync {
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>>;
+}
+
+impl<T> Transport for Arc<T>
+where
+    T: Transport + ?Sized,
+{
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>> {
+        (**self).request(request)
+    }
+}
+
+impl<T> Transport for Box<T>
+where
+    T: Transport + ?Sized,
+{
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>> {
+        (**self).request(request)
+    }
+}
+
+/// A request couldn't be sent or its response not received. Keeps the
+/// error of the HTTP layer as its source, e.g. a `hyper::Error` to tell a
+/// refused connection from a timeout.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::TransportError;
+/// use std::error::Error;
+/// use std::io;
+///
+/// let error = TransportError::new(io::Error::from(io::ErrorKind::TimedOut));
+///
+/// let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+/// assert_eq!(io::ErrorKind::TimedOut, source.kind());
+/// ```
+#[derive(Clone)]
+pub struct TransportError {
+    error: Arc<dyn Error + Send + Sync>,
+}
+
+impl TransportError {
+    /// A transport error caused by the given error, or by one with the given
+    /// message.
+    pub fn new<E>(error: E) -> TransportError
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        TransportError {
+            error: Arc::from(error.into()),
+        }
+    }
+
+    /// The error of the HTTP layer.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.error
+    }
+}
+
+/// Compares the errors by their message, as the errors of the HTTP layer
+/// can't be compared otherwise.
+impl PartialEq for TransportError {
+    fn eq(&self, other: &TransportError) -> bool {
+        self.error.to_string() == other.error.to_string()
+    }
+}
+
+impl fmt::Debug for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TransportError").field(&self.error).finish()
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "transport error")
+    }
+}
+
+impl From<hyper::Error> for TransportError {
+    fn from(e: hyper::Error) -> Self {
+        TransportError::new(e)
+    }
+}
+
+/// Sends requests with a pooled hyper client over HTTPS, or plain HTTP for
+/// `http` URLs.
+pub struct HyperTransport {
+    http_client: HttpClient<HttpsConnector<HttpConnector>>,
+}
+
+impl HyperTransport {
+    pub fn new() -> HyperTransport {
+        let mut http_client = HttpClient::builder();
+        http_client.pool_max_idle_per_host(usize::MAX);
+
+        HyperTransport {
+            http_client: http_client.build(HttpsConnector::new()),
+        }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        HyperTransport::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>> {
+        self.http_client
+            .request(request)
+            .map(|response| response.map_err(TransportError::from))
+            .boxed()
+    }
+}
+
+/// A request received by a `MockTransport`.
+#[derive(Clone)]
+pub struct CapturedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl CapturedRequest {
+    /// The body parsed as JSON, `Value::Null` if it isn't.
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// Redacts the `Authorization` header and cuts down the body, which holds
+/// the registration token, see `Unredacted`.
+impl fmt::Debug for CapturedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapturedRequest")
+            .field("method", &self.method)
+            .field("uri", &self.uri)
+            .field("headers", &RedactedHeaders(&self.headers))
+            .field("body", &Token(&String::from_utf8_lossy(&self.body)))
+            .finish()
+    }
+}
+
+struct RedactedHeaders<'a>(&'a HeaderMap);
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+
+        for (name, value) in self.0 {
+            if name == AUTHORIZATION {
+                map.entry(name, &Secret(value.to_str().unwrap_or_default()));
+            } else {
+                map.entry(name, value);
+            }
+        }
+
+        map.finish()
+    }
+}
+
+/// A `Transport` answering with queued responses, in order, and keeping the
+/// requests it received. Fails with a `TransportError` when no response is
+/// left.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{ClientBuilder, MessageBuilder, MockTransport};
+/// use hyper::StatusCode;
+/// use std::sync::Arc;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let transport = Arc::new(MockTransport::new());
+/// transport.push_json(StatusCode::OK, serde_json::json!({"name": "projects/p/messages/1"}));
+///
+/// let mut builder = ClientBuilder::new("p".to_string());
+/// builder.transport(transport.clone());
+/// let client = builder.finalize();
+///
+/// client.send(MessageBuilder::new("<access token>", "<registration id>").finalize()).await.unwrap();
+///
+/// let requests = transport.requests();
+/// assert_eq!("/v1/projects/p/messages:send", requests[0].uri.path());
+/// assert_eq!("<registration id>", requests[0].json()["message"]["token"]);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct MockTransport {
+    responses: Mutex<VecDeque<Result<Response<Body>, TransportError>>>,
+    requests: Mutex<Vec<CapturedRequest>>,
+}
+
+/// Redacts the requests received as `CapturedRequest` does.
+impl fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockTransport")
+            .field("responses", &self.responses.lock().unwrap())
+            .field("requests", &self.requests.lock().unwrap())
+            .finish()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Queue a response, e.g. one with a `Retry-After` header.
+    pub fn push_response(&self, response: Response<Body>) {
+        self.responses.lock().unwrap().push_back(Ok(response));
+    }
+
+    /// Queue a JSON response with the given status.
+    pub fn push_json(&self, status: StatusCode, body: Value) {
+        let response = Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        self.push_response(response);
+    }
+
+    /// Queue a failure to reach the server.
+    pub fn push_error(&self, error: TransportError) {
+        self.responses.lock().unwrap().push_back(Err(error));
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<CapturedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for MockTransport {
+    fn request(
+        &self,
+        request: Request<Body>,
+    ) -> BoxFuture<'_, Result<Response<Body>, TransportError>> {
+        async move {
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+
+            self.requests.lock().unwrap().push(CapturedRequest {
+                method: parts.method,
+                uri: parts.uri,
+                headers: parts.headers,
+                body: body.to_vec(),
+            });
+
+            let response = self.responses.lock().unwrap().pop_front();
+            response.unwrap_or_else(|| Err(TransportError::new("no response queued")))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+
+use chrono::Utc;
+use futures::future;
+
+use crate::client::response::{FcmError, FcmResponse};
+use crate::client::Client;
+use crate::message::Message;
+use crate::redact::Token;
+use crate::store::TokenStoreError;
+
+/// The outcome of sending a message to one of the tokens of a user.
+pub struct TokenSendResult {
+    pub token: String,
+    pub result: Result<FcmResponse, FcmError>,
+}
+
+/// Cuts down the token, see `Unredacted`.
+impl fmt::Debug for TokenSendResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenSendResult")
+            .field("token", &Token(&self.token))
+            .field("result", &self.result)
+            .finish()
+    }
+}
+
+impl Client {
+    /// Send a copy of the message to every token the `TokenStore` has for
+    /// the user, concurrently. The target of the message is replaced by each
+    /// token. Delivered tokens are recorded in the store and unregistered
+    /// ones removed from it.
+    ///
+    /// Fails without sending anything if no store was configured with
+    /// `ClientBuilder::token_store` or it couldn't be read.
+    ///
+    /// # Examples:
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// use fcm::{ClientBuilder, MemoryTokenStore, MessageBuilder, TokenStore};
+    /// use std::sync::Arc;
+    ///
+    /// let store = Arc::new(MemoryTokenStore::new());
+    /// store.add_token("user-42", "<registration id>")?;
+    ///
+    /// let mut builder = ClientBuilder::new("<project id>".to_string());
+    /// builder.token_store(store.clone());
+    /// let client = builder.finalize();
+    ///
+    /// let message = MessageBuilder::new("<FCM access token>", "").finalize();
+    ///
+    /// for sent in client.send_to_user("user-42", message).await? {
+    ///     println!("{}: {:?}", sent.token, sent.result);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_to_user(
+        &self,
+        user_id: &str,
+        message: Message<'_>,
+    ) -> Result<Vec<TokenSendResult>, FcmError> {
+        let token_store = self.token_store.as_deref().ok_or_else(|| {
+            FcmError::TokenStore(TokenStoreError("no token store configured".to_string()))
+        })?;
+
+        let tokens = token_store.tokens(user_id).map_err(FcmError::TokenStore)?;
+
+        let results = future::join_all(
+            tokens
+                .iter()
+                .map(|token| self.send(message.with_token(token))),
+        )
+        .await;
+
+        Ok(tokens
+            .into_iter()
+            .zip(results)
+            .map(|(token, result)| {
+                if result.is_ok() {
+                    if let Err(e) = token_store.record_success(&token, Utc::now()) {
+                        log::warn!("Couldn't update the token store: {}", e);
+                    }
+                }
+
+                TokenSendResult { token, result }
+            })
+            .collect())
+    }
+}
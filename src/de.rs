@@ -0,0 +1,97 @@
+use std::cell::Cell;
+
+use serde::de::{DeserializeOwned, Deserializer, Error};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// What to do with fields of a payload not known to this crate, e.g. ones
+/// added to the FCM API after this version was released.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum UnknownFields {
+    /// Fail with an error naming the first unknown field.
+    Reject,
+
+    /// Keep the fields, and send them along when the payload is serialized
+    /// again.
+    Preserve,
+}
+
+thread_local! {
+    static POLICY: Cell<UnknownFields> = const { Cell::new(UnknownFields::Preserve) };
+}
+
+/// Restores the previous policy, also when deserializing panics.
+struct PolicyGuard(UnknownFields);
+
+impl PolicyGuard {
+    fn set(policy: UnknownFields) -> PolicyGuard {
+        PolicyGuard(POLICY.with(|p| p.replace(policy)))
+    }
+}
+
+impl Drop for PolicyGuard {
+    fn drop(&mut self) {
+        POLICY.with(|p| p.set(self.0));
+    }
+}
+
+/// Deserialize a message or any of its parts from JSON, handling unknown
+/// fields as given. Plain `serde_json::from_str` preserves them.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{MessageBody, UnknownFields};
+///
+/// let json = r#"{"token": "<registration id>", "notification": {"title": "Hey!"}}"#;
+/// let body: MessageBody = fcm::from_str(json, UnknownFields::Reject).unwrap();
+///
+/// let json = r#"{"token": "<registration id>", "priority": "high"}"#;
+/// assert!(fcm::from_str::<MessageBody>(json, UnknownFields::Reject).is_err());
+/// ```
+pub fn from_str<T>(json: &str, unknown_fields: UnknownFields) -> Result<T, serde_json::Error>
+where
+    T: DeserializeOwned,
+{
+    let _guard = PolicyGuard::set(unknown_fields);
+    serde_json::from_str(json)
+}
+
+/// Deserialize a message or any of its parts from a JSON value, handling
+/// unknown fields as given.
+pub fn from_value<T>(value: Value, unknown_fields: UnknownFields) -> Result<T, serde_json::Error>
+where
+    T: DeserializeOwned,
+{
+    let _guard = PolicyGuard::set(unknown_fields);
+    serde_json::from_value(value)
+}
+
+/// Collects the fields left over by the other fields of a struct, to be used
+/// with `#[serde(flatten)]`.
+pub(crate) fn unknown_fields<'de, D>(deserializer: D) -> Result<Map<String, Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let fields = Map::deserialize(deserializer)?;
+
+    match fields.keys().next() {
+        Some(name) if POLICY.with(|p| p.get()) == UnknownFields::Reject => {
+            Err(D::Error::custom(format_args!("unknown field `{}`", name)))
+        }
+        _ => Ok(fields),
+    }
+}
+
+/// A flattened JSON object holding custom keys, `None` if there are none.
+pub(crate) fn flattened_object<'de, D>(deserializer: D) -> Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let fields = Map::deserialize(deserializer)?;
+
+    if fields.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(Value::Object(fields)))
+    }
+}
@@ -0,0 +1,662 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::channel::oneshot;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::Value;
+
+use crate::client::ClientBuilder;
+use crate::de::{self, UnknownFields};
+use crate::message::{MessageBody, Violation};
+use crate::redact::Secret;
+
+#[cfg(test)]
+mod tests;
+
+/// The access token every emulator accepts, next to the ones it issues on
+/// its token endpoint.
+pub const EMULATOR_ACCESS_TOKEN: &str = "emulator-access-token";
+
+/// An error code of the v1 API the emulator can be told to answer with.
+/// Referred from [Firebase
+/// documentation](https://firebase.google.com/docs/reference/fcm/rest/v1/ErrorCode).
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum EmulatedError {
+    /// `400 INVALID_ARGUMENT`
+    InvalidArgument,
+
+    /// `404 UNREGISTERED`
+    Unregistered,
+
+    /// `403 SENDER_ID_MISMATCH`
+    SenderIdMismatch,
+
+    /// `429 QUOTA_EXCEEDED`
+    QuotaExceeded,
+
+    /// `503 UNAVAILABLE`
+    Unavailable,
+
+    /// `500 INTERNAL`
+    Internal,
+
+    /// `401 THIRD_PARTY_AUTH_ERROR`
+    ThirdPartyAuthError,
+}
+
+impl EmulatedError {
+    fn status(self) -> StatusCode {
+        match self {
+            EmulatedError::InvalidArgument => StatusCode::BAD_REQUEST,
+            EmulatedError::Unregistered => StatusCode::NOT_FOUND,
+            EmulatedError::SenderIdMismatch => StatusCode::FORBIDDEN,
+            EmulatedError::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            EmulatedError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            EmulatedError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            EmulatedError::ThirdPartyAuthError => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_code(self) -> &'static str {
+        match self {
+            EmulatedError::InvalidArgument => "INVALID_ARGUMENT",
+            EmulatedError::Unregistered => "UNREGISTERED",
+            EmulatedError::SenderIdMismatch => "SENDER_ID_MISMATCH",
+            EmulatedError::QuotaExceeded => "QUOTA_EXCEEDED",
+            EmulatedError::Unavailable => "UNAVAILABLE",
+            EmulatedError::Internal => "INTERNAL",
+            EmulatedError::ThirdPartyAuthError => "THIRD_PARTY_AUTH_ERROR",
+        }
+    }
+}
+
+/// A scripted failure of a send, optionally with a `Retry-After` header.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Fault {
+    error: EmulatedError,
+    retry_after: Option<Duration>,
+}
+
+impl Fault {
+    pub fn new(error: EmulatedError) -> Fault {
+        Fault {
+            error,
+            retry_after: None,
+        }
+    }
+
+    /// Send a `Retry-After` header of the given whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    fn response(&self) -> Response<Body> {
+        let mut response = error_response(
+            self.error.status(),
+            "Scripted failure of the FCM emulator.",
+            Some(self.error.error_code()),
+            Vec::new(),
+        );
+
+        if let Some(retry_after) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.as_secs().into());
+        }
+
+        response
+    }
+}
+
+impl From<EmulatedError> for Fault {
+    fn from(error: EmulatedError) -> Self {
+        Fault::new(error)
+    }
+}
+
+/// A message the emulator accepted.
+#[derive(Clone)]
+pub struct ReceivedMessage {
+    pub project_id: String,
+    pub access_token: String,
+
+    /// The message resource name returned to the client.
+    pub name: String,
+
+    pub body: MessageBody<'static>,
+}
+
+/// Redacts the access token and cuts down the registration token, see
+/// `Unredacted`.
+impl fmt::Debug for ReceivedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReceivedMessage")
+            .field("project_id", &self.project_id)
+            .field("access_token", &Secret(&self.access_token))
+            .field("name", &self.name)
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    access_tokens: HashSet<String>,
+    messages: Vec<ReceivedMessage>,
+    faults: VecDeque<Fault>,
+    token_faults: HashMap<String, Fault>,
+
+    /// The subscribers of each topic, with the date they subscribed.
+    topics: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SendRequest {
+    message: MessageBody<'static>,
+
+    #[serde(default)]
+    validate_only: bool,
+}
+
+#[derive(Deserialize)]
+struct TopicManagementRequest {
+    to: String,
+    registration_tokens: Vec<String>,
+}
+
+/// An in-process fake of the FCM HTTP v1 API, the OAuth 2.0 token endpoint
+/// and the Instance ID topic endpoints, listening on a random local port.
+/// Requires the `emulator` feature.
+///
+/// Messages are validated like the real service does and recorded for
+/// assertions. Failures can be scripted for the next sends or for single
+/// tokens. The server stops when the emulator is dropped.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{EmulatedError, Emulator, FcmError, Fault, MessageBuilder};
+/// use std::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let emulator = Emulator::start();
+/// let client = emulator.client_builder("my-project").finalize();
+///
+/// let message = MessageBuilder::new(emulator.access_token(), "<registration id>").finalize();
+/// client.send(message).await.unwrap();
+///
+/// assert_eq!(1, emulator.messages().len());
+///
+/// emulator.fail_next(Fault::new(EmulatedError::Unavailable).retry_after(Duration::from_secs(30)));
+///
+/// let message = MessageBuilder::new(emulator.access_token(), "<registration id>").finalize();
+/// assert!(matches!(client.send(message).await, Err(FcmError::ServerError(Some(_)))));
+/// # }
+/// ```
+pub struct Emulator {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl Emulator {
+    /// Start the emulator. Must be called from within a Tokio runtime.
+    pub fn start() -> Emulator {
+        let mut state = State::default();
+        state
+            .access_tokens
+            .insert(EMULATOR_ACCESS_TOKEN.to_string());
+
+        let state = Arc::new(Mutex::new(state));
+
+        let make_service = {
+            let state = state.clone();
+
+            make_service_fn(move |_| {
+                let state = state.clone();
+
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let state = state.clone();
+
+                        async move {
+                            let (parts, body) = request.into_parts();
+                            let body = hyper::body::to_bytes(body).await?;
+
+                            Ok::<_, hyper::Error>(handle(&state, &parts, &body))
+                        }
+                    }))
+                }
+            })
+        };
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Emulator {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// The scheme and host of the emulator, serving both the FCM and the
+    /// Instance ID API.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The token endpoint, to use as the `token_uri` of a service account
+    /// key. Any signed JWT bearer assertion is exchanged for an access token.
+    pub fn token_url(&self) -> String {
+        format!("{}/token", self.url())
+    }
+
+    /// A `ClientBuilder` for the project, talking to the emulator.
+    pub fn client_builder(&self, project_id: &str) -> ClientBuilder {
+        let mut builder = ClientBuilder::new(project_id.to_string());
+        builder.fcm_url(&self.url()).iid_url(&self.url());
+        builder
+    }
+
+    /// An access token the emulator accepts.
+    pub fn access_token(&self) -> &'static str {
+        EMULATOR_ACCESS_TOKEN
+    }
+
+    /// The messages accepted so far, in the order they were received.
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    /// The tokens subscribed to the topic.
+    pub fn subscribers(&self, topic: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .topics
+            .get(topic.trim_start_matches("/topics/"))
+            .map(|tokens| tokens.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Fail the next send, after any failures scripted before.
+    pub fn fail_next(&self, fault: impl Into<Fault>) {
+        self.state.lock().unwrap().faults.push_back(fault.into());
+    }
+
+    /// Fail every send to the token. An `Unregistered` token is also
+    /// reported as not found by the Instance ID endpoints.
+    pub fn fail_token(&self, token: &str, fault: impl Into<Fault>) {
+        self.state
+            .lock()
+            .unwrap()
+            .token_faults
+            .insert(token.to_string(), fault.into());
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, parts: &http::request::Parts, body: &[u8]) -> Response<Body> {
+    let mut state = state.lock().unwrap();
+    let path = parts.uri.path();
+
+    match (&parts.method, path) {
+        (&Method::POST, "/token") => issue_token(&mut state, body),
+        (&Method::POST, "/iid/v1:batchAdd") => manage_topic(&mut state, parts, body, true),
+        (&Method::POST, "/iid/v1:batchRemove") => manage_topic(&mut state, parts, body, false),
+        (&Method::GET, _) if path.starts_with("/iid/info/") => {
+            token_info(&state, parts, &path["/iid/info/".len()..])
+        }
+        (&Method::POST, _) => match send_project(path) {
+            Some(project_id) => send(&mut state, parts, project_id, body),
+            None => json_response(StatusCode::NOT_FOUND, json!({"error": "Not Found"})),
+        },
+        _ => json_response(StatusCode::NOT_FOUND, json!({"error": "Not Found"})),
+    }
+}
+
+/// The project id of a `/v1/projects/{project_id}/messages:send` path.
+fn send_project(path: &str) -> Option<&str> {
+    path.strip_prefix("/v1/projects/")?
+        .strip_suffix("/messages:send")
+        .filter(|project_id| !project_id.is_empty() && !project_id.contains('/'))
+}
+
+fn authorized(state: &State, parts: &http::request::Parts) -> Option<String> {
+    parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| state.access_tokens.contains(*token))
+        .map(String::from)
+}
+
+fn send(
+    state: &mut State,
+    parts: &http::request::Parts,
+    project_id: &str,
+    body: &[u8],
+) -> Response<Body> {
+    let access_token = match authorized(state, parts) {
+        Some(access_token) => access_token,
+        None => {
+            return error_response(
+                StatusCode::UNAUTHORIZED,
+                "Request had invalid authentication credentials.",
+                None,
+                Vec::new(),
+            )
+        }
+    };
+
+    if let Some(fault) = state.faults.pop_front() {
+        return fault.response();
+    }
+
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return invalid_argument(&format!("Invalid JSON payload received. {}", e)),
+    };
+
+    let targets = ["token", "topic", "condition"]
+        .iter()
+        .filter(|target| request["message"].get(**target).is_some())
+        .count();
+
+    if targets != 1 {
+        return invalid_argument("Exactly one of token, topic or condition must be set.");
+    }
+
+    let request: SendRequest = match de::from_value(request, UnknownFields::Reject) {
+        Ok(request) => request,
+        Err(e) => return invalid_argument(&format!("Invalid JSON payload received. {}", e)),
+    };
+
+    if let Err(violations) = request.message.validate() {
+        return bad_request(&violations);
+    }
+
+    if let Some(fault) = request
+        .message
+        .token
+        .as_deref()
+        .and_then(|token| state.token_faults.get(token))
+    {
+        return fault.response();
+    }
+
+    if request.validate_only {
+        let name = format!("projects/{}/messages/fake_message_id", project_id);
+        return json_response(StatusCode::OK, json!({ "name": name }));
+    }
+
+    let name = format!(
+        "projects/{}/messages/{}",
+        project_id,
+        state.messages.len() + 1
+    );
+
+    state.messages.push(ReceivedMessage {
+        project_id: project_id.to_string(),
+        access_token,
+        name: name.clone(),
+        body: request.message,
+    });
+
+    json_response(StatusCode::OK, json!({ "name": name }))
+}
+
+fn issue_token(state: &mut State, body: &[u8]) -> Response<Body> {
+    let form: HashMap<String, String> = String::from_utf8_lossy(body)
+        .split('&')
+        .filter_map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            Some((percent_decode(pair.next()?), percent_decode(pair.next()?)))
+        })
+        .collect();
+
+    let grant_type = form.get("grant_type").map(String::as_str);
+    let assertion = form.get("assertion").map(String::as_str).unwrap_or("");
+
+    if grant_type != Some("urn:ietf:params:oauth:grant-type:jwt-bearer")
+        || assertion.split('.').count() != 3
+    {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            json!({"error": "invalid_grant", "error_description": "Invalid JWT bearer assertion."}),
+        );
+    }
+
+    let access_token = format!("emulator-token-{}", state.access_tokens.len());
+    state.access_tokens.insert(access_token.clone());
+
+    json_response(
+        StatusCode::OK,
+        json!({
+            "access_token": access_token,
+            "expires_in": 3599,
+            "token_type": "Bearer"
+        }),
+    )
+}
+
+fn manage_topic(
+    state: &mut State,
+    parts: &http::request::Parts,
+    body: &[u8],
+    subscribe: bool,
+) -> Response<Body> {
+    if authorized(state, parts).is_none() {
+        return json_response(StatusCode::UNAUTHORIZED, json!({"error": "Unauthorized"}));
+    }
+
+    let request: TopicManagementRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => {
+            return json_response(StatusCode::BAD_REQUEST, json!({"error": "InvalidRequest"}))
+        }
+    };
+
+    let topic = match request.to.strip_prefix("/topics/") {
+        Some(topic) if is_valid_topic(topic) => topic.to_string(),
+        _ => {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                json!({"error": "InvalidTopicName"}),
+            )
+        }
+    };
+
+    let today = Utc::now().format("%Y-%m-%d").to_string();
+
+    let results: Vec<Value> = request
+        .registration_tokens
+        .into_iter()
+        .map(|token| {
+            if is_unregistered(state, &token) {
+                return json!({"error": "NOT_FOUND"});
+            }
+
+            let subscribers = state.topics.entry(topic.clone()).or_default();
+
+            if subscribe {
+                subscribers.entry(token).or_insert_with(|| today.clone());
+            } else {
+                subscribers.remove(&token);
+            }
+
+            json!({})
+        })
+        .collect();
+
+    json_response(StatusCode::OK, json!({ "results": results }))
+}
+
+fn token_info(state: &State, parts: &http::request::Parts, token: &str) -> Response<Body> {
+    if authorized(state, parts).is_none() {
+        return json_response(StatusCode::UNAUTHORIZED, json!({"error": "Unauthorized"}));
+    }
+
+    if is_unregistered(state, token) {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            json!({"error": "No information found about this instance id."}),
+        );
+    }
+
+    let topics: BTreeMap<&str, Value> = state
+        .topics
+        .iter()
+        .filter_map(|(topic, subscribers)| {
+            let date = subscribers.get(token)?;
+            Some((topic.as_str(), json!({ "addDate": date })))
+        })
+        .collect();
+
+    json_response(StatusCode::OK, json!({"rel": {"topics": topics}}))
+}
+
+fn is_unregistered(state: &State, token: &str) -> bool {
+    matches!(
+        state.token_faults.get(token),
+        Some(fault) if fault.error == EmulatedError::Unregistered
+    )
+}
+
+fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.~%".contains(&b))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn invalid_argument(message: &str) -> Response<Body> {
+    error_response(
+        StatusCode::BAD_REQUEST,
+        message,
+        Some("INVALID_ARGUMENT"),
+        Vec::new(),
+    )
+}
+
+/// An `INVALID_ARGUMENT` error listing the violations as field violations,
+/// as the real service does.
+fn bad_request(violations: &[Violation]) -> Response<Body> {
+    let field_violations: Vec<Value> = violations
+        .iter()
+        .map(|v| json!({"field": v.path, "description": v.to_string()}))
+        .collect();
+
+    error_response(
+        StatusCode::BAD_REQUEST,
+        &violations[0].to_string(),
+        Some("INVALID_ARGUMENT"),
+        vec![json!({
+            "@type": "type.googleapis.com/google.rpc.BadRequest",
+            "fieldViolations": field_violations
+        })],
+    )
+}
+
+/// An error in the format of the v1 API, with the FCM error code in the
+/// details.
+fn error_response(
+    status: StatusCode,
+    message: &str,
+    error_code: Option<&str>,
+    mut details: Vec<Value>,
+) -> Response<Body> {
+    if let Some(error_code) = error_code {
+        details.insert(
+            0,
+            json!({
+                "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                "errorCode": error_code
+            }),
+        );
+    }
+
+    let rpc_status = match status {
+        StatusCode::BAD_REQUEST => "INVALID_ARGUMENT",
+        StatusCode::UNAUTHORIZED => "UNAUTHENTICATED",
+        StatusCode::FORBIDDEN => "PERMISSION_DENIED",
+        StatusCode::NOT_FOUND => "NOT_FOUND",
+        StatusCode::TOO_MANY_REQUESTS => "RESOURCE_EXHAUSTED",
+        StatusCode::SERVICE_UNAVAILABLE => "UNAVAILABLE",
+        _ => "INTERNAL",
+    };
+
+    json_response(
+        status,
+        json!({
+            "error": {
+                "code": status.as_u16(),
+                "message": message,
+                "status": rpc_status,
+                "details": details
+            }
+        }),
+    )
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json; charset=UTF-8")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
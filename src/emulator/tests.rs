@@ -0,0 +1,270 @@
+use crate::{
+    EmulatedError, Emulator, Fault, FcmError, MessageBuilder, NotificationBuilder, RemovalReason,
+    RetryAfter, TokenEvent, TopicManagementError,
+};
+use chrono::Duration as ChronoDuration;
+use hyper::{Body, Request, StatusCode};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+async fn post(url: String, content_type: &str, body: String) -> (StatusCode, serde_json::Value) {
+    let request = Request::post(url)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = hyper::Client::new().request(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn should_record_sent_messages() {
+    let emulator = Emulator::start();
+    let client = emulator.client_builder("test-project").finalize();
+
+    let mut notification = NotificationBuilder::new();
+    notification.title("Hello");
+
+    let mut builder = MessageBuilder::new(emulator.access_token(), "device");
+    builder.notification(notification.finalize());
+
+    let response = client.send(builder.finalize()).await.unwrap();
+    let messages = emulator.messages();
+
+    assert_eq!(1, messages.len());
+    assert_eq!("test-project", messages[0].project_id);
+    assert_eq!("projects/test-project/messages/1", messages[0].name);
+    assert_eq!(Some("device"), messages[0].body.token.as_deref());
+    assert!(!format!("{:?}", messages[0]).contains(emulator.access_token()));
+    assert!(response.error.is_none());
+}
+
+#[tokio::test]
+async fn should_reject_unknown_access_tokens() {
+    let emulator = Emulator::start();
+    let client = emulator.client_builder("test-project").finalize();
+
+    let message = MessageBuilder::new("not-issued", "device").finalize();
+
+    assert_eq!(
+        Err(FcmError::Unauthorized),
+        client.send(message).await.map(|_| ())
+    );
+    assert!(emulator.messages().is_empty());
+}
+
+#[tokio::test]
+async fn should_validate_payloads_like_the_real_service() {
+    let emulator = Emulator::start();
+    let url = format!("{}/v1/projects/test-project/messages:send", emulator.url());
+
+    let send = |message: serde_json::Value| {
+        let request = Request::post(url.clone())
+            .header(
+                "authorization",
+                format!("Bearer {}", emulator.access_token()),
+            )
+            .body(Body::from(json!({ "message": message }).to_string()))
+            .unwrap();
+
+        async move {
+            let response = hyper::Client::new().request(request).await.unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+            (status, body)
+        }
+    };
+
+    let (status, body) = send(json!({"token": "a", "topic": "b"})).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert_eq!("INVALID_ARGUMENT", body["error"]["status"]);
+
+    let (status, body) = send(json!({"token": "a", "priority": "high"})).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("unknown field `priority`"));
+
+    let (status, body) = send(json!({"topic": "a b", "data": {"from": "me"}})).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert_eq!(
+        json!([
+            {"field": "message.data.from", "description": "message.data.from: the data key \"from\" is reserved"},
+            {"field": "message.topic", "description": "message.topic: invalid topic name \"a b\""}
+        ]),
+        body["error"]["details"][1]["fieldViolations"]
+    );
+
+    let (status, _) = send(json!({"topic": "news"})).await;
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!(1, emulator.messages().len());
+}
+
+#[tokio::test]
+async fn should_validate_without_delivering() {
+    let emulator = Emulator::start();
+    let client = emulator.client_builder("test-project").finalize();
+
+    let message = MessageBuilder::new(emulator.access_token(), "device").finalize();
+    let response = client.validate(message).await.unwrap();
+
+    assert_eq!(
+        Some("projects/test-project/messages/fake_message_id"),
+        response.name.as_deref()
+    );
+
+    let mut message = MessageBuilder::new(emulator.access_token(), "device");
+    message.topic("not a topic");
+
+    match client.validate(message.finalize()).await {
+        Err(FcmError::InvalidMessage(reason)) => {
+            assert!(reason.contains("(message.topic: "), "{}", reason)
+        }
+        result => panic!("expected the reason of the rejection, got {:?}", result),
+    }
+    assert!(emulator.messages().is_empty());
+}
+
+#[tokio::test]
+async fn should_fail_scripted_sends() {
+    let emulator = Emulator::start();
+    let client = emulator.client_builder("test-project").finalize();
+    let send = || client.send(MessageBuilder::new(emulator.access_token(), "device").finalize());
+
+    emulator.fail_next(Fault::new(EmulatedError::Unavailable).retry_after(Duration::from_secs(30)));
+    emulator.fail_next(EmulatedError::Internal);
+
+    assert_eq!(
+        Err(FcmError::ServerError(Some(RetryAfter::Delay(
+            ChronoDuration::seconds(30)
+        )))),
+        send().await.map(|_| ())
+    );
+    assert_eq!(Err(FcmError::ServerError(None)), send().await.map(|_| ()));
+    assert!(send().await.is_ok());
+    assert_eq!(1, emulator.messages().len());
+}
+
+#[tokio::test]
+async fn should_answer_rate_limited_sends_with_retry_after() {
+    let emulator = Emulator::start();
+    emulator
+        .fail_next(Fault::new(EmulatedError::QuotaExceeded).retry_after(Duration::from_secs(5)));
+
+    let request = Request::post(format!(
+        "{}/v1/projects/test-project/messages:send",
+        emulator.url()
+    ))
+    .header(
+        "authorization",
+        format!("Bearer {}", emulator.access_token()),
+    )
+    .body(Body::from(
+        json!({"message": {"token": "device"}}).to_string(),
+    ))
+    .unwrap();
+
+    let response = hyper::Client::new().request(request).await.unwrap();
+
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+    assert_eq!("5", response.headers()["retry-after"]);
+}
+
+#[tokio::test]
+async fn should_fail_sends_to_unregistered_tokens() {
+    let emulator = Emulator::start();
+    emulator.fail_token("dead", EmulatedError::Unregistered);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let mut builder = emulator.client_builder("test-project");
+    {
+        let events = events.clone();
+        builder.token_feedback(move |event| events.lock().unwrap().push(event));
+    }
+    let client = builder.finalize();
+
+    let message = MessageBuilder::new(emulator.access_token(), "dead").finalize();
+
+    assert_eq!(
+        Err(FcmError::NotFound),
+        client.send(message).await.map(|_| ())
+    );
+    assert_eq!(
+        vec![TokenEvent::Remove(
+            "dead".to_string(),
+            RemovalReason::Unregistered
+        )],
+        *events.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn should_manage_topic_subscriptions() {
+    let emulator = Emulator::start();
+    emulator.fail_token("dead", EmulatedError::Unregistered);
+    let client = emulator.client_builder("test-project").finalize();
+    let access_token = emulator.access_token();
+
+    let results = client
+        .subscribe_to_topic(access_token, "news", &["a", "b", "dead"])
+        .await
+        .unwrap();
+
+    assert_eq!(Some(TopicManagementError::NotFound), results[2].error);
+    assert_eq!(vec!["a", "b"], emulator.subscribers("news"));
+
+    client
+        .unsubscribe_from_topic(access_token, "news", &["a"])
+        .await
+        .unwrap();
+
+    assert_eq!(vec!["b"], emulator.subscribers("/topics/news"));
+
+    let info = client.token_info(access_token, "b").await.unwrap();
+    assert!(info.rel.topics.contains_key("news"));
+
+    assert_eq!(
+        Err(FcmError::NotFound),
+        client.token_info(access_token, "dead").await
+    );
+}
+
+#[tokio::test]
+async fn should_issue_access_tokens_for_jwt_assertions() {
+    let emulator = Emulator::start();
+
+    let (status, body) = post(
+        emulator.token_url(),
+        "application/x-www-form-urlencoded",
+        "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion=a.b.c"
+            .to_string(),
+    )
+    .await;
+
+    assert_eq!(StatusCode::OK, status);
+    assert_eq!("Bearer", body["token_type"]);
+
+    let access_token = body["access_token"].as_str().unwrap();
+    let client = emulator.client_builder("test-project").finalize();
+    let message = MessageBuilder::new(access_token, "device").finalize();
+
+    assert!(client.send(message).await.is_ok());
+    assert_eq!(access_token, emulator.messages()[0].access_token);
+
+    let (status, body) = post(
+        emulator.token_url(),
+        "application/x-www-form-urlencoded",
+        "grant_type=client_credentials".to_string(),
+    )
+    .await;
+
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert_eq!("invalid_grant", body["error"]);
+}
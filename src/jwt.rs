@@ -0,0 +1,24 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use serde::Serialize;
+
+/// Sign a compact JSON web token with ES256, as used by both VAPID and APNs
+/// token based authentication.
+pub(crate) fn sign_es256<H, C>(key: &SigningKey, header: &H, claims: &C) -> String
+where
+    H: Serialize,
+    C: Serialize,
+{
+    let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(header).unwrap());
+    let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap());
+    let message = format!("{}.{}", header, claims);
+
+    let signature: Signature = key.sign(message.as_bytes());
+
+    format!(
+        "{}.{}",
+        message,
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    )
+}
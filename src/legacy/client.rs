@@ -0,0 +1,238 @@
+use chrono::Utc;
+use futures::stream::StreamExt;
+use http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use hyper::{
+    client::{Client as HttpClient, HttpConnector},
+    Body, Request, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+
+use super::LegacyMessage;
+use crate::client::{dispatch, legacy_events, FCM_URL};
+use crate::store::{TokenStore, TokenStoreError};
+use crate::{ErrorReason, FcmError, FcmResponse, RetryAfter, TokenFeedback};
+
+/// The legacy API accepts at most this many registration ids per message.
+const MULTICAST_SIZE: usize = 1000;
+
+/// A builder to get a `LegacyClient` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::LegacyClientBuilder;
+///
+/// let mut builder = LegacyClientBuilder::new();
+/// builder.fcm_url("http://localhost:8080");
+/// let client = builder.finalize();
+/// ```
+pub struct LegacyClientBuilder {
+    fcm_url: String,
+    token_feedback: Option<Box<dyn TokenFeedback>>,
+    token_store: Option<Box<dyn TokenStore>>,
+}
+
+impl Default for LegacyClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LegacyClientBuilder {
+    /// Get a new `LegacyClientBuilder`.
+    pub fn new() -> LegacyClientBuilder {
+        LegacyClientBuilder {
+            fcm_url: FCM_URL.to_string(),
+            token_feedback: None,
+            token_store: None,
+        }
+    }
+
+    /// The scheme and host of the FCM API, defaults to `FCM_URL`.
+    pub fn fcm_url(&mut self, fcm_url: &str) -> &mut Self {
+        self.fcm_url = fcm_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// A hook to be told about canonical registration ids and registration
+    /// tokens FCM reported as no longer valid.
+    pub fn token_feedback<F>(&mut self, token_feedback: F) -> &mut Self
+    where
+        F: TokenFeedback + 'static,
+    {
+        self.token_feedback = Some(Box::new(token_feedback));
+        self
+    }
+
+    /// The store to resolve the tokens of `send_to_user` from. Canonical
+    /// registration ids and tokens FCM reports as no longer valid are
+    /// updated in it after any send.
+    pub fn token_store<S>(&mut self, token_store: S) -> &mut Self
+    where
+        S: TokenStore + 'static,
+    {
+        self.token_store = Some(Box::new(token_store));
+        self
+    }
+
+    /// Complete the build and get a `LegacyClient` instance
+    pub fn finalize(self) -> LegacyClient {
+        let mut http_client = HttpClient::builder();
+        http_client.pool_max_idle_per_host(usize::MAX);
+
+        LegacyClient {
+            fcm_url: self.fcm_url,
+            token_feedback: self.token_feedback,
+            token_store: self.token_store,
+            http_client: http_client.build(HttpsConnector::new()),
+        }
+    }
+}
+
+/// An async client for the legacy HTTP API, authenticating with a server key
+/// instead of an OAuth 2.0 access token. Meant for projects still migrating
+/// to the v1 API of `Client`.
+///
+/// # Examples:
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use fcm::{LegacyClient, LegacyMessageBuilder, LegacyNotificationBuilder};
+///
+/// let client = LegacyClient::new();
+///
+/// let mut notification = LegacyNotificationBuilder::new();
+/// notification.title("Hey!");
+///
+/// let mut builder = LegacyMessageBuilder::new("<server key>", "<registration id>");
+/// builder.notification(notification.finalize());
+///
+/// let response = client.send(builder.finalize()).await?;
+/// println!("Sent: {:?}", response);
+/// # Ok(())
+/// # }
+/// ```
+pub struct LegacyClient {
+    fcm_url: String,
+    token_feedback: Option<Box<dyn TokenFeedback>>,
+    token_store: Option<Box<dyn TokenStore>>,
+    http_client: HttpClient<HttpsConnector<HttpConnector>>,
+}
+
+impl Default for LegacyClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LegacyClient {
+    /// Get a new instance of LegacyClient.
+    pub fn new() -> LegacyClient {
+        LegacyClientBuilder::new().finalize()
+    }
+
+    /// Try sending a `LegacyMessage` to FCM. Errors of individual recipients
+    /// and canonical registration ids are reported in the `results` of the
+    /// response.
+    pub async fn send(&self, message: LegacyMessage<'_>) -> Result<FcmResponse, FcmError> {
+        let payload = serde_json::to_vec(&message.body).unwrap();
+
+        let request = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                CONTENT_LENGTH,
+                format!("{}", payload.len() as u64).as_bytes(),
+            )
+            .header(AUTHORIZATION, format!("key={}", message.api_key).as_bytes())
+            .uri(format!("{}/fcm/send", self.fcm_url))
+            .body(Body::from(payload))
+            .unwrap();
+
+        let response = self.http_client.request(request).await?;
+        let response_status = response.status();
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|ra| ra.to_str().ok())
+            .and_then(RetryAfter::from_str);
+
+        let mut body: Vec<u8> = Vec::new();
+        let mut chunks = response.into_body();
+
+        while let Some(chunk) = chunks.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+
+        match response_status {
+            StatusCode::OK => {
+                let fcm_response: FcmResponse = serde_json::from_slice(&body)
+                    .map_err(|_| FcmError::InvalidMessage("Invalid Response".to_string()))?;
+
+                match fcm_response.error {
+                    Some(ErrorReason::Unavailable) | Some(ErrorReason::InternalServerError) => {
+                        Err(FcmError::ServerError(retry_after))
+                    }
+                    _ => {
+                        for event in legacy_events(&message.body.recipients(), &fcm_response) {
+                            dispatch(
+                                self.token_feedback.as_deref(),
+                                self.token_store.as_deref(),
+                                event,
+                            );
+                        }
+
+                        Ok(fcm_response)
+                    }
+                }
+            }
+            StatusCode::UNAUTHORIZED => Err(FcmError::Unauthorized),
+            StatusCode::BAD_REQUEST => Err(FcmError::InvalidMessage(
+                String::from_utf8_lossy(&body).into_owned(),
+            )),
+            status if status.is_server_error() => Err(FcmError::ServerError(retry_after)),
+            _ => Err(FcmError::InvalidMessage("Unknown Error".to_string())),
+        }
+    }
+
+    /// Send the message to every token the `TokenStore` has for the user,
+    /// multicasting to up to 1000 tokens per request. The recipients of the
+    /// message are replaced by the tokens. Delivered tokens are recorded in
+    /// the store, canonical ids and unregistered tokens updated in it.
+    ///
+    /// Fails if no store was configured with
+    /// `LegacyClientBuilder::token_store` or it couldn't be read, and stops
+    /// at the first request that fails.
+    pub async fn send_to_user(
+        &self,
+        user_id: &str,
+        message: LegacyMessage<'_>,
+    ) -> Result<Vec<FcmResponse>, FcmError> {
+        let token_store = self.token_store.as_deref().ok_or_else(|| {
+            FcmError::TokenStore(TokenStoreError("no token store configured".to_string()))
+        })?;
+
+        let tokens = token_store.tokens(user_id).map_err(FcmError::TokenStore)?;
+        let mut responses = Vec::new();
+
+        for chunk in tokens.chunks(MULTICAST_SIZE) {
+            let chunk: Vec<&str> = chunk.iter().map(|t| t.as_str()).collect();
+            let response = self.send(message.with_recipients(&chunk)).await?;
+
+            if let Some(ref results) = response.results {
+                for (token, result) in chunk.iter().zip(results) {
+                    if result.message_id.is_some() && result.registration_id.is_none() {
+                        if let Err(e) = token_store.record_success(token, Utc::now()) {
+                            log::warn!("Couldn't update the token store: {}", e);
+                        }
+                    }
+                }
+            }
+
+            responses.push(response);
+        }
+
+        Ok(responses)
+    }
+}
@@ -0,0 +1,578 @@
+mod client;
+
+pub use self::client::*;
+
+#[cfg(test)]
+mod tests;
+
+use std::borrow::Cow;
+use std::fmt;
+
+use erased_serde::Serialize;
+use serde_json::{self, Map, Value};
+
+use crate::message::Priority;
+use crate::owned::{owned, owned_vec};
+use crate::de::unknown_fields;
+use crate::redact::{self, Secret};
+
+/// A notification in the format of the legacy HTTP API. Use the
+/// corresponding `LegacyNotificationBuilder` to get an instance.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LegacyNotification<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android_channel_id: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    badge: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_loc_args: Option<Vec<Cow<'a, str>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body_loc_key: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    click_action: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sound: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_loc_args: Option<Vec<Cow<'a, str>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_loc_key: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> LegacyNotification<'a> {
+    /// Convert into a notification owning its strings.
+    pub fn into_owned(self) -> LegacyNotification<'static> {
+        LegacyNotification {
+            android_channel_id: self.android_channel_id.map(owned),
+            badge: self.badge.map(owned),
+            body: self.body.map(owned),
+            body_loc_args: self.body_loc_args.map(owned_vec),
+            body_loc_key: self.body_loc_key.map(owned),
+            click_action: self.click_action.map(owned),
+            color: self.color.map(owned),
+            icon: self.icon.map(owned),
+            image: self.image.map(owned),
+            sound: self.sound.map(owned),
+            tag: self.tag.map(owned),
+            title: self.title.map(owned),
+            title_loc_args: self.title_loc_args.map(owned_vec),
+            title_loc_key: self.title_loc_key.map(owned),
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// A builder to get a `LegacyNotification` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::LegacyNotificationBuilder;
+///
+/// let mut builder = LegacyNotificationBuilder::new();
+/// builder.title("Australia vs New Zealand");
+/// builder.body("3 runs to win in 1 ball");
+/// builder.sound("default");
+/// let notification = builder.finalize();
+/// ```
+#[derive(Default)]
+pub struct LegacyNotificationBuilder<'a> {
+    android_channel_id: Option<Cow<'a, str>>,
+    badge: Option<Cow<'a, str>>,
+    body: Option<Cow<'a, str>>,
+    body_loc_args: Option<Vec<Cow<'a, str>>>,
+    body_loc_key: Option<Cow<'a, str>>,
+    click_action: Option<Cow<'a, str>>,
+    color: Option<Cow<'a, str>>,
+    icon: Option<Cow<'a, str>>,
+    image: Option<Cow<'a, str>>,
+    sound: Option<Cow<'a, str>>,
+    tag: Option<Cow<'a, str>>,
+    title: Option<Cow<'a, str>>,
+    title_loc_args: Option<Vec<Cow<'a, str>>>,
+    title_loc_key: Option<Cow<'a, str>>,
+}
+
+impl<'a> LegacyNotificationBuilder<'a> {
+    /// Get a new `LegacyNotificationBuilder` instance.
+    pub fn new() -> LegacyNotificationBuilder<'a> {
+        LegacyNotificationBuilder::default()
+    }
+
+    /// The notification channel on Android O and newer.
+    pub fn android_channel_id(&mut self, android_channel_id: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.android_channel_id = Some(android_channel_id.into());
+        self
+    }
+
+    /// The value of the badge on the home screen app icon, iOS only.
+    pub fn badge(&mut self, badge: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.badge = Some(badge.into());
+        self
+    }
+
+    /// The notification's body text.
+    pub fn body(&mut self, body: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// The arguments for the format specifiers of `body_loc_key`.
+    pub fn body_loc_args<S>(&mut self, body_loc_args: &'a [S]) -> &mut Self
+    where
+        S: AsRef<str>,
+    {
+        self.body_loc_args = Some(body_loc_args.iter().map(|s| s.as_ref().into()).collect());
+        self
+    }
+
+    /// The key to the body string in the app's string resources.
+    pub fn body_loc_key(&mut self, body_loc_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body_loc_key = Some(body_loc_key.into());
+        self
+    }
+
+    /// The action associated with a user click on the notification.
+    pub fn click_action(&mut self, click_action: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.click_action = Some(click_action.into());
+        self
+    }
+
+    /// The notification's icon color in `#rrggbb` format, Android only.
+    pub fn color(&mut self, color: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.color = Some(color.into());
+        self
+    }
+
+    /// The drawable resource of the notification's icon, Android only.
+    pub fn icon(&mut self, icon: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// The URL of an image to be downloaded and displayed in the
+    /// notification.
+    pub fn image(&mut self, image: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// The sound to play when the device receives the notification.
+    pub fn sound(&mut self, sound: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.sound = Some(sound.into());
+        self
+    }
+
+    /// Notifications with the same tag replace each other in the
+    /// notification drawer, Android only.
+    pub fn tag(&mut self, tag: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// The notification's title.
+    pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// The arguments for the format specifiers of `title_loc_key`.
+    pub fn title_loc_args<S>(&mut self, title_loc_args: &'a [S]) -> &mut Self
+    where
+        S: AsRef<str>,
+    {
+        self.title_loc_args = Some(title_loc_args.iter().map(|s| s.as_ref().into()).collect());
+        self
+    }
+
+    /// The key to the title string in the app's string resources.
+    pub fn title_loc_key(&mut self, title_loc_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title_loc_key = Some(title_loc_key.into());
+        self
+    }
+
+    /// Complete the build and get a `LegacyNotification` instance
+    pub fn finalize(self) -> LegacyNotification<'a> {
+        LegacyNotification {
+            android_channel_id: self.android_channel_id,
+            badge: self.badge,
+            body: self.body,
+            body_loc_args: self.body_loc_args,
+            body_loc_key: self.body_loc_key,
+            click_action: self.click_action,
+            color: self.color,
+            icon: self.icon,
+            image: self.image,
+            sound: self.sound,
+            tag: self.tag,
+            title: self.title,
+            title_loc_args: self.title_loc_args,
+            title_loc_key: self.title_loc_key,
+            unknown: Map::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct LegacyMessageBody<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_key: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    condition: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_available: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dry_run: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mutable_content: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification: Option<LegacyNotification<'a>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<Priority>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registration_ids: Option<Vec<Cow<'a, str>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restricted_package_name: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_to_live: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> LegacyMessage<'a> {
+    /// Convert into a message owning all of its strings, which can be moved
+    /// into a spawned task or sent over a channel.
+    pub fn into_owned(self) -> LegacyMessage<'static> {
+        LegacyMessage {
+            api_key: owned(self.api_key),
+            body: self.body.into_owned(),
+        }
+    }
+
+    /// A copy of the message multicast to the tokens instead of its own
+    /// recipients.
+    pub(crate) fn with_recipients<'b>(&self, tokens: &[&'b str]) -> LegacyMessage<'b>
+    where
+        'a: 'b,
+    {
+        let mut message: LegacyMessage<'b> = self.clone();
+        message.body.condition = None;
+        message.body.to = None;
+        message.body.registration_ids = Some(tokens.iter().map(|t| Cow::Borrowed(*t)).collect());
+        message
+    }
+}
+
+impl<'a> LegacyMessageBody<'a> {
+    /// Convert into a message body owning its strings.
+    pub fn into_owned(self) -> LegacyMessageBody<'static> {
+        LegacyMessageBody {
+            collapse_key: self.collapse_key.map(owned),
+            condition: self.condition.map(owned),
+            content_available: self.content_available,
+            data: self.data,
+            dry_run: self.dry_run,
+            mutable_content: self.mutable_content,
+            notification: self.notification.map(|n| n.into_owned()),
+            priority: self.priority,
+            registration_ids: self.registration_ids.map(owned_vec),
+            restricted_package_name: self.restricted_package_name.map(owned),
+            time_to_live: self.time_to_live,
+            to: self.to.map(owned),
+            unknown: self.unknown,
+        }
+    }
+
+    /// The registration tokens the message is sent to, in the order of the
+    /// results of the response. Empty for topics and conditions.
+    pub(crate) fn recipients(&self) -> Vec<&str> {
+        match (self.to.as_deref(), self.registration_ids.as_ref()) {
+            (Some(to), _) if !to.starts_with("/topics/") => vec![to],
+            (_, Some(ids)) => ids.iter().map(|id| id.as_ref()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Cuts down the registration tokens, see `Unredacted`.
+impl fmt::Debug for LegacyMessageBody<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LegacyMessageBody")
+            .field("collapse_key", &self.collapse_key)
+            .field("condition", &self.condition)
+            .field("content_available", &self.content_available)
+            .field("data", &self.data)
+            .field("dry_run", &self.dry_run)
+            .field("mutable_content", &self.mutable_content)
+            .field("notification", &self.notification)
+            .field("priority", &self.priority)
+            .field(
+                "registration_ids",
+                &self.registration_ids.as_deref().map(redact::tokens),
+            )
+            .field("restricted_package_name", &self.restricted_package_name)
+            .field("time_to_live", &self.time_to_live)
+            .field("to", &redact::token(&self.to))
+            .field("unknown", &self.unknown)
+            .finish()
+    }
+}
+
+/// A message for the legacy HTTP API, sent with a `LegacyClient`. Construct
+/// it with a `LegacyMessageBuilder`.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::LegacyMessageBuilder;
+///
+/// let mut builder = LegacyMessageBuilder::new("<server key>", "<registration id>");
+/// builder.dry_run(true);
+/// let message = builder.finalize();
+/// ```
+#[derive(Clone)]
+pub struct LegacyMessage<'a> {
+    pub api_key: Cow<'a, str>,
+    pub body: LegacyMessageBody<'a>,
+}
+
+/// Redacts the API key and cuts down the registration tokens, see
+/// `Unredacted`.
+impl fmt::Debug for LegacyMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LegacyMessage")
+            .field("api_key", &Secret(&self.api_key))
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+/// A builder to get a `LegacyMessage` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::{LegacyMessageBuilder, Priority};
+///
+/// let ids = vec!["<registration id 1>", "<registration id 2>"];
+/// let mut builder = LegacyMessageBuilder::new_multi("<server key>", &ids);
+/// builder.priority(Priority::High);
+/// builder.time_to_live(3600);
+/// let message = builder.finalize();
+/// ```
+pub struct LegacyMessageBuilder<'a> {
+    api_key: Cow<'a, str>,
+    collapse_key: Option<Cow<'a, str>>,
+    condition: Option<Cow<'a, str>>,
+    content_available: Option<bool>,
+    data: Option<Value>,
+    dry_run: Option<bool>,
+    mutable_content: Option<bool>,
+    notification: Option<LegacyNotification<'a>>,
+    priority: Option<Priority>,
+    registration_ids: Option<Vec<Cow<'a, str>>>,
+    restricted_package_name: Option<Cow<'a, str>>,
+    time_to_live: Option<u32>,
+    to: Option<Cow<'a, str>>,
+}
+
+impl<'a> LegacyMessageBuilder<'a> {
+    /// Get a new instance of LegacyMessage, sent to a registration token,
+    /// notification key or `/topics/` prefixed topic.
+    pub fn new(api_key: impl Into<Cow<'a, str>>, to: impl Into<Cow<'a, str>>) -> Self {
+        LegacyMessageBuilder {
+            api_key: api_key.into(),
+            collapse_key: None,
+            condition: None,
+            content_available: None,
+            data: None,
+            dry_run: None,
+            mutable_content: None,
+            notification: None,
+            priority: None,
+            registration_ids: None,
+            restricted_package_name: None,
+            time_to_live: None,
+            to: Some(to.into()),
+        }
+    }
+
+    /// Get a new instance of LegacyMessage, multicast to up to 1000
+    /// registration tokens.
+    pub fn new_multi<S>(api_key: impl Into<Cow<'a, str>>, ids: &'a [S]) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let mut builder = Self::new(api_key, "");
+        builder.to = None;
+        builder.registration_ids = Some(ids.iter().map(|id| id.as_ref().into()).collect());
+        builder
+    }
+
+    /// Identifies a group of messages of which only the last one is
+    /// delivered when the device comes back online.
+    pub fn collapse_key(&mut self, collapse_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.collapse_key = Some(collapse_key.into());
+        self
+    }
+
+    /// Condition to send a message to, e.g. "'foo' in topics && 'bar' in topics".
+    /// Replaces the recipients given to the constructor.
+    pub fn condition(&mut self, condition: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.to = None;
+        self.registration_ids = None;
+        self.condition = Some(condition.into());
+        self
+    }
+
+    /// On iOS, wake the app in the background when the message arrives.
+    pub fn content_available(&mut self, content_available: bool) -> &mut Self {
+        self.content_available = Some(content_available);
+        self
+    }
+
+    /// Use this to add custom key-value pairs to the message. The data can be
+    /// anything that Serde can serialize to JSON.
+    pub fn data(&mut self, data: &dyn Serialize) -> Result<&mut Self, serde_json::Error> {
+        self.data = Some(serde_json::to_value(data)?);
+        Ok(self)
+    }
+
+    /// When set to `true`, allows you to test FCM without actually sending the message.
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = Some(dry_run);
+        self
+    }
+
+    /// On iOS, let a notification service extension modify the notification
+    /// before it's displayed.
+    pub fn mutable_content(&mut self, mutable_content: bool) -> &mut Self {
+        self.mutable_content = Some(mutable_content);
+        self
+    }
+
+    /// Use this to set a `LegacyNotification` for the message.
+    pub fn notification(&mut self, notification: LegacyNotification<'a>) -> &mut Self {
+        self.notification = Some(notification);
+        self
+    }
+
+    /// The priority of the message, `Normal` or `High`.
+    pub fn priority(&mut self, priority: Priority) -> &mut Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Only deliver the message to apps with this Android package name.
+    pub fn restricted_package_name(
+        &mut self,
+        restricted_package_name: impl Into<Cow<'a, str>>,
+    ) -> &mut Self {
+        self.restricted_package_name = Some(restricted_package_name.into());
+        self
+    }
+
+    /// How long in seconds FCM keeps the message while the device is
+    /// offline, at most 2419200 (four weeks).
+    pub fn time_to_live(&mut self, time_to_live: u32) -> &mut Self {
+        self.time_to_live = Some(time_to_live);
+        self
+    }
+
+    /// Complete the build and get a `LegacyMessage` instance
+    pub fn finalize(self) -> LegacyMessage<'a> {
+        LegacyMessage {
+            api_key: self.api_key,
+            body: LegacyMessageBody {
+                collapse_key: self.collapse_key,
+                condition: self.condition,
+                content_available: self.content_available,
+                data: self.data,
+                dry_run: self.dry_run,
+                mutable_content: self.mutable_content,
+                notification: self.notification,
+                priority: self.priority,
+                registration_ids: self.registration_ids,
+                restricted_package_name: self.restricted_package_name,
+                time_to_live: self.time_to_live,
+                to: self.to,
+                unknown: Map::new(),
+            },
+        }
+    }
+}
+
+/// Redacts the API key and cuts down the registration tokens, see
+/// `Unredacted`.
+impl fmt::Debug for LegacyMessageBuilder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LegacyMessageBuilder")
+            .field("api_key", &Secret(&self.api_key))
+            .field("collapse_key", &self.collapse_key)
+            .field("condition", &self.condition)
+            .field("content_available", &self.content_available)
+            .field("data", &self.data)
+            .field("dry_run", &self.dry_run)
+            .field("mutable_content", &self.mutable_content)
+            .field("notification", &self.notification)
+            .field("priority", &self.priority)
+            .field(
+                "registration_ids",
+                &self.registration_ids.as_deref().map(redact::tokens),
+            )
+            .field("restricted_package_name", &self.restricted_package_name)
+            .field("time_to_live", &self.time_to_live)
+            .field("to", &redact::token(&self.to))
+            .finish()
+    }
+}
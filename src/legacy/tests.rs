@@ -0,0 +1,285 @@
+use crate::testing;
+use crate::{
+    ErrorReason, FcmError, LegacyClient, LegacyClientBuilder, LegacyMessageBuilder,
+    LegacyNotificationBuilder, MemoryTokenStore, Priority, RemovalReason, TokenEvent, TokenStore,
+};
+use hyper::{Body, Response, StatusCode};
+use std::sync::{Arc, Mutex};
+
+fn client(addr: std::net::SocketAddr) -> LegacyClient {
+    let mut builder = LegacyClientBuilder::new();
+    builder.fcm_url(&format!("http://{}", addr));
+    builder.finalize()
+}
+
+#[test]
+fn should_leave_nones_out_of_the_json() {
+    let msg = LegacyMessageBuilder::new("api_key", "token").finalize();
+    let payload = serde_json::to_value(&msg.body).unwrap();
+
+    assert_eq!(json!({"to": "token"}), payload);
+}
+
+#[test]
+fn should_render_a_full_legacy_message_to_json() {
+    let mut notification = LegacyNotificationBuilder::new();
+
+    notification
+        .title("foo")
+        .body("bar")
+        .icon("gif")
+        .sound("pling")
+        .badge("12")
+        .tag("spook")
+        .color("#666666")
+        .click_action("spam")
+        .android_channel_id("news")
+        .body_loc_key("PLAY")
+        .body_loc_args(&["foo", "bar"]);
+
+    let mut builder = LegacyMessageBuilder::new("api_key", "/topics/news");
+
+    builder
+        .notification(notification.finalize())
+        .priority(Priority::High)
+        .time_to_live(3600)
+        .collapse_key("scores")
+        .content_available(true)
+        .restricted_package_name("com.example");
+
+    builder.data(&json!({"score": "3-2"})).unwrap();
+
+    let payload = serde_json::to_value(&builder.finalize().body).unwrap();
+
+    let expected_payload = json!({
+        "collapse_key": "scores",
+        "content_available": true,
+        "data": {"score": "3-2"},
+        "notification": {
+            "android_channel_id": "news",
+            "badge": "12",
+            "body": "bar",
+            "body_loc_args": ["foo", "bar"],
+            "body_loc_key": "PLAY",
+            "click_action": "spam",
+            "color": "#666666",
+            "icon": "gif",
+            "sound": "pling",
+            "tag": "spook",
+            "title": "foo"
+        },
+        "priority": "high",
+        "restricted_package_name": "com.example",
+        "time_to_live": 3600,
+        "to": "/topics/news"
+    });
+
+    assert_eq!(expected_payload, payload);
+}
+
+#[test]
+fn should_redact_the_api_key_and_tokens_in_debug_output() {
+    let ids = vec!["fGx8Kk2pQ0:APA91bHun4", "cQ1mZ7xW:APA91bEr8t"];
+    let message = LegacyMessageBuilder::new_multi("AAAA-server-key", &ids).finalize();
+    let output = format!("{:?}", message);
+
+    assert!(output.contains("api_key: <redacted>"), "{}", output);
+    assert!(
+        output.contains("registration_ids: Some([\"fGx8Kk2p...\", \"cQ1mZ7xW...\"])"),
+        "{}",
+        output
+    );
+    assert!(!output.contains("AAAA-server-key"), "{}", output);
+}
+
+#[test]
+fn should_multicast_to_registration_ids() {
+    let ids = vec!["token1".to_string(), "token2".to_string()];
+    let msg = LegacyMessageBuilder::new_multi("api_key", &ids).finalize();
+    let payload = serde_json::to_value(&msg.body).unwrap();
+
+    assert_eq!(json!({"registration_ids": ["token1", "token2"]}), payload);
+}
+
+#[test]
+fn should_replace_the_recipient_with_a_condition() {
+    let mut builder = LegacyMessageBuilder::new("api_key", "token");
+    builder.condition("'foo' in topics");
+    let payload = serde_json::to_value(&builder.finalize().body).unwrap();
+
+    assert_eq!(json!({"condition": "'foo' in topics"}), payload);
+}
+
+#[tokio::test]
+async fn should_send_a_legacy_message_with_the_server_key() {
+    let addr = testing::serve(|parts, body| {
+        assert_eq!("POST", parts.method);
+        assert_eq!("/fcm/send", parts.uri.path());
+        assert_eq!("key=server-key", parts.headers["authorization"]);
+
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json!(["token1", "token2"]), body["registration_ids"]);
+
+        Response::new(Body::from(
+            json!({
+                "multicast_id": 216,
+                "success": 1,
+                "failure": 1,
+                "canonical_ids": 1,
+                "results": [
+                    {"message_id": "1:0408", "registration_id": "token3"},
+                    {"error": "NotRegistered"}
+                ]
+            })
+            .to_string(),
+        ))
+    });
+
+    let ids = ["token1", "token2"];
+    let message = LegacyMessageBuilder::new_multi("server-key", &ids).finalize();
+    let response = client(addr).send(message).await.unwrap();
+
+    assert_eq!(Some(216), response.multicast_id);
+    assert_eq!(Some(1), response.canonical_ids);
+
+    let results = response.results.unwrap();
+    assert_eq!(Some("token3".to_string()), results[0].registration_id);
+    assert_eq!(Some(ErrorReason::NotRegistered), results[1].error);
+}
+
+#[tokio::test]
+async fn should_map_legacy_errors() {
+    let addr = testing::serve(|parts, _| {
+        let (status, body) = match parts.headers["authorization"].to_str().unwrap() {
+            "key=bad" => (StatusCode::UNAUTHORIZED, String::new()),
+            "key=busy" => (StatusCode::OK, json!({"error": "Unavailable"}).to_string()),
+            _ => (StatusCode::SERVICE_UNAVAILABLE, String::new()),
+        };
+
+        Response::builder()
+            .status(status)
+            .header("Retry-After", "30")
+            .body(Body::from(body))
+            .unwrap()
+    });
+
+    let client = client(addr);
+
+    assert_eq!(
+        Err(FcmError::Unauthorized),
+        client
+            .send(LegacyMessageBuilder::new("bad", "token").finalize())
+            .await
+            .map(|_| ())
+    );
+
+    for key in &["busy", "good"] {
+        assert!(matches!(
+            client
+                .send(LegacyMessageBuilder::new(*key, "token").finalize())
+                .await,
+            Err(FcmError::ServerError(Some(_)))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn should_report_canonical_ids_and_dead_tokens() {
+    let addr = testing::serve(|_, _| {
+        Response::new(Body::from(
+            json!({
+                "multicast_id": 216,
+                "success": 2,
+                "failure": 2,
+                "canonical_ids": 1,
+                "results": [
+                    {"message_id": "1:0408", "registration_id": "token5"},
+                    {"error": "NotRegistered"},
+                    {"message_id": "1:0409"},
+                    {"error": "Unavailable"}
+                ]
+            })
+            .to_string(),
+        ))
+    });
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+
+    let mut builder = LegacyClientBuilder::new();
+    builder.fcm_url(&format!("http://{}", addr));
+    builder.token_feedback(move |event| sink.lock().unwrap().push(event));
+    let client = builder.finalize();
+
+    let ids = ["token1", "token2", "token3", "token4"];
+    let message = LegacyMessageBuilder::new_multi("server-key", &ids).finalize();
+    client.send(message).await.unwrap();
+
+    assert_eq!(
+        vec![
+            TokenEvent::Replace("token1".to_string(), "token5".to_string()),
+            TokenEvent::Remove("token2".to_string(), RemovalReason::Unregistered),
+        ],
+        *events.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn should_multicast_to_all_tokens_of_a_user() {
+    let addr = testing::serve(|_, body| {
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(None, body.get("to"));
+        assert_eq!(
+            json!(["token1", "token2", "token3"]),
+            body["registration_ids"]
+        );
+
+        Response::new(Body::from(
+            json!({
+                "multicast_id": 216,
+                "success": 2,
+                "failure": 1,
+                "canonical_ids": 1,
+                "results": [
+                    {"message_id": "1:0408"},
+                    {"message_id": "1:0409", "registration_id": "token4"},
+                    {"error": "NotRegistered"}
+                ]
+            })
+            .to_string(),
+        ))
+    });
+
+    let store = Arc::new(MemoryTokenStore::new());
+    store.add_token("alice", "token1").unwrap();
+    store.add_token("alice", "token2").unwrap();
+    store.add_token("alice", "token3").unwrap();
+
+    let mut builder = LegacyClientBuilder::new();
+    builder.fcm_url(&format!("http://{}", addr));
+    builder.token_store(store.clone());
+    let client = builder.finalize();
+
+    let message = LegacyMessageBuilder::new("server-key", "ignored").finalize();
+    let responses = client.send_to_user("alice", message).await.unwrap();
+
+    assert_eq!(1, responses.len());
+    assert_eq!(vec!["token1", "token4"], store.tokens("alice").unwrap());
+    assert!(store.last_success("token1").unwrap().is_some());
+}
+
+#[test]
+fn should_convert_a_legacy_message_into_an_owned_one() {
+    let ids = vec!["token1".to_string(), "token2".to_string()];
+    let mut builder = LegacyMessageBuilder::new_multi(String::from("server-key"), &ids);
+    builder.collapse_key(format!("scores-{}", 42));
+
+    let message: crate::LegacyMessage<'static> = builder.finalize().into_owned();
+    drop(ids);
+
+    assert_eq!("server-key", message.api_key);
+    assert_eq!(
+        json!({"collapse_key": "scores-42", "registration_ids": ["token1", "token2"]}),
+        serde_json::to_value(&message.body).unwrap()
+    );
+}
@@ -12,13 +12,13 @@
 //! # use std::collections::HashMap;
 //! # #[tokio::main]
 //! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-//! let client = fcm::Client::new();
+//! let client = fcm::Client::new("<Firebase project id>".to_string());
 //!
 //! let mut map = HashMap::new();
 //! map.insert("message", "Howdy!");
 //!
 //! let mut builder = fcm::MessageBuilder::new("<FCM API Key>", "<registration id>");
-//! builder.data(&map);
+//! builder.data(map);
 //!
 //! let response = client.send(builder.finalize()).await?;
 //! println!("Sent: {:?}", response);
@@ -42,7 +42,7 @@
 //! ```no_run
 //! # #[tokio::main]
 //! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-//! let client = fcm::Client::new();
+//! let client = fcm::Client::new("<Firebase project id>".to_string());
 //!
 //! let mut notification_builder = fcm::NotificationBuilder::new();
 //! notification_builder.title("Hey!");
@@ -71,8 +71,28 @@ mod notification;
 pub use crate::notification::*;
 mod client;
 mod android;
+pub use crate::android::*;
 mod apns;
+pub use crate::apns::*;
 mod webpush;
+pub use crate::webpush::*;
+mod legacy;
+pub use crate::legacy::*;
+mod store;
+pub use crate::store::*;
+#[cfg(feature = "emulator")]
+mod emulator;
+#[cfg(feature = "emulator")]
+pub use crate::emulator::*;
+mod jwt;
+mod owned;
+mod de;
+pub use crate::de::{from_str, from_value, UnknownFields};
+mod redact;
+pub use crate::redact::Unredacted;
+
+#[cfg(test)]
+mod testing;
 
 pub use crate::client::*;
 
@@ -0,0 +1,140 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+
+use erased_serde::Serialize;
+use serde::de::Error;
+use serde_json::{self, Value};
+
+/// The custom data of a message, a map of strings to strings as the v1 API
+/// requires. Used for `MessageBody`, `AndroidConfig` and `WebpushConfig`.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::DataMap;
+///
+/// let mut data = DataMap::new();
+/// data.insert("score", "3-1");
+///
+/// let flat = DataMap::flatten(&serde_json::json!({
+///     "score": "3-1",
+///     "minute": 90,
+///     "scorers": ["Hansen", "Berg"]
+/// }))
+/// .unwrap();
+///
+/// assert_eq!(Some("90"), flat.get("minute"));
+/// assert_eq!(Some(r#"["Hansen","Berg"]"#), flat.get("scorers"));
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+#[serde(transparent)]
+pub struct DataMap(BTreeMap<String, String>);
+
+impl DataMap {
+    /// Get a new, empty `DataMap`.
+    pub fn new() -> DataMap {
+        DataMap::default()
+    }
+
+    /// Anything Serde serializes to a JSON object of strings. Fails for
+    /// other values, such as numbers or nested objects.
+    pub fn from_serialize(data: &dyn Serialize) -> Result<DataMap, serde_json::Error> {
+        serde_json::from_value(serde_json::to_value(data)?)
+    }
+
+    /// Anything Serde serializes to a JSON object, with numbers and booleans
+    /// turned into their string form and nested arrays and objects JSON
+    /// encoded. A `null` becomes `"null"`.
+    pub fn flatten(data: &dyn Serialize) -> Result<DataMap, serde_json::Error> {
+        match serde_json::to_value(data)? {
+            Value::Object(data) => Ok(data
+                .into_iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect()),
+            _ => Err(serde_json::Error::custom("data must be a JSON object")),
+        }
+    }
+
+    /// Set the value of the key, returning the previous one.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.0.insert(key.into(), value.into())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The keys and values, ordered by key.
+    pub fn iter(&self) -> btree_map::Iter<'_, String, String> {
+        self.0.iter()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for DataMap
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        DataMap(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<K, V, S> From<HashMap<K, V, S>> for DataMap
+where
+    K: Into<String>,
+    V: Into<String>,
+    S: BuildHasher,
+{
+    fn from(map: HashMap<K, V, S>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<K, V> From<BTreeMap<K, V>> for DataMap
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    fn from(map: BTreeMap<K, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a DataMap {
+    type Item = (&'a String, &'a String);
+    type IntoIter = btree_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for DataMap {
+    type Item = (String, String);
+    type IntoIter = btree_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
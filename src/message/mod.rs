@@ -1,22 +1,33 @@
 use std::borrow::Cow;
+use std::fmt;
 
-use erased_serde::Serialize;
-use serde_json::{self, Value};
+use serde_json::{self, Map, Value};
 
 use crate::notification::Notification;
 use crate::android::AndroidConfig;
+use crate::apns::ApnsConfig;
+use crate::webpush::WebpushConfig;
+use crate::owned::owned;
+use crate::de::unknown_fields;
+use crate::redact::{self, Secret};
+
+mod data;
+mod validate;
+
+pub use self::data::*;
+pub use self::validate::*;
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Serialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Priority {
     Normal,
     High,
 }
 
-#[derive(Serialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum NotificationPriority {
     Unspecified,
@@ -27,43 +38,153 @@ pub enum NotificationPriority {
     Max,
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct FcmOptions<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    analytics_label: Option<&'a str>,
+    analytics_label: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> FcmOptions<'a> {
+    /// Convert into options owning their strings.
+    pub fn into_owned(self) -> FcmOptions<'static> {
+        FcmOptions {
+            analytics_label: self.analytics_label.map(owned),
+            unknown: self.unknown,
+        }
+    }
 }
 
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct MessageBody<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     android: Option<AndroidConfig<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    condition: Option<&'a str>,
+    apns: Option<ApnsConfig<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    data: Option<Value>,
+    condition: Option<Cow<'a, str>>,
 
-    dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<DataMap>,
+
+    /// Only known to the client, not a field of the v1 message: it's kept
+    /// when the message is serialized, but sent as `validate_only` of the
+    /// request instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dry_run: Option<bool>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     fcm_options: Option<FcmOptions<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<&'a str>,
+    name: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     notification: Option<Notification<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    token: Option<&'a str>,
+    pub(crate) token: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    topic: Option<&'a str>,
+    pub(crate) topic: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webpush: Option<WebpushConfig<'a>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> MessageBody<'a> {
+    /// Convert into a message body owning its strings.
+    pub fn into_owned(self) -> MessageBody<'static> {
+        MessageBody {
+            android: self.android.map(|a| a.into_owned()),
+            apns: self.apns.map(|a| a.into_owned()),
+            condition: self.condition.map(owned),
+            data: self.data,
+            dry_run: self.dry_run,
+            fcm_options: self.fcm_options.map(|o| o.into_owned()),
+            name: self.name.map(owned),
+            notification: self.notification.map(|n| n.into_owned()),
+            token: self.token.map(owned),
+            topic: self.topic.map(owned),
+            webpush: self.webpush.map(|w| w.into_owned()),
+            unknown: self.unknown,
+        }
+    }
 
-    //webpush
-    //apns
+    /// What kind of target the message is sent to: `token`, `topic`,
+    /// `condition` or `none`.
+    #[cfg(any(feature = "metrics", feature = "tracing"))]
+    pub(crate) fn target_kind(&self) -> &'static str {
+        if self.token.is_some() {
+            "token"
+        } else if self.topic.is_some() {
+            "topic"
+        } else if self.condition.is_some() {
+            "condition"
+        } else {
+            "none"
+        }
+    }
+
+    /// Check the body for the mistakes FCM would reject it for, see
+    /// `Message::validate`.
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut violations = Violations::new();
+
+        if let Some(ref data) = self.data {
+            violations.check_data("message.data", data);
+        }
+
+        if let Some(ref topic) = self.topic {
+            violations.check_topic("message.topic", topic);
+        }
+
+        if let Some(ref android) = self.android {
+            android.validate("message.android", &mut violations);
+        }
+
+        if let Some(ref webpush) = self.webpush {
+            webpush.validate("message.webpush", &mut violations);
+        }
+
+        violations.into_result()
+    }
+}
+
+/// Cuts down the registration token, see `Unredacted`.
+impl fmt::Debug for MessageBody<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageBody")
+            .field("android", &self.android)
+            .field("apns", &self.apns)
+            .field("condition", &self.condition)
+            .field("data", &self.data)
+            .field("dry_run", &self.dry_run)
+            .field("fcm_options", &self.fcm_options)
+            .field("name", &self.name)
+            .field("notification", &self.notification)
+            .field("token", &redact::token(&self.token))
+            .field("topic", &self.topic)
+            .field("webpush", &self.webpush)
+            .field("unknown", &self.unknown)
+            .finish()
+    }
 }
 
 /// Represents a FCM message. Construct the FCM message
@@ -76,12 +197,81 @@ pub struct MessageBody<'a> {
 /// builder.dry_run(true);
 /// let message = builder.finalize();
 /// ```
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct Message<'a> {
-    pub access_token: &'a str,
+    pub access_token: Cow<'a, str>,
     pub body: MessageBody<'a>,
 }
 
+impl<'a> Message<'a> {
+    /// Convert into a message owning all of its strings, which can be moved
+    /// into a spawned task or sent over a channel.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::{Message, MessageBuilder};
+    ///
+    /// let token = String::from("<registration id>");
+    /// let message: Message<'static> = MessageBuilder::new("<FCM access token>", &token)
+    ///     .finalize()
+    ///     .into_owned();
+    ///
+    /// std::thread::spawn(move || println!("{:?}", message));
+    /// ```
+    pub fn into_owned(self) -> Message<'static> {
+        Message {
+            access_token: owned(self.access_token),
+            body: self.body.into_owned(),
+        }
+    }
+
+    /// Check the message for the mistakes FCM would reject it for: data
+    /// payloads over `MAX_DATA_SIZE` bytes, reserved data keys, invalid
+    /// topic names and a time to live over `MAX_TTL`. All violations found
+    /// are returned.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::{MessageBuilder, ViolationKind};
+    ///
+    /// let mut builder = MessageBuilder::new("<FCM access token>", "<device token>");
+    /// builder.data(vec![("from", "me")].into_iter().collect::<fcm::DataMap>());
+    /// builder.topic("news and weather");
+    ///
+    /// let violations = builder.finalize().validate().unwrap_err();
+    ///
+    /// assert_eq!("message.data.from", violations[0].path);
+    /// assert_eq!(ViolationKind::ReservedDataKey("from".to_string()), violations[0].kind);
+    /// assert_eq!("message.topic", violations[1].path);
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        self.body.validate()
+    }
+
+    /// A copy of the message sent to the token instead of its own target.
+    pub(crate) fn with_token<'b>(&self, token: &'b str) -> Message<'b>
+    where
+        'a: 'b,
+    {
+        let mut message: Message<'b> = self.clone();
+        message.body.condition = None;
+        message.body.topic = None;
+        message.body.token = Some(Cow::Borrowed(token));
+        message
+    }
+}
+
+/// Redacts the access token and cuts down the registration token, see
+/// `Unredacted`.
+impl fmt::Debug for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Message")
+            .field("access_token", &Secret(&self.access_token))
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
 ///
 /// A builder to get a `Message` instance.
 ///
@@ -94,34 +284,37 @@ pub struct Message<'a> {
 /// builder.dry_run(true);
 /// let message = builder.finalize();
 /// ```
-#[derive(Debug)]
 pub struct MessageBuilder<'a> {
-    access_token: &'a str,
+    access_token: Cow<'a, str>,
     android: Option<AndroidConfig<'a>>,
-    condition: Option<&'a str>,
-    data: Option<Value>,
+    apns: Option<ApnsConfig<'a>>,
+    condition: Option<Cow<'a, str>>,
+    data: Option<DataMap>,
     dry_run: Option<bool>,
     fcm_options: Option<FcmOptions<'a>>,
-    name: Option<&'a str>,
+    name: Option<Cow<'a, str>>,
     notification: Option<Notification<'a>>,
-    token: Option<&'a str>,
-    topic: Option<&'a str>,
+    token: Option<Cow<'a, str>>,
+    topic: Option<Cow<'a, str>>,
+    webpush: Option<WebpushConfig<'a>>,
 }
 
 impl<'a> MessageBuilder<'a> {
     /// Get a new instance of Message. You need to supply to.
-    pub fn new(access_token: &'a str, token: &'a str) -> Self {
+    pub fn new(access_token: impl Into<Cow<'a, str>>, token: impl Into<Cow<'a, str>>) -> Self {
         MessageBuilder {
-            access_token,
+            access_token: access_token.into(),
             android: None,
+            apns: None,
             condition: None,
             data: None,
             dry_run: None,
             fcm_options: None,
             name: None,
             notification: None,
-            token: Some(token),
+            token: Some(token.into()),
             topic: None,
+            webpush: None,
         }
     }
 
@@ -132,15 +325,26 @@ impl<'a> MessageBuilder<'a> {
         self
     }
 
+    /// Apple Push Notification Service specific options.
+    pub fn apns(&mut self, apns: ApnsConfig<'a>) -> &mut Self {
+        self.apns = Some(apns);
+        self
+    }
+
     /// Condition to send a message to, e.g. "'foo' in topics && 'bar' in topics"..
-    pub fn condition(&mut self, condition: &'a str) -> &mut Self {
-        self.condition = Some(condition);
+    /// Replaces the token given to the constructor, as a message has a
+    /// single target.
+    pub fn condition(&mut self, condition: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.token = None;
+        self.topic = None;
+        self.condition = Some(condition.into());
         self
     }
 
     /// Use this to add custom key-value pairs to the message. This data
-    /// must be handled appropriately on the client end. The data can be
-    /// anything that Serde can serialize to JSON.
+    /// must be handled appropriately on the client end. Use
+    /// `DataMap::from_serialize` or `DataMap::flatten` for data Serde can
+    /// serialize.
     ///
     /// # Examples:
     /// ```rust
@@ -151,15 +355,16 @@ impl<'a> MessageBuilder<'a> {
     /// map.insert("message", "Howdy!");
     ///
     /// let mut builder = MessageBuilder::new("<FCM API Key>", "<registration id>");
-    /// builder.data(&map);
+    /// builder.data(map);
     /// let message = builder.finalize();
     /// ```
-    pub fn data(&mut self, data: &dyn Serialize) -> Result<&mut Self, serde_json::Error> {
-        self.data = Some(serde_json::to_value(data)?);
-        Ok(self)
+    pub fn data(&mut self, data: impl Into<DataMap>) -> &mut Self {
+        self.data = Some(data.into());
+        self
     }
 
     /// When set to `true`, allows you to test FCM without actually sending the message.
+    /// Sent as `validate_only`, see also `ClientBuilder::validate_only`.
     pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
         self.dry_run = Some(dry_run);
         self
@@ -171,8 +376,8 @@ impl<'a> MessageBuilder<'a> {
     }
 
     /// The identifier of the message sent, in the format of projects/*/messages/{message_id}.
-    pub fn name(&mut self, name: &'a str) -> &mut Self {
-        self.name = Some(name);
+    pub fn name(&mut self, name: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.name = Some(name.into());
         self
     }
 
@@ -195,13 +400,31 @@ impl<'a> MessageBuilder<'a> {
         self
     }
 
-    pub fn token(&mut self, token: &'a str) -> &mut Self {
-        self.token = Some(token);
+    /// Send the message to all devices of a device group. The v1 API accepts
+    /// the group's notification key in place of a registration token.
+    pub fn notification_key(&mut self, notification_key: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.token(notification_key)
+    }
+
+    pub fn token(&mut self, token: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.topic = None;
+        self.condition = None;
+        self.token = Some(token.into());
         self
     }
 
-    pub fn topic(&mut self, topic: &'a str) -> &mut Self {
-        self.topic = Some(topic);
+    /// Send the message to the subscribers of a topic instead of the token
+    /// given to the constructor.
+    pub fn topic(&mut self, topic: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.token = None;
+        self.condition = None;
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Webpush protocol options, including the web notification to display.
+    pub fn webpush(&mut self, webpush: WebpushConfig<'a>) -> &mut Self {
+        self.webpush = Some(webpush);
         self
     }
 
@@ -211,6 +434,7 @@ impl<'a> MessageBuilder<'a> {
             access_token: self.access_token,
             body: MessageBody {
                 android: self.android,
+                apns: self.apns,
                 condition: self.condition,
                 data: self.data.clone(),
                 dry_run: self.dry_run,
@@ -219,7 +443,30 @@ impl<'a> MessageBuilder<'a> {
                 notification: self.notification,
                 token: self.token,
                 topic: self.topic,
+                webpush: self.webpush,
+                unknown: Map::new(),
             },
         }
     }
 }
+
+/// Redacts the access token and cuts down the registration token, see
+/// `Unredacted`.
+impl fmt::Debug for MessageBuilder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageBuilder")
+            .field("access_token", &Secret(&self.access_token))
+            .field("android", &self.android)
+            .field("apns", &self.apns)
+            .field("condition", &self.condition)
+            .field("data", &self.data)
+            .field("dry_run", &self.dry_run)
+            .field("fcm_options", &self.fcm_options)
+            .field("name", &self.name)
+            .field("notification", &self.notification)
+            .field("token", &redact::token(&self.token))
+            .field("topic", &self.topic)
+            .field("webpush", &self.webpush)
+            .finish()
+    }
+}
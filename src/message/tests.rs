@@ -1,24 +1,21 @@
 use crate::notification::NotificationBuilder;
-use serde_json;
-use std::borrow::Cow;
-use crate::{MessageBuilder, Priority};
+use crate::MessageBuilder;
 
 #[test]
 fn should_create_new_message() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.to, Some("token"));
+    assert_eq!(msg.body.token.as_deref(), Some("token"));
 }
 
 #[test]
 fn should_leave_nones_out_of_the_json() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
-    let payload = serde_json::to_string(&msg.body).unwrap();
+    let payload = serde_json::to_value(&msg.body).unwrap();
 
     let expected_payload = json!({
-        "to": "token"
-    })
-    .to_string();
+        "token": "token"
+    });
 
     assert_eq!(expected_payload, payload);
 }
@@ -31,26 +28,25 @@ fn should_add_custom_data_to_the_payload() {
     struct CustomData {
         foo: &'static str,
         bar: bool,
-    };
+    }
 
     let data = CustomData {
         foo: "bar",
         bar: false,
     };
 
-    builder.data(&data).unwrap();
+    builder.data(crate::DataMap::flatten(&data).unwrap());
 
     let msg = builder.finalize();
-    let payload = serde_json::to_string(&msg.body).unwrap();
+    let payload = serde_json::to_value(&msg.body).unwrap();
 
     let expected_payload = json!({
         "data": {
             "foo": "bar",
-            "bar": false,
+            "bar": "false",
         },
-        "to": "token"
-    })
-    .to_string();
+        "token": "token"
+    });
 
     assert_eq!(expected_payload, payload);
 }
@@ -60,150 +56,359 @@ fn should_be_able_to_render_a_full_message_to_json() {
     let mut builder = MessageBuilder::new("api_key", "token");
 
     builder
-        .registration_ids(&["one", "two"])
-        .collapse_key("foo")
-        .priority(Priority::High)
-        .content_available(false)
-        .delay_while_idle(true)
-        .time_to_live(420)
-        .restricted_package_name("pkg")
+        .name("projects/test-project/messages/1")
         .notification(NotificationBuilder::new().finalize())
         .dry_run(false);
 
-    let payload = serde_json::to_string(&builder.finalize().body).unwrap();
+    let payload = serde_json::to_value(&builder.finalize().body).unwrap();
 
     let expected_payload = json!({
-        "to": "token",
-        "registration_ids": ["one", "two"],
-        "collapse_key": "foo",
-        "priority": "high",
-        "content_available": false,
-        "delay_while_idle": true,
-        "time_to_live": 420,
-        "restricted_package_name": "pkg",
         "dry_run": false,
+        "name": "projects/test-project/messages/1",
         "notification": {},
-    })
-    .to_string();
+        "token": "token"
+    });
 
     assert_eq!(expected_payload, payload);
 }
 
 #[test]
-fn should_set_registration_ids() {
+fn should_set_condition() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.registration_ids, None);
+    assert_eq!(msg.body.condition, None);
 
     let mut builder = MessageBuilder::new("api_key", "token");
-    builder.registration_ids(&["id1"]);
+    builder.condition("'foo' in topics && 'bar' in topics");
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.registration_ids, Some(vec![Cow::from("id1")]));
+    assert_eq!(
+        msg.body.condition.as_deref(),
+        Some("'foo' in topics && 'bar' in topics")
+    );
 }
 
 #[test]
-fn should_set_collapse_key() {
+fn should_set_topic() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.collapse_key, None);
+    assert_eq!(msg.body.topic, None);
 
     let mut builder = MessageBuilder::new("api_key", "token");
-    builder.collapse_key("key");
+    builder.topic("news");
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.collapse_key, Some("key"));
+    assert_eq!(msg.body.topic.as_deref(), Some("news"));
 }
 
 #[test]
-fn should_set_priority() {
+fn should_set_name() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.priority, None);
+    assert_eq!(msg.body.name, None);
 
     let mut builder = MessageBuilder::new("api_key", "token");
-    builder.priority(Priority::Normal);
+    builder.name("projects/test-project/messages/1");
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.priority, Some(Priority::Normal));
+    assert_eq!(
+        msg.body.name.as_deref(),
+        Some("projects/test-project/messages/1")
+    );
 }
 
 #[test]
-fn should_set_content_available() {
+fn should_set_dry_run() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.content_available, None);
+    assert_eq!(msg.body.dry_run, None);
 
     let mut builder = MessageBuilder::new("api_key", "token");
-    builder.content_available(true);
+    builder.dry_run(true);
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.content_available, Some(true));
+    assert_eq!(msg.body.dry_run, Some(true));
 }
 
 #[test]
-fn should_set_delay_while_idle() {
+fn should_set_notifications() {
     let msg = MessageBuilder::new("api_key", "token").finalize();
 
-    assert_eq!(msg.body.delay_while_idle, None);
+    assert_eq!(msg.body.notification, None);
+
+    let nm = NotificationBuilder::new().finalize();
 
     let mut builder = MessageBuilder::new("api_key", "token");
-    builder.delay_while_idle(true);
+    builder.notification(nm);
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.delay_while_idle, Some(true));
+    assert!(msg.body.notification.is_some());
 }
 
 #[test]
-fn should_set_time_to_live() {
-    let msg = MessageBuilder::new("api_key", "token").finalize();
+fn should_keep_a_single_target() {
+    let mut builder = MessageBuilder::new("access_token", "token");
+    builder.topic("news");
+    builder.condition("'news' in topics");
+    let payload = serde_json::to_value(&builder.finalize().body).unwrap();
+
+    assert_eq!(json!("'news' in topics"), payload["condition"]);
+    assert_eq!(None, payload.get("token"));
+    assert_eq!(None, payload.get("topic"));
+}
 
-    assert_eq!(msg.body.time_to_live, None);
+#[tokio::test]
+async fn should_send_an_owned_message_from_a_spawned_task() {
+    fn build(token: String) -> crate::Message<'static> {
+        let mut notification = NotificationBuilder::new();
+        notification.title(format!("Hello {}", token));
 
-    let mut builder = MessageBuilder::new("api_key", "token");
-    builder.time_to_live(10);
-    let msg = builder.finalize();
+        let mut builder = MessageBuilder::new("access_token", token.as_str());
+        builder.notification(notification.finalize());
+        builder.topic(String::from("news"));
+        builder.finalize().into_owned()
+    }
+
+    let message = build("token".to_string());
+
+    let payload = tokio::spawn(async move { serde_json::to_value(&message.body).unwrap() })
+        .await
+        .unwrap();
+
+    let expected_payload = json!({
+        "notification": {"title": "Hello token"},
+        "topic": "news"
+    });
 
-    assert_eq!(msg.body.time_to_live, Some(10));
+    assert_eq!(expected_payload, payload);
 }
 
 #[test]
-fn should_set_restricted_package_name() {
-    let msg = MessageBuilder::new("api_key", "token").finalize();
+fn should_read_back_a_serialized_message() {
+    use crate::{ApnsConfigBuilder, ApsAlert, ApsBuilder, Payload, Urgency, WebpushConfigBuilder};
+    use crate::MessageBody;
+    use std::time::Duration;
 
-    assert_eq!(msg.body.restricted_package_name, None);
+    let mut notification = NotificationBuilder::new();
+    notification.title("Hello");
 
-    let mut builder = MessageBuilder::new("api_key", "token");
-    builder.restricted_package_name("name");
+    let mut aps = ApsBuilder::new();
+    aps.alert(ApsAlert::new().body("From APNs")).badge(3);
+
+    let mut apns = ApnsConfigBuilder::new();
+    apns.payload(Payload::new(aps.finalize()).data(json!({"story": 42})));
+
+    let mut webpush = WebpushConfigBuilder::new();
+    webpush.ttl(Duration::from_secs(3600)).urgency(Urgency::High);
+
+    let mut builder = MessageBuilder::new("access_token", "token");
+    builder.notification(notification.finalize());
+    builder.apns(apns.finalize());
+    builder.webpush(webpush.finalize());
+    builder.data(vec![("foo", "bar")].into_iter().collect::<crate::DataMap>());
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.restricted_package_name, Some("name"));
+    let json = serde_json::to_string(&msg.body).unwrap();
+    let body: MessageBody = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(msg.body, body);
 }
 
 #[test]
-fn should_set_dry_run() {
-    let msg = MessageBuilder::new("api_key", "token").finalize();
+fn should_reject_unknown_fields_when_asked_to() {
+    use crate::{MessageBody, UnknownFields};
 
-    assert_eq!(msg.body.dry_run, None);
+    let json = r#"{"token": "token", "notification": {"title": "Hello", "sticky": true}}"#;
+    let err = crate::from_str::<MessageBody>(json, UnknownFields::Reject).unwrap_err();
 
-    let mut builder = MessageBuilder::new("api_key", "token");
+    assert!(err.to_string().contains("unknown field `sticky`"));
+}
+
+#[test]
+fn should_keep_the_dry_run_when_round_tripping_a_message() {
+    use crate::{MessageBody, UnknownFields};
+
+    let mut builder = MessageBuilder::new("access_token", "token");
     builder.dry_run(true);
     let msg = builder.finalize();
 
-    assert_eq!(msg.body.dry_run, Some(true));
+    let json = serde_json::to_string(&msg.body).unwrap();
+    let body: MessageBody = crate::from_str(&json, UnknownFields::Reject).unwrap();
+
+    assert_eq!(Some(true), body.dry_run);
+    assert_eq!(msg.body, body);
 }
 
 #[test]
-fn should_set_notifications() {
-    let msg = MessageBuilder::new("api_key", "token").finalize();
+fn should_preserve_unknown_fields() {
+    use crate::{MessageBody, UnknownFields};
 
-    assert_eq!(msg.body.notification, None);
+    let json = json!({
+        "notification": {"title": "Hello", "sticky": true},
+        "token": "token",
+        "future_option": {"enabled": true}
+    });
 
-    let nm = NotificationBuilder::new().finalize();
+    let body: MessageBody = crate::from_value(json.clone(), UnknownFields::Preserve).unwrap();
 
-    let mut builder = MessageBuilder::new("api_key", "token");
-    builder.notification(nm);
-    let msg = builder.finalize();
+    assert_eq!(json, serde_json::to_value(&body).unwrap());
+}
+
+#[test]
+fn should_accept_a_valid_message() {
+    let mut builder = MessageBuilder::new("access_token", "token");
+    builder.topic("/topics/news~1.0%");
+    builder.data(vec![("score", "3x1")].into_iter().collect::<crate::DataMap>());
+
+    assert_eq!(Ok(()), builder.finalize().validate());
+}
+
+#[test]
+fn should_report_violations_with_their_json_paths() {
+    use crate::{MessageBody, ViolationKind};
+
+    let body: MessageBody = serde_json::from_value(json!({
+        "topic": "news and weather",
+        "data": {"google.sent_time": "now", "gcm": "yes"},
+        "android": {"ttl": "2419201s"},
+        "webpush": {"headers": {"TTL": "2419201"}}
+    }))
+    .unwrap();
+
+    let violations: Vec<_> = body
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .map(|v| (v.path, v.kind))
+        .collect();
+
+    assert_eq!(
+        vec![
+            (
+                "message.data.gcm".to_string(),
+                ViolationKind::ReservedDataKey("gcm".to_string())
+            ),
+            (
+                "message.data[\"google.sent_time\"]".to_string(),
+                ViolationKind::ReservedDataKey("google.sent_time".to_string())
+            ),
+            (
+                "message.topic".to_string(),
+                ViolationKind::InvalidTopic("news and weather".to_string())
+            ),
+            (
+                "message.android.ttl".to_string(),
+                ViolationKind::InvalidTtl("2419201s".to_string())
+            ),
+            (
+                "message.webpush.headers.TTL".to_string(),
+                ViolationKind::InvalidTtl("2419201".to_string())
+            ),
+        ],
+        violations
+    );
+}
+
+#[test]
+fn should_limit_the_data_size() {
+    use crate::{DataMap, ViolationKind, MAX_DATA_SIZE};
+
+    let mut data = DataMap::new();
+    data.insert("blob", "x".repeat(MAX_DATA_SIZE));
+
+    let mut builder = MessageBuilder::new("access_token", "token");
+    builder.data(data);
+
+    let violations = builder.finalize().validate().unwrap_err();
+
+    assert_eq!("message.data", violations[0].path);
+    assert_eq!(ViolationKind::MessageTooBig(MAX_DATA_SIZE + 4), violations[0].kind);
+}
+
+#[test]
+fn should_parse_ttls_in_seconds() {
+    use crate::MessageBody;
+
+    for (ttl, valid) in &[
+        ("0s", true),
+        ("3.5s", true),
+        ("2419200s", true),
+        ("2419200.000000001s", false),
+        ("3.5", false),
+        ("s", false),
+        ("-1s", false),
+        ("1.0000000001s", false),
+    ] {
+        let body: MessageBody =
+            serde_json::from_value(json!({"android": {"ttl": ttl}})).unwrap();
+
+        assert_eq!(*valid, body.validate().is_ok(), "{}", ttl);
+    }
+}
+
+#[test]
+fn should_only_accept_string_data_values() {
+    use crate::DataMap;
+
+    assert_eq!(
+        Some("bar"),
+        DataMap::from_serialize(&json!({"foo": "bar"}))
+            .unwrap()
+            .get("foo")
+    );
+    assert!(DataMap::from_serialize(&json!({"foo": 1})).is_err());
+    assert!(DataMap::from_serialize(&json!({"foo": {"bar": "baz"}})).is_err());
+    assert!(serde_json::from_value::<crate::MessageBody>(json!({"data": {"foo": true}})).is_err());
+}
+
+#[test]
+fn should_flatten_data_into_strings() {
+    use crate::DataMap;
+
+    let data = DataMap::flatten(&json!({
+        "text": "hi",
+        "count": 3,
+        "ratio": 0.5,
+        "seen": false,
+        "missing": null,
+        "nested": {"a": [1, "b"]}
+    }))
+    .unwrap();
+
+    let expected: DataMap = vec![
+        ("text", "hi"),
+        ("count", "3"),
+        ("ratio", "0.5"),
+        ("seen", "false"),
+        ("missing", "null"),
+        ("nested", r#"{"a":[1,"b"]}"#),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(expected, data);
+    assert!(DataMap::flatten(&json!(["not", "an", "object"])).is_err());
+}
+
+#[test]
+fn should_redact_tokens_in_debug_output() {
+    let mut builder = MessageBuilder::new("ya29.secret-access-token", "fGx8Kk2pQ0:APA91bHun4");
+    builder.dry_run(true);
+
+    let builder_output = format!("{:?}", builder);
+    let message = builder.finalize();
+    let output = format!("{:?}", message);
+
+    for output in &[builder_output, output] {
+        assert!(output.contains("access_token: <redacted>"), "{}", output);
+        assert!(output.contains("token: Some(\"fGx8Kk2p...\")"), "{}", output);
+        assert!(!output.contains("secret-access-token"), "{}", output);
+        assert!(!output.contains("APA91bHun4"), "{}", output);
+    }
+
+    let output = format!("{:?}", crate::Unredacted(&message));
 
-    assert!(msg.body.notification != None);
+    assert!(output.contains("\"ya29.secret-access-token\""), "{}", output);
+    assert!(output.contains("\"fGx8Kk2pQ0:APA91bHun4\""), "{}", output);
+    assert!(format!("{:?}", message).contains("<redacted>"));
 }
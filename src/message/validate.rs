@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::DataMap;
+
+/// The largest data payload FCM accepts, counting the bytes of its keys and
+/// values.
+pub const MAX_DATA_SIZE: usize = 4096;
+
+/// The longest time to live FCM accepts, four weeks.
+pub const MAX_TTL: Duration = Duration::from_secs(28 * 24 * 60 * 60);
+
+/// A problem with a message FCM would reject it for.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ViolationKind {
+    /// The data payload has the given size, more than `MAX_DATA_SIZE`
+    /// bytes. Reported as `MessageTooBig` by FCM.
+    MessageTooBig(usize),
+
+    /// The data key is reserved: `from`, `message_type`, or starting with
+    /// `google` or `gcm`. Reported as `InvalidDataKey` by FCM.
+    ReservedDataKey(String),
+
+    /// The topic name contains characters other than letters, digits and
+    /// `-_.~%`.
+    InvalidTopic(String),
+
+    /// The time to live isn't a duration of at most `MAX_TTL`. Reported as
+    /// `InvalidTtl` by FCM.
+    InvalidTtl(String),
+}
+
+/// A violation found by `Message::validate`, with the JSON path of the
+/// offending field, e.g. `message.android.data["google.sent_time"]`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Violation {
+    pub path: String,
+    pub kind: ViolationKind,
+}
+
+impl Error for Violation {}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ViolationKind::MessageTooBig(size) => write!(
+                f,
+                "{}: {} bytes of data, at most {} are allowed",
+                self.path, size, MAX_DATA_SIZE
+            ),
+            ViolationKind::ReservedDataKey(ref key) => {
+                write!(f, "{}: the data key {:?} is reserved", self.path, key)
+            }
+            ViolationKind::InvalidTopic(ref topic) => {
+                write!(f, "{}: invalid topic name {:?}", self.path, topic)
+            }
+            ViolationKind::InvalidTtl(ref ttl) => write!(
+                f,
+                "{}: invalid time to live {:?}, expected at most {}s",
+                self.path,
+                ttl,
+                MAX_TTL.as_secs()
+            ),
+        }
+    }
+}
+
+/// Collects the violations of a message, each part of which adds its own.
+pub(crate) struct Violations(Vec<Violation>);
+
+impl Violations {
+    pub(crate) fn new() -> Violations {
+        Violations(Vec::new())
+    }
+
+    pub(crate) fn add(&mut self, path: impl Into<String>, kind: ViolationKind) {
+        self.0.push(Violation {
+            path: path.into(),
+            kind,
+        });
+    }
+
+    pub(crate) fn into_result(self) -> Result<(), Vec<Violation>> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.0)
+        }
+    }
+
+    /// Check a data payload for reserved keys and its size.
+    pub(crate) fn check_data(&mut self, path: &str, data: &DataMap) {
+        let mut size = 0;
+
+        for (key, value) in data {
+            if is_reserved_key(key) {
+                self.add(
+                    child_path(path, key),
+                    ViolationKind::ReservedDataKey(key.clone()),
+                );
+            }
+
+            size += key.len() + value.len();
+        }
+
+        if size > MAX_DATA_SIZE {
+            self.add(path, ViolationKind::MessageTooBig(size));
+        }
+    }
+
+    /// Check a topic name, with or without the `/topics/` prefix.
+    pub(crate) fn check_topic(&mut self, path: &str, topic: &str) {
+        let name = topic.strip_prefix("/topics/").unwrap_or(topic);
+
+        let valid = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.~%".contains(&b));
+
+        if !valid {
+            self.add(path, ViolationKind::InvalidTopic(topic.to_string()));
+        }
+    }
+
+    /// Check a time to live in the `3.5s` format of the v1 API.
+    pub(crate) fn check_ttl(&mut self, path: &str, ttl: &str) {
+        let in_range = matches!(parse_seconds(ttl), Some(secs) if secs <= MAX_TTL.as_secs_f64());
+
+        if !in_range {
+            self.add(path, ViolationKind::InvalidTtl(ttl.to_string()));
+        }
+    }
+
+    /// Check a time to live given as a `Duration`.
+    pub(crate) fn check_ttl_duration(&mut self, path: &str, ttl: Duration) {
+        if ttl > MAX_TTL {
+            self.add(path, ViolationKind::InvalidTtl(ttl.as_secs().to_string()));
+        }
+    }
+}
+
+fn is_reserved_key(key: &str) -> bool {
+    key == "from" || key == "message_type" || key.starts_with("google") || key.starts_with("gcm")
+}
+
+/// Seconds with up to nine fractional digits, followed by `s`.
+fn parse_seconds(ttl: &str) -> Option<f64> {
+    let number = ttl.strip_suffix('s')?;
+    let (whole, fraction) = match number.find('.') {
+        Some(i) => (&number[..i], &number[i + 1..]),
+        None => (number, ""),
+    };
+
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+
+    if whole.is_empty() || !digits(whole) || !digits(fraction) || fraction.len() > 9 {
+        return None;
+    }
+
+    number.parse().ok()
+}
+
+/// The path of a key in the object at `path`, quoted if it isn't a plain
+/// identifier.
+pub(crate) fn child_path(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if plain {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{}]", path, Value::String(key.to_string()))
+    }
+}
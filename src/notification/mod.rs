@@ -1,21 +1,46 @@
 use std::borrow::Cow;
 
+use serde_json::{Map, Value};
+
+use crate::owned::owned;
+use crate::de::unknown_fields;
+
 #[cfg(test)]
 mod tests;
 
 /// This struct represents a FCM notification. Use the
 /// corresponding `NotificationBuilder` to get an instance. You can then use
 /// this notification instance when sending a FCM message.
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Notification<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub body: Option<&'a str>,
+    pub body: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub image: Option<&'a str>,
+    pub image: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub title: Option<&'a str>,
+    pub title: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> Notification<'a> {
+    /// Convert into a notification owning its strings, e.g. to send it from
+    /// another task.
+    pub fn into_owned(self) -> Notification<'static> {
+        Notification {
+            body: self.body.map(owned),
+            image: self.image.map(owned),
+            title: self.title.map(owned),
+            unknown: self.unknown,
+        }
+    }
 }
 
 /// A builder to get a `Notification` instance.
@@ -31,9 +56,9 @@ pub struct Notification<'a> {
 /// let notification = builder.finalize();
 /// ```
 pub struct NotificationBuilder<'a> {
-    title: Option<&'a str>,
-    body: Option<&'a str>,
-    image: Option<&'a str>,
+    title: Option<Cow<'a, str>>,
+    body: Option<Cow<'a, str>>,
+    image: Option<Cow<'a, str>>,
 }
 
 impl<'a> NotificationBuilder<'a> {
@@ -47,20 +72,20 @@ impl<'a> NotificationBuilder<'a> {
     }
 
     // Set the title of the notification
-    pub fn title(&mut self, title: &'a str) -> &mut Self {
-        self.title = Some(title);
+    pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
         self
     }
 
     /// Set the body of the notification
-    pub fn body(&mut self, body: &'a str) -> &mut Self {
-        self.body = Some(body);
+    pub fn body(&mut self, body: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body = Some(body.into());
         self
     }
 
     /// Set the notification image.
-    pub fn image(&mut self, icon: &'a str) -> &mut Self {
-        self.image = Some(icon);
+    pub fn image(&mut self, icon: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.image = Some(icon.into());
         self
     }
 
@@ -69,7 +94,14 @@ impl<'a> NotificationBuilder<'a> {
         Notification {
             title: self.title,
             body: self.body,
-            image: self.icon,
+            image: self.image,
+            unknown: Map::new(),
         }
     }
 }
+
+impl<'a> Default for NotificationBuilder<'a> {
+    fn default() -> Self {
+        NotificationBuilder::new()
+    }
+}
@@ -0,0 +1,12 @@
+//! Helpers for the `into_owned` methods of the payload types, turning the
+//! borrowed strings of a `Cow` into owned ones.
+
+use std::borrow::Cow;
+
+pub(crate) fn owned(value: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(value.into_owned())
+}
+
+pub(crate) fn owned_vec(values: Vec<Cow<'_, str>>) -> Vec<Cow<'static, str>> {
+    values.into_iter().map(owned).collect()
+}
@@ -0,0 +1,104 @@
+use std::cell::Cell;
+use std::fmt;
+
+/// How much of a registration token `Debug` output shows.
+const TOKEN_PREFIX_LEN: usize = 8;
+
+thread_local! {
+    static UNREDACTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous setting, also when formatting panics.
+struct UnredactedGuard(bool);
+
+impl UnredactedGuard {
+    fn set() -> UnredactedGuard {
+        UnredactedGuard(UNREDACTED.with(|u| u.replace(true)))
+    }
+}
+
+impl Drop for UnredactedGuard {
+    fn drop(&mut self) {
+        UNREDACTED.with(|u| u.set(self.0));
+    }
+}
+
+/// The `Debug` output of a value with its access tokens, API keys and
+/// registration tokens shown in full. By default they're redacted, and
+/// registration tokens cut down to their first characters.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{MessageBuilder, Unredacted};
+///
+/// let message = MessageBuilder::new("<access token>", "<registration id>").finalize();
+///
+/// assert!(!format!("{:?}", message).contains("<access token>"));
+/// assert!(format!("{:?}", Unredacted(&message)).contains("<access token>"));
+/// ```
+pub struct Unredacted<'a, T: ?Sized>(pub &'a T);
+
+impl<T> fmt::Debug for Unredacted<'_, T>
+where
+    T: fmt::Debug + ?Sized,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let _guard = UnredactedGuard::set();
+        self.0.fmt(f)
+    }
+}
+
+fn unredacted() -> bool {
+    UNREDACTED.with(|u| u.get())
+}
+
+/// An access token, API key or other secret, shown as `<redacted>`.
+pub(crate) struct Secret<'a>(pub(crate) &'a str);
+
+impl fmt::Debug for Secret<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if unredacted() {
+            fmt::Debug::fmt(self.0, f)
+        } else {
+            f.write_str("<redacted>")
+        }
+    }
+}
+
+/// A registration token, shown by its first characters.
+pub(crate) struct Token<'a>(pub(crate) &'a str);
+
+impl Token<'_> {
+    fn prefix(&self) -> Option<&str> {
+        match self.0.char_indices().nth(TOKEN_PREFIX_LEN) {
+            Some((end, _)) if !unredacted() => Some(&self.0[..end]),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix() {
+            Some(prefix) => write!(f, "\"{}...\"", prefix),
+            None => fmt::Debug::fmt(self.0, f),
+        }
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix() {
+            Some(prefix) => write!(f, "{}...", prefix),
+            None => f.write_str(self.0),
+        }
+    }
+}
+
+pub(crate) fn token<S: AsRef<str>>(token: &Option<S>) -> Option<Token<'_>> {
+    token.as_ref().map(|token| Token(token.as_ref()))
+}
+
+pub(crate) fn tokens<S: AsRef<str>>(tokens: &[S]) -> Vec<Token<'_>> {
+    tokens.iter().map(|token| Token(token.as_ref())).collect()
+}
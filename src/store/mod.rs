@@ -0,0 +1,201 @@
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::*;
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+use crate::client::TokenEvent;
+use crate::redact::Token;
+
+/// Persistence of the registration tokens of each user, used by
+/// `send_to_user` to find the devices of a user and to prune the tokens FCM
+/// reports as no longer valid.
+///
+/// `MemoryTokenStore` keeps the tokens in memory, `SqliteTokenStore` in a
+/// SQLite database when the `sqlite` feature is enabled.
+pub trait TokenStore: Send + Sync {
+    /// Register a token for the user. Adding a known token moves it to the
+    /// given user.
+    fn add_token(&self, user_id: &str, token: &str) -> Result<(), TokenStoreError>;
+
+    /// All tokens of the user, in the order they were added.
+    fn tokens(&self, user_id: &str) -> Result<Vec<String>, TokenStoreError>;
+
+    /// Forget the token.
+    fn remove_token(&self, token: &str) -> Result<(), TokenStoreError>;
+
+    /// Replace the token with its canonical registration id, keeping the
+    /// user it belongs to.
+    fn replace_token(&self, old: &str, new: &str) -> Result<(), TokenStoreError>;
+
+    /// Remember when a message was last delivered to the token.
+    fn record_success(&self, token: &str, at: DateTime<Utc>) -> Result<(), TokenStoreError>;
+
+    /// When a message was last delivered to the token, if ever.
+    fn last_success(&self, token: &str) -> Result<Option<DateTime<Utc>>, TokenStoreError>;
+}
+
+impl<T> TokenStore for Arc<T>
+where
+    T: TokenStore + ?Sized,
+{
+    fn add_token(&self, user_id: &str, token: &str) -> Result<(), TokenStoreError> {
+        (**self).add_token(user_id, token)
+    }
+
+    fn tokens(&self, user_id: &str) -> Result<Vec<String>, TokenStoreError> {
+        (**self).tokens(user_id)
+    }
+
+    fn remove_token(&self, token: &str) -> Result<(), TokenStoreError> {
+        (**self).remove_token(token)
+    }
+
+    fn replace_token(&self, old: &str, new: &str) -> Result<(), TokenStoreError> {
+        (**self).replace_token(old, new)
+    }
+
+    fn record_success(&self, token: &str, at: DateTime<Utc>) -> Result<(), TokenStoreError> {
+        (**self).record_success(token, at)
+    }
+
+    fn last_success(&self, token: &str) -> Result<Option<DateTime<Utc>>, TokenStoreError> {
+        (**self).last_success(token)
+    }
+}
+
+/// A `TokenStore` failed to read or write the tokens.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TokenStoreError(pub String);
+
+impl Error for TokenStoreError {}
+
+impl fmt::Display for TokenStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "token store error: {}", self.0)
+    }
+}
+
+struct StoredToken {
+    user_id: String,
+    token: String,
+    last_success: Option<DateTime<Utc>>,
+}
+
+impl fmt::Debug for StoredToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoredToken")
+            .field("user_id", &self.user_id)
+            .field("token", &Token(&self.token))
+            .field("last_success", &self.last_success)
+            .finish()
+    }
+}
+
+/// A `TokenStore` keeping the tokens in memory, for tests and single process
+/// deployments.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{MemoryTokenStore, TokenStore};
+///
+/// let store = MemoryTokenStore::new();
+/// store.add_token("user-42", "<registration id>").unwrap();
+///
+/// assert_eq!(vec!["<registration id>"], store.tokens("user-42").unwrap());
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<Vec<StoredToken>>,
+}
+
+impl MemoryTokenStore {
+    /// Get a new, empty `MemoryTokenStore`.
+    pub fn new() -> MemoryTokenStore {
+        MemoryTokenStore::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn add_token(&self, user_id: &str, token: &str) -> Result<(), TokenStoreError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|t| t.token != token);
+
+        tokens.push(StoredToken {
+            user_id: user_id.to_string(),
+            token: token.to_string(),
+            last_success: None,
+        });
+
+        Ok(())
+    }
+
+    fn tokens(&self, user_id: &str) -> Result<Vec<String>, TokenStoreError> {
+        let tokens = self.tokens.lock().unwrap();
+
+        Ok(tokens
+            .iter()
+            .filter(|t| t.user_id == user_id)
+            .map(|t| t.token.clone())
+            .collect())
+    }
+
+    fn remove_token(&self, token: &str) -> Result<(), TokenStoreError> {
+        self.tokens.lock().unwrap().retain(|t| t.token != token);
+        Ok(())
+    }
+
+    fn replace_token(&self, old: &str, new: &str) -> Result<(), TokenStoreError> {
+        let mut tokens = self.tokens.lock().unwrap();
+
+        if tokens.iter().any(|t| t.token == new) {
+            tokens.retain(|t| t.token != old);
+        } else if let Some(stored) = tokens.iter_mut().find(|t| t.token == old) {
+            stored.token = new.to_string();
+            stored.last_success = None;
+        }
+
+        Ok(())
+    }
+
+    fn record_success(&self, token: &str, at: DateTime<Utc>) -> Result<(), TokenStoreError> {
+        let mut tokens = self.tokens.lock().unwrap();
+
+        if let Some(stored) = tokens.iter_mut().find(|t| t.token == token) {
+            stored.last_success = Some(at);
+        }
+
+        Ok(())
+    }
+
+    fn last_success(&self, token: &str) -> Result<Option<DateTime<Utc>>, TokenStoreError> {
+        let tokens = self.tokens.lock().unwrap();
+
+        Ok(tokens
+            .iter()
+            .find(|t| t.token == token)
+            .and_then(|t| t.last_success))
+    }
+}
+
+/// Apply a token change reported by FCM to the store. Failures are only
+/// logged, as the message itself was sent.
+pub(crate) fn prune(store: &dyn TokenStore, event: &TokenEvent) {
+    let result = match event {
+        TokenEvent::Replace(old, new) => store.replace_token(old, new),
+        TokenEvent::Remove(token, _) => store.remove_token(token),
+    };
+
+    if let Err(e) = result {
+        log::warn!("Couldn't update the token store: {}", e);
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{TokenStore, TokenStoreError};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS fcm_tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL,
+        last_success INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS fcm_tokens_user_id ON fcm_tokens (user_id);
+";
+
+/// A `TokenStore` keeping the tokens in the `fcm_tokens` table of a SQLite
+/// database, created if it doesn't exist. Requires the `sqlite` feature.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::{SqliteTokenStore, TokenStore};
+///
+/// let store = SqliteTokenStore::open_in_memory().unwrap();
+/// store.add_token("user-42", "<registration id>").unwrap();
+///
+/// assert_eq!(vec!["<registration id>"], store.tokens("user-42").unwrap());
+/// ```
+pub struct SqliteTokenStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteTokenStore {
+    /// Open or create the database at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteTokenStore, TokenStoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Create a database living only as long as the store.
+    pub fn open_in_memory() -> Result<SqliteTokenStore, TokenStoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Use an already opened connection, e.g. to share the application's
+    /// database.
+    pub fn from_connection(connection: Connection) -> Result<SqliteTokenStore, TokenStoreError> {
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(SqliteTokenStore {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl TokenStore for SqliteTokenStore {
+    fn add_token(&self, user_id: &str, token: &str) -> Result<(), TokenStoreError> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO fcm_tokens (token, user_id) VALUES (?1, ?2)",
+            params![token, user_id],
+        )?;
+
+        Ok(())
+    }
+
+    fn tokens(&self, user_id: &str) -> Result<Vec<String>, TokenStoreError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT token FROM fcm_tokens WHERE user_id = ?1 ORDER BY rowid")?;
+
+        let tokens = statement
+            .query_map(params![user_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(tokens)
+    }
+
+    fn remove_token(&self, token: &str) -> Result<(), TokenStoreError> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM fcm_tokens WHERE token = ?1", params![token])?;
+
+        Ok(())
+    }
+
+    fn replace_token(&self, old: &str, new: &str) -> Result<(), TokenStoreError> {
+        let connection = self.connection.lock().unwrap();
+
+        let known: Option<String> = connection
+            .query_row(
+                "SELECT token FROM fcm_tokens WHERE token = ?1",
+                params![new],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if known.is_some() {
+            connection.execute("DELETE FROM fcm_tokens WHERE token = ?1", params![old])?;
+        } else {
+            connection.execute(
+                "UPDATE fcm_tokens SET token = ?2, last_success = NULL WHERE token = ?1",
+                params![old, new],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn record_success(&self, token: &str, at: DateTime<Utc>) -> Result<(), TokenStoreError> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE fcm_tokens SET last_success = ?2 WHERE token = ?1",
+            params![token, at.timestamp_millis()],
+        )?;
+
+        Ok(())
+    }
+
+    fn last_success(&self, token: &str) -> Result<Option<DateTime<Utc>>, TokenStoreError> {
+        let last_success: Option<Option<i64>> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT last_success FROM fcm_tokens WHERE token = ?1",
+                params![token],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(last_success
+            .flatten()
+            .and_then(|millis| Utc.timestamp_millis_opt(millis).single()))
+    }
+}
+
+impl From<rusqlite::Error> for TokenStoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        TokenStoreError(e.to_string())
+    }
+}
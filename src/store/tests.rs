@@ -0,0 +1,61 @@
+use crate::{MemoryTokenStore, TokenStore};
+use chrono::{TimeZone, Utc};
+
+fn should_keep_the_tokens_of_each_user(store: &dyn TokenStore) {
+    store.add_token("alice", "token1").unwrap();
+    store.add_token("bob", "token2").unwrap();
+    store.add_token("alice", "token3").unwrap();
+
+    assert_eq!(vec!["token1", "token3"], store.tokens("alice").unwrap());
+    assert_eq!(vec!["token2"], store.tokens("bob").unwrap());
+    assert!(store.tokens("carol").unwrap().is_empty());
+
+    store.add_token("bob", "token1").unwrap();
+
+    assert_eq!(vec!["token3"], store.tokens("alice").unwrap());
+    assert_eq!(vec!["token2", "token1"], store.tokens("bob").unwrap());
+}
+
+fn should_remove_and_replace_tokens(store: &dyn TokenStore) {
+    store.add_token("alice", "token1").unwrap();
+    store.add_token("alice", "token2").unwrap();
+    store.add_token("alice", "token3").unwrap();
+
+    store.remove_token("token2").unwrap();
+    store.replace_token("token1", "token4").unwrap();
+    assert_eq!(vec!["token4", "token3"], store.tokens("alice").unwrap());
+
+    store.replace_token("token4", "token3").unwrap();
+    assert_eq!(vec!["token3"], store.tokens("alice").unwrap());
+}
+
+fn should_record_the_last_success(store: &dyn TokenStore) {
+    let at = Utc.timestamp_millis_opt(1_600_000_000_123).unwrap();
+
+    store.add_token("alice", "token1").unwrap();
+    assert_eq!(None, store.last_success("token1").unwrap());
+
+    store.record_success("token1", at).unwrap();
+    assert_eq!(Some(at), store.last_success("token1").unwrap());
+
+    store.replace_token("token1", "token2").unwrap();
+    assert_eq!(None, store.last_success("token2").unwrap());
+    assert_eq!(None, store.last_success("token1").unwrap());
+}
+
+#[test]
+fn should_keep_tokens_in_memory() {
+    should_keep_the_tokens_of_each_user(&MemoryTokenStore::new());
+    should_remove_and_replace_tokens(&MemoryTokenStore::new());
+    should_record_the_last_success(&MemoryTokenStore::new());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn should_keep_tokens_in_sqlite() {
+    use crate::SqliteTokenStore;
+
+    should_keep_the_tokens_of_each_user(&SqliteTokenStore::open_in_memory().unwrap());
+    should_remove_and_replace_tokens(&SqliteTokenStore::open_in_memory().unwrap());
+    should_record_the_last_success(&SqliteTokenStore::open_in_memory().unwrap());
+}
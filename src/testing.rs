@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+
+/// Serve HTTP/1 requests on a random local port with the given handler,
+/// receiving the request head and the full body.
+pub(crate) fn serve<F>(handler: F) -> SocketAddr
+where
+    F: Fn(http::request::Parts, Vec<u8>) -> Response<Body> + Send + Sync + 'static,
+{
+    spawn(handler, false)
+}
+
+/// Like `serve`, but only speaking HTTP/2 with prior knowledge.
+pub(crate) fn serve_h2<F>(handler: F) -> SocketAddr
+where
+    F: Fn(http::request::Parts, Vec<u8>) -> Response<Body> + Send + Sync + 'static,
+{
+    spawn(handler, true)
+}
+
+fn spawn<F>(handler: F, http2_only: bool) -> SocketAddr
+where
+    F: Fn(http::request::Parts, Vec<u8>) -> Response<Body> + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+
+    let make_service = make_service_fn(move |_| {
+        let handler = handler.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let handler = handler.clone();
+
+                async move {
+                    let (parts, body) = request.into_parts();
+                    let body = hyper::body::to_bytes(body).await?;
+
+                    Ok::<_, hyper::Error>(handler(parts, body.to_vec()))
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(http2_only)
+        .serve(make_service);
+    let addr = server.local_addr();
+
+    tokio::spawn(server);
+
+    addr
+}
@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use base64::engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use chrono::Utc;
+use futures::stream::StreamExt;
+use http::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use http::Uri;
+use hyper::{
+    client::{Client as HttpClient, HttpConnector},
+    Body, Request, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+use p256::ecdsa::SigningKey;
+use p256::pkcs8::DecodePrivateKey;
+use p256::SecretKey;
+
+use super::{ece, WebpushConfig};
+use crate::jwt;
+use crate::redact::Secret;
+use crate::RetryAfter;
+
+/// The TTL sent when the `WebpushConfig` doesn't set one: four weeks, the
+/// longest time FCM keeps a message as well.
+const DEFAULT_TTL: Duration = Duration::from_secs(2_419_200);
+
+/// How long a VAPID token is valid. Push services reject tokens that expire
+/// more than 24 hours in the future.
+const VAPID_TOKEN_LIFETIME: i64 = 12 * 60 * 60;
+
+/// A browser push subscription, as returned by `PushSubscription.toJSON()` in
+/// the browser.
+///
+/// # Examples:
+/// ```rust
+/// use fcm::PushSubscription;
+///
+/// let subscription: PushSubscription = serde_json::from_str(r#"{
+///     "endpoint": "https://updates.push.services.mozilla.com/wpush/v2/gAAAAA",
+///     "expirationTime": null,
+///     "keys": {
+///         "p256dh": "BNcRdreALRFXTkOOUHK1EtK2wtaz5Ry4YfYCA_0QTpQtUbVlUls0VJXg7A8u-Ts1XbjhazAkj7I99e8QcYP7DkM",
+///         "auth": "tBHItJI5svbpez7KI4CCXg"
+///     }
+/// }"#).unwrap();
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+/// The user agent's public key and authentication secret of a
+/// `PushSubscription`, both base64url encoded.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct PushSubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+/// Redacts the authentication secret, see `Unredacted`.
+impl fmt::Debug for PushSubscriptionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PushSubscriptionKeys")
+            .field("p256dh", &self.p256dh)
+            .field("auth", &Secret(&self.auth))
+            .finish()
+    }
+}
+
+/// The application server's key pair used to identify itself to push
+/// services, as described in [RFC 8292](https://tools.ietf.org/html/rfc8292).
+pub struct VapidKey {
+    signing_key: SigningKey,
+    subject: String,
+}
+
+impl VapidKey {
+    /// Create a key from the base64url encoded 32 byte private key, the
+    /// format most web push libraries generate. The subject is a `mailto:` or
+    /// `https:` URL the push service can use to contact you.
+    pub fn from_base64(private_key: &str, subject: &str) -> Result<VapidKey, WebPushError> {
+        let bytes = decode_base64(private_key, "VAPID private key")?;
+        let secret_key = SecretKey::from_slice(&bytes)
+            .map_err(|_| WebPushError::InvalidKey("invalid VAPID private key".to_string()))?;
+
+        Ok(VapidKey {
+            signing_key: SigningKey::from(secret_key),
+            subject: subject.to_string(),
+        })
+    }
+
+    /// Create a key from a PEM encoded private key, either PKCS#8 or SEC1
+    /// (`BEGIN EC PRIVATE KEY`).
+    pub fn from_pem(pem: &str, subject: &str) -> Result<VapidKey, WebPushError> {
+        let secret_key = SecretKey::from_pkcs8_pem(pem)
+            .or_else(|_| SecretKey::from_sec1_pem(pem))
+            .map_err(|_| WebPushError::InvalidKey("invalid VAPID private key".to_string()))?;
+
+        Ok(VapidKey {
+            signing_key: SigningKey::from(secret_key),
+            subject: subject.to_string(),
+        })
+    }
+
+    /// The base64url encoded public key, to be used as the
+    /// `applicationServerKey` when subscribing in the browser.
+    pub fn public_key(&self) -> String {
+        let point = self.signing_key.verifying_key().to_encoded_point(false);
+        URL_SAFE_NO_PAD.encode(point.as_bytes())
+    }
+
+    fn authorization(&self, endpoint: &Uri) -> Result<String, WebPushError> {
+        let audience = match (endpoint.scheme_str(), endpoint.authority()) {
+            (Some(scheme), Some(authority)) => format!("{}://{}", scheme, authority),
+            _ => return Err(WebPushError::InvalidEndpoint(endpoint.to_string())),
+        };
+
+        let header = json!({"typ": "JWT", "alg": "ES256"});
+        let claims = json!({
+            "aud": audience,
+            "exp": Utc::now().timestamp() + VAPID_TOKEN_LIFETIME,
+            "sub": self.subject,
+        });
+
+        Ok(format!(
+            "vapid t={}, k={}",
+            jwt::sign_es256(&self.signing_key, &header, &claims),
+            self.public_key()
+        ))
+    }
+}
+
+/// An async client sending messages directly to browser push services with
+/// the [Web Push protocol](https://tools.ietf.org/html/rfc8030), bypassing
+/// FCM.
+///
+/// # Examples:
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use fcm::{PushSubscription, Urgency, VapidKey, WebPushClient, WebpushConfigBuilder};
+///
+/// # let subscription_json = "";
+/// let subscription: PushSubscription = serde_json::from_str(subscription_json)?;
+/// let vapid = VapidKey::from_base64("<VAPID private key>", "mailto:push@example.com")?;
+/// let client = WebPushClient::new(vapid);
+///
+/// let mut builder = WebpushConfigBuilder::new();
+/// builder.urgency(Urgency::High);
+///
+/// client.send(&subscription, b"{\"title\":\"Hey!\"}", &builder.finalize()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct WebPushClient {
+    vapid: VapidKey,
+    http_client: HttpClient<HttpsConnector<HttpConnector>>,
+}
+
+impl WebPushClient {
+    /// Get a new instance of WebPushClient.
+    pub fn new(vapid: VapidKey) -> WebPushClient {
+        let mut http_client = HttpClient::builder();
+        http_client.pool_max_idle_per_host(usize::MAX);
+
+        WebPushClient {
+            vapid,
+            http_client: http_client.build(HttpsConnector::new()),
+        }
+    }
+
+    /// Encrypt the payload for the subscription and post it to its push
+    /// service. The `TTL`, `Urgency` and `Topic` headers are taken from the
+    /// `WebpushConfig`.
+    pub async fn send(
+        &self,
+        subscription: &PushSubscription,
+        payload: &[u8],
+        config: &WebpushConfig<'_>,
+    ) -> Result<(), WebPushError> {
+        let endpoint: Uri = subscription
+            .endpoint
+            .parse()
+            .map_err(|_| WebPushError::InvalidEndpoint(subscription.endpoint.clone()))?;
+
+        let ua_public = decode_base64(&subscription.keys.p256dh, "subscription p256dh key")?;
+        let auth_secret = decode_base64(&subscription.keys.auth, "subscription auth secret")?;
+        let body = ece::encrypt(&ua_public, &auth_secret, payload)?;
+
+        let headers = config.headers.as_ref();
+        let ttl = headers.and_then(|h| h.ttl).unwrap_or(DEFAULT_TTL);
+
+        let mut builder = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_ENCODING, "aes128gcm")
+            .header(CONTENT_LENGTH, format!("{}", body.len() as u64).as_bytes())
+            .header(AUTHORIZATION, self.vapid.authorization(&endpoint)?)
+            .header("TTL", ttl.as_secs().to_string());
+
+        if let Some(urgency) = headers.and_then(|h| h.urgency) {
+            builder = builder.header("Urgency", urgency.as_str());
+        }
+
+        if let Some(topic) = headers.and_then(|h| h.topic.as_deref()) {
+            builder = builder.header("Topic", topic);
+        }
+
+        let request = builder.uri(endpoint).body(Body::from(body)).unwrap();
+        let response = self.http_client.request(request).await?;
+        let response_status = response.status();
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|ra| ra.to_str().ok())
+            .and_then(RetryAfter::from_str);
+
+        let mut body: Vec<u8> = Vec::new();
+        let mut chunks = response.into_body();
+
+        while let Some(chunk) = chunks.next().await {
+            body.extend_from_slice(&chunk?);
+        }
+
+        match response_status {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND | StatusCode::GONE => Err(WebPushError::SubscriptionGone),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(WebPushError::Unauthorized),
+            StatusCode::PAYLOAD_TOO_LARGE => Err(WebPushError::PayloadTooLarge),
+            StatusCode::TOO_MANY_REQUESTS => Err(WebPushError::TooManyRequests(retry_after)),
+            status if status.is_server_error() => Err(WebPushError::ServerError(retry_after)),
+            _ => Err(WebPushError::InvalidRequest(
+                String::from_utf8_lossy(&body).into_owned(),
+            )),
+        }
+    }
+}
+
+/// Decode a base64url encoded key, naming only the key in the error as the
+/// value is secret.
+fn decode_base64(value: &str, name: &str) -> Result<Vec<u8>, WebPushError> {
+    let value = value.trim_end_matches('=');
+
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .or_else(|_| URL_SAFE.decode(value))
+        .map_err(|_| WebPushError::InvalidKey(format!("{} is not valid base64url", name)))
+}
+
+/// Errors sending a message directly to a push service.
+#[derive(PartialEq)]
+pub enum WebPushError {
+    /// The VAPID key or one of the subscription keys couldn't be decoded.
+    InvalidKey(String),
+
+    /// The subscription endpoint is not an absolute URL.
+    InvalidEndpoint(String),
+
+    /// The encrypted payload is larger than the 4096 bytes push services
+    /// have to accept.
+    PayloadTooLarge,
+
+    /// The push service rejected the VAPID authorization, e.g. because the
+    /// subscription was created with a different application server key.
+    Unauthorized,
+
+    /// The subscription expired or the user unsubscribed. Remove it and stop
+    /// sending messages to it.
+    SubscriptionGone,
+
+    /// The push service rejected the request for another reason, given in
+    /// the response body.
+    InvalidRequest(String),
+
+    /// Too many messages were sent to the push service. Honor the
+    /// [RetryAfter](enum.RetryAfter.html) value if included.
+    TooManyRequests(Option<RetryAfter>),
+
+    /// The push service couldn't process the request. Retry later, honoring
+    /// the [RetryAfter](enum.RetryAfter.html) value if included.
+    ServerError(Option<RetryAfter>),
+}
+
+impl Error for WebPushError {}
+
+/// Redacts what's wrong with an invalid key, as it's about a secret, see
+/// `Unredacted`.
+impl fmt::Debug for WebPushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebPushError::InvalidKey(ref s) => {
+                f.debug_tuple("InvalidKey").field(&Secret(s)).finish()
+            }
+            WebPushError::InvalidEndpoint(ref s) => {
+                f.debug_tuple("InvalidEndpoint").field(s).finish()
+            }
+            WebPushError::PayloadTooLarge => f.write_str("PayloadTooLarge"),
+            WebPushError::Unauthorized => f.write_str("Unauthorized"),
+            WebPushError::SubscriptionGone => f.write_str("SubscriptionGone"),
+            WebPushError::InvalidRequest(ref s) => {
+                f.debug_tuple("InvalidRequest").field(s).finish()
+            }
+            WebPushError::TooManyRequests(ref retry_after) => {
+                f.debug_tuple("TooManyRequests").field(retry_after).finish()
+            }
+            WebPushError::ServerError(ref retry_after) => {
+                f.debug_tuple("ServerError").field(retry_after).finish()
+            }
+        }
+    }
+}
+
+impl fmt::Display for WebPushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebPushError::InvalidKey(ref s) => write!(f, "invalid key: {}", s),
+            WebPushError::InvalidEndpoint(ref s) => write!(f, "invalid endpoint {}", s),
+            WebPushError::PayloadTooLarge => write!(f, "the payload is too large"),
+            WebPushError::Unauthorized => write!(f, "the VAPID authorization was rejected"),
+            WebPushError::SubscriptionGone => write!(f, "the subscription is no longer valid"),
+            WebPushError::InvalidRequest(ref s) => write!(f, "invalid request {}", s),
+            WebPushError::TooManyRequests(_) => write!(f, "too many requests"),
+            WebPushError::ServerError(_) => {
+                write!(f, "the push service couldn't process the request")
+            }
+        }
+    }
+}
+
+impl From<hyper::Error> for WebPushError {
+    fn from(_: hyper::Error) -> Self {
+        Self::ServerError(None)
+    }
+}
@@ -0,0 +1,106 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use hkdf::Hkdf;
+use p256::ecdh::EphemeralSecret;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::PublicKey;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+
+use super::client::WebPushError;
+
+/// The record size announced in the content coding header. The whole payload
+/// is sent as a single record.
+const RECORD_SIZE: u32 = 4096;
+
+/// Salt, record size, key id length and the 65 byte uncompressed public key.
+const HEADER_LEN: usize = 16 + 4 + 1 + 65;
+
+/// The authentication tag appended by AES-GCM.
+const TAG_LEN: usize = 16;
+
+/// The largest plaintext that fits a single record, leaving space for the
+/// padding delimiter and the authentication tag.
+pub(crate) const MAX_PLAINTEXT_LEN: usize = RECORD_SIZE as usize - HEADER_LEN - TAG_LEN - 1;
+
+/// Encrypt a push message for the user agent according to
+/// [RFC 8291](https://tools.ietf.org/html/rfc8291), using the `aes128gcm`
+/// content coding of [RFC 8188](https://tools.ietf.org/html/rfc8188).
+pub(crate) fn encrypt(
+    ua_public: &[u8],
+    auth_secret: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, WebPushError> {
+    if plaintext.len() > MAX_PLAINTEXT_LEN {
+        return Err(WebPushError::PayloadTooLarge);
+    }
+
+    let ua_public_key = PublicKey::from_sec1_bytes(ua_public)
+        .map_err(|_| WebPushError::InvalidKey("p256dh is not a P-256 public key".to_string()))?;
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let as_secret = EphemeralSecret::random(&mut OsRng);
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let ecdh_secret = as_secret.diffie_hellman(&ua_public_key);
+
+    let (cek, nonce) = derive_key_and_nonce(
+        ecdh_secret.raw_secret_bytes(),
+        auth_secret,
+        &salt,
+        ua_public,
+        as_public.as_bytes(),
+    );
+
+    let mut record = Vec::with_capacity(plaintext.len() + 1);
+    record.extend_from_slice(plaintext);
+    record.push(2);
+
+    let cipher = Aes128Gcm::new_from_slice(&cek).unwrap();
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), record.as_slice())
+        .map_err(|_| WebPushError::PayloadTooLarge)?;
+
+    let mut body = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+
+    Ok(body)
+}
+
+/// Derive the content encryption key and nonce from the ECDH shared secret,
+/// the subscription's authentication secret and the record salt.
+pub(crate) fn derive_key_and_nonce(
+    ecdh_secret: &[u8],
+    auth_secret: &[u8],
+    salt: &[u8],
+    ua_public: &[u8],
+    as_public: &[u8],
+) -> ([u8; 16], [u8; 12]) {
+    let mut key_info = Vec::with_capacity(14 + ua_public.len() + as_public.len());
+    key_info.extend_from_slice(b"WebPush: info\0");
+    key_info.extend_from_slice(ua_public);
+    key_info.extend_from_slice(as_public);
+
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth_secret), ecdh_secret)
+        .expand(&key_info, &mut ikm)
+        .unwrap();
+
+    let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+
+    let mut cek = [0u8; 16];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .unwrap();
+
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .unwrap();
+
+    (cek, nonce)
+}
@@ -1,44 +1,590 @@
-use serde_json::Value;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error as _;
+use serde_json::{Map, Value};
+
+use crate::owned::owned;
+use crate::de::unknown_fields;
+use crate::message::{DataMap, Violations};
+
+mod client;
+mod ece;
+
+pub use self::client::*;
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Serialize, Debug, PartialEq)]
+/// The text direction of a web notification.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// A button shown with a web notification. The `action` identifies the
+/// button in the service worker's `notificationclick` event.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WebNotificationAction<'a> {
+    action: Cow<'a, str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<Cow<'a, str>>,
+
+    title: Cow<'a, str>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> WebNotificationAction<'a> {
+    /// Get a new `WebNotificationAction` with the given identifier and button
+    /// title.
+    pub fn new(
+        action: impl Into<Cow<'a, str>>,
+        title: impl Into<Cow<'a, str>>,
+    ) -> WebNotificationAction<'a> {
+        WebNotificationAction {
+            action: action.into(),
+            icon: None,
+            title: title.into(),
+            unknown: Map::new(),
+        }
+    }
+
+    /// Set the URL of an icon to display with the action.
+    pub fn icon(mut self, icon: impl Into<Cow<'a, str>>) -> Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// Convert into an action owning its strings.
+    pub fn into_owned(self) -> WebNotificationAction<'static> {
+        WebNotificationAction {
+            action: owned(self.action),
+            icon: self.icon.map(owned),
+            title: owned(self.title),
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// This struct represents a web notification, following the options of the
+/// [Notifications API](https://developer.mozilla.org/en-US/docs/Web/API/Notification/Notification).
+/// Use the corresponding `WebNotificationBuilder` to get an instance.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct WebNotification<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    title: Option<&'a str>,
+    actions: Option<Vec<WebNotificationAction<'a>>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    badge: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    body: Option<&'a str>,
+    dir: Option<Direction>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    renotify: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    require_interaction: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    silent: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<Cow<'a, str>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vibrate: Option<Vec<u32>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> WebNotification<'a> {
+    /// Convert into a notification owning its strings, e.g. to send it from
+    /// another task.
+    pub fn into_owned(self) -> WebNotification<'static> {
+        WebNotification {
+            actions: self
+                .actions
+                .map(|actions| actions.into_iter().map(|a| a.into_owned()).collect()),
+            badge: self.badge.map(owned),
+            body: self.body.map(owned),
+            data: self.data,
+            dir: self.dir,
+            icon: self.icon.map(owned),
+            image: self.image.map(owned),
+            lang: self.lang.map(owned),
+            renotify: self.renotify,
+            require_interaction: self.require_interaction,
+            silent: self.silent,
+            tag: self.tag.map(owned),
+            timestamp: self.timestamp,
+            title: self.title.map(owned),
+            vibrate: self.vibrate,
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// A builder to get a `WebNotification` instance.
+///
+/// # Examples
+///
+/// ```rust
+/// use fcm::{WebNotificationAction, WebNotificationBuilder};
+///
+/// let mut builder = WebNotificationBuilder::new();
+/// builder.title("Australia vs New Zealand");
+/// builder.body("3 runs to win in 1 ball");
+/// builder.action(WebNotificationAction::new("watch", "Watch live"));
+/// let notification = builder.finalize();
+/// ```
+pub struct WebNotificationBuilder<'a> {
+    actions: Option<Vec<WebNotificationAction<'a>>>,
+    badge: Option<Cow<'a, str>>,
+    body: Option<Cow<'a, str>>,
+    data: Option<Value>,
+    dir: Option<Direction>,
+    icon: Option<Cow<'a, str>>,
+    image: Option<Cow<'a, str>>,
+    lang: Option<Cow<'a, str>>,
+    renotify: Option<bool>,
+    require_interaction: Option<bool>,
+    silent: Option<bool>,
+    tag: Option<Cow<'a, str>>,
+    timestamp: Option<u64>,
+    title: Option<Cow<'a, str>>,
+    vibrate: Option<Vec<u32>>,
+}
+
+impl<'a> WebNotificationBuilder<'a> {
+    /// Get a new `WebNotificationBuilder` instance.
+    pub fn new() -> WebNotificationBuilder<'a> {
+        WebNotificationBuilder {
+            actions: None,
+            badge: None,
+            body: None,
+            data: None,
+            dir: None,
+            icon: None,
+            image: None,
+            lang: None,
+            renotify: None,
+            require_interaction: None,
+            silent: None,
+            tag: None,
+            timestamp: None,
+            title: None,
+            vibrate: None,
+        }
+    }
+
+    /// Add an action button to the notification. Browsers only display a
+    /// limited number of actions, see `Notification.maxActions`.
+    pub fn action(&mut self, action: WebNotificationAction<'a>) -> &mut Self {
+        self.actions.get_or_insert_with(Vec::new).push(action);
+        self
+    }
+
+    /// URL of the image representing the notification when there is not
+    /// enough space to display the notification itself.
+    pub fn badge(&mut self, badge: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.badge = Some(badge.into());
+        self
+    }
+
+    /// Set the body of the notification
+    pub fn body(&mut self, body: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// Arbitrary data made available to the service worker as
+    /// `notification.data`.
+    pub fn data(&mut self, data: Value) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// The direction in which to display the notification text.
+    pub fn dir(&mut self, dir: Direction) -> &mut Self {
+        self.dir = Some(dir);
+        self
+    }
+
+    /// Set the notification icon.
+    pub fn icon(&mut self, icon: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// URL of an image to be displayed in the notification.
+    pub fn image(&mut self, image: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// The language of the notification as a BCP 47 language tag.
+    pub fn lang(&mut self, lang: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    /// Notify the user again when this notification replaces an older one
+    /// with the same tag.
+    pub fn renotify(&mut self, renotify: bool) -> &mut Self {
+        self.renotify = Some(renotify);
+        self
+    }
+
+    /// Keep the notification visible until the user clicks or dismisses it.
+    pub fn require_interaction(&mut self, require_interaction: bool) -> &mut Self {
+        self.require_interaction = Some(require_interaction);
+        self
+    }
+
+    /// Show the notification without sound or vibration.
+    pub fn silent(&mut self, silent: bool) -> &mut Self {
+        self.silent = Some(silent);
+        self
+    }
+
+    /// Tagging a notification allows you to replace existing notifications
+    /// with the same tag with this new notification
+    pub fn tag(&mut self, tag: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// The time the notification refers to, in milliseconds since the Unix
+    /// epoch.
+    pub fn timestamp(&mut self, timestamp: u64) -> &mut Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    // Set the title of the notification
+    pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// The vibration pattern, alternating vibration and pause durations in
+    /// milliseconds.
+    pub fn vibrate(&mut self, vibrate: &[u32]) -> &mut Self {
+        self.vibrate = Some(vibrate.to_vec());
+        self
+    }
+
+    /// Complete the build and get a `WebNotification` instance
+    pub fn finalize(self) -> WebNotification<'a> {
+        WebNotification {
+            actions: self.actions,
+            badge: self.badge,
+            body: self.body,
+            data: self.data,
+            dir: self.dir,
+            icon: self.icon,
+            image: self.image,
+            lang: self.lang,
+            renotify: self.renotify,
+            require_interaction: self.require_interaction,
+            silent: self.silent,
+            tag: self.tag,
+            timestamp: self.timestamp,
+            title: self.title,
+            vibrate: self.vibrate,
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl<'a> Default for WebNotificationBuilder<'a> {
+    fn default() -> Self {
+        WebNotificationBuilder::new()
+    }
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct WebpushFcmOptions<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    analytics_label: Option<&'a str>,
+    analytics_label: Option<Cow<'a, str>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    link: Option<&'a str>,
+    link: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> WebpushFcmOptions<'a> {
+    /// Convert into options owning their strings.
+    pub fn into_owned(self) -> WebpushFcmOptions<'static> {
+        WebpushFcmOptions {
+            analytics_label: self.analytics_label.map(owned),
+            link: self.link.map(owned),
+            unknown: self.unknown,
+        }
+    }
+}
+
+/// How urgent a webpush message is, letting the push service save the
+/// device's battery for less important messages. Referred from
+/// [RFC 8030](https://tools.ietf.org/html/rfc8030#section-5.3).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum Urgency {
+    VeryLow,
+    Low,
+    Normal,
+    High,
+}
+
+impl Urgency {
+    fn as_str(self) -> &'static str {
+        match self {
+            Urgency::VeryLow => "very-low",
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::High => "high",
+        }
+    }
+}
+
+/// The `Topic` header was longer than 32 characters or contained characters
+/// outside of the URL-safe base64 alphabet.
+#[derive(PartialEq, Debug)]
+pub struct InvalidTopic(pub String);
+
+impl Error for InvalidTopic {}
+
+impl fmt::Display for InvalidTopic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid webpush topic {:?}, expected at most 32 URL-safe base64 characters",
+            self.0
+        )
+    }
+}
+
+/// The typed headers, which can't be repeated among the other headers.
+const TYPED_HEADERS: [&str; 3] = ["TTL", "Urgency", "Topic"];
+
+/// The additional webpush headers weren't a JSON object, or repeated one of
+/// the typed headers.
+#[derive(PartialEq, Debug)]
+pub enum InvalidHeaders {
+    NotAnObject,
+    /// The header is set with the typed `ttl`, `urgency` or `topic` setter
+    /// instead.
+    TypedHeader(String),
+}
+
+impl Error for InvalidHeaders {}
+
+impl fmt::Display for InvalidHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidHeaders::NotAnObject => write!(f, "webpush headers must be a JSON object"),
+            InvalidHeaders::TypedHeader(name) => write!(
+                f,
+                "webpush header {:?} must be set with its typed setter",
+                name
+            ),
+        }
+    }
+}
+
+/// The first header that repeats one of the typed headers, which HTTP
+/// compares case-insensitively.
+fn typed_header(headers: &Map<String, Value>) -> Option<&String> {
+    headers.keys().find(|name| {
+        TYPED_HEADERS
+            .iter()
+            .any(|typed| name.eq_ignore_ascii_case(typed))
+    })
+}
+
+/// The webpush protocol headers, rendered as the string map FCM expects.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WebpushHeaders<'a> {
+    #[serde(
+        rename = "TTL",
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_ttl",
+        deserialize_with = "deserialize_ttl"
+    )]
+    ttl: Option<Duration>,
+
+    #[serde(rename = "Urgency", skip_serializing_if = "Option::is_none")]
+    urgency: Option<Urgency>,
+
+    #[serde(rename = "Topic", skip_serializing_if = "Option::is_none")]
+    topic: Option<Cow<'a, str>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "other_headers",
+        skip_serializing_if = "Option::is_none"
+    )]
+    other: Option<Map<String, Value>>,
+}
+
+impl<'a> WebpushHeaders<'a> {
+    /// Convert into headers owning their strings.
+    pub fn into_owned(self) -> WebpushHeaders<'static> {
+        WebpushHeaders {
+            ttl: self.ttl,
+            urgency: self.urgency,
+            topic: self.topic.map(owned),
+            other: self.other,
+        }
+    }
 }
 
-#[derive(Serialize, Debug, PartialEq)]
+fn serialize_ttl<S>(ttl: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match ttl {
+        Some(ttl) => serializer.serialize_str(&ttl.as_secs().to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_ttl<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let ttl = String::deserialize(deserializer)?;
+
+    ttl.parse()
+        .map(|secs| Some(Duration::from_secs(secs)))
+        .map_err(|_| D::Error::custom(format_args!("invalid TTL {:?}, expected seconds", ttl)))
+}
+
+/// The headers left over by the typed ones, `None` if there are none.
+fn other_headers<'de, D>(deserializer: D) -> Result<Option<Map<String, Value>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let headers = Map::deserialize(deserializer)?;
+
+    if let Some(name) = typed_header(&headers) {
+        return Err(D::Error::custom(format_args!("duplicate webpush header `{}`", name)));
+    }
+
+    if headers.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(headers))
+    }
+}
+
+fn is_valid_topic(topic: &str) -> bool {
+    topic.len() <= 32
+        && topic
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct WebpushConfig<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    data: Option<Value>,
+    data: Option<DataMap>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    headers: Option<Value>,
+    headers: Option<WebpushHeaders<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     notification: Option<WebNotification<'a>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     fcm_options: Option<WebpushFcmOptions<'a>>,
+
+    #[serde(
+        flatten,
+        deserialize_with = "unknown_fields",
+        skip_serializing_if = "Map::is_empty"
+    )]
+    unknown: Map<String, Value>,
+}
+
+impl<'a> WebpushConfig<'a> {
+    /// Convert into a config owning its strings, e.g. to send it from
+    /// another task.
+    pub fn into_owned(self) -> WebpushConfig<'static> {
+        WebpushConfig {
+            data: self.data,
+            headers: self.headers.map(|h| h.into_owned()),
+            notification: self.notification.map(|n| n.into_owned()),
+            fcm_options: self.fcm_options.map(|o| o.into_owned()),
+            unknown: self.unknown,
+        }
+    }
+
+    pub(crate) fn validate(&self, path: &str, violations: &mut Violations) {
+        if let Some(ref data) = self.data {
+            violations.check_data(&format!("{}.data", path), data);
+        }
+
+        if let Some(ttl) = self.headers.as_ref().and_then(|h| h.ttl) {
+            violations.check_ttl_duration(&format!("{}.headers.TTL", path), ttl);
+        }
+    }
 }
 
 pub struct WebpushConfigBuilder<'a> {
-    data: Option<Value>,
-    headers: Option<Value>,
+    data: Option<DataMap>,
+    headers: Option<Map<String, Value>>,
+    ttl: Option<Duration>,
+    urgency: Option<Urgency>,
+    topic: Option<Cow<'a, str>>,
     notification: Option<WebNotification<'a>>,
     fcm_options: Option<WebpushFcmOptions<'a>>,
 }
@@ -49,21 +595,83 @@ impl<'a> WebpushConfigBuilder<'a> {
         WebpushConfigBuilder {
             data: None,
             headers: None,
+            ttl: None,
+            urgency: None,
+            topic: None,
             notification: None,
             fcm_options: None,
         }
     }
 
-    pub fn data(&mut self, data: Value) -> &mut Self {
-        self.data = Some(data);
+    /// Custom key-value pairs for the service worker.
+    pub fn data(&mut self, data: impl Into<DataMap>) -> &mut Self {
+        self.data = Some(data.into());
         self
     }
 
-    pub fn headers(&mut self, headers: Value) -> &mut Self {
+    /// Additional webpush protocol headers as a JSON object of strings. The
+    /// `TTL`, `Urgency` and `Topic` headers are set with their typed setters
+    /// and are rejected here.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::WebpushConfigBuilder;
+    /// use serde_json::json;
+    ///
+    /// let mut builder = WebpushConfigBuilder::new();
+    /// assert!(builder.headers(json!({"X-Custom": "yes"})).is_ok());
+    /// assert!(builder.headers(json!({"ttl": "60"})).is_err());
+    /// ```
+    pub fn headers(&mut self, headers: Value) -> Result<&mut Self, InvalidHeaders> {
+        let headers = match headers {
+            Value::Object(headers) => headers,
+            _ => return Err(InvalidHeaders::NotAnObject),
+        };
+
+        if let Some(name) = typed_header(&headers) {
+            return Err(InvalidHeaders::TypedHeader(name.clone()));
+        }
+
         self.headers = Some(headers);
+        Ok(self)
+    }
+
+    /// How long the push service should keep the message if the device is
+    /// offline. Sent in whole seconds.
+    pub fn ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = Some(ttl);
         self
     }
 
+    /// How urgent the message is for the user.
+    pub fn urgency(&mut self, urgency: Urgency) -> &mut Self {
+        self.urgency = Some(urgency);
+        self
+    }
+
+    /// Messages with the same topic replace each other while the device is
+    /// offline. Must be at most 32 characters of the URL-safe base64
+    /// alphabet.
+    ///
+    /// # Examples:
+    /// ```rust
+    /// use fcm::WebpushConfigBuilder;
+    ///
+    /// let mut builder = WebpushConfigBuilder::new();
+    /// assert!(builder.topic("score-update").is_ok());
+    /// assert!(builder.topic("score update").is_err());
+    /// ```
+    pub fn topic(&mut self, topic: impl Into<Cow<'a, str>>) -> Result<&mut Self, InvalidTopic> {
+        let topic = topic.into();
+
+        if !is_valid_topic(&topic) {
+            return Err(InvalidTopic(topic.into_owned()));
+        }
+
+        self.topic = Some(topic);
+        Ok(self)
+    }
+
     pub fn notification(&mut self, notification: WebNotification<'a>) -> &mut Self {
         self.notification = Some(notification);
         self
@@ -76,11 +684,34 @@ impl<'a> WebpushConfigBuilder<'a> {
 
     /// Complete the build and get a `ApnConfig` instance
     pub fn finalize(self) -> WebpushConfig<'a> {
+        let has_headers = self.headers.is_some()
+            || self.ttl.is_some()
+            || self.urgency.is_some()
+            || self.topic.is_some();
+
+        let headers = if has_headers {
+            Some(WebpushHeaders {
+                ttl: self.ttl,
+                urgency: self.urgency,
+                topic: self.topic,
+                other: self.headers,
+            })
+        } else {
+            None
+        };
+
         WebpushConfig {
             data: self.data,
-            headers: self.headers,
+            headers,
             notification: self.notification,
             fcm_options: self.fcm_options,
+            unknown: Map::new(),
         }
     }
 }
+
+impl<'a> Default for WebpushConfigBuilder<'a> {
+    fn default() -> Self {
+        WebpushConfigBuilder::new()
+    }
+}
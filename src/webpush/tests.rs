@@ -1,40 +1,65 @@
-use serde_json;
-use std::borrow::Cow;
-use crate::NotificationBuilder;
+use crate::{
+    Direction, InvalidHeaders, Urgency, WebNotificationAction, WebNotificationBuilder,
+    WebpushConfigBuilder,
+};
+use crate::{PushSubscription, PushSubscriptionKeys, VapidKey, WebPushClient, WebPushError};
+use crate::testing;
+use super::ece;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::{Body, Response, StatusCode};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[test]
-fn should_be_able_to_render_a_full_notification_to_json() {
-    let mut builder = NotificationBuilder::new();
+fn should_be_able_to_render_a_full_web_notification_to_json() {
+    let mut builder = WebNotificationBuilder::new();
 
     builder
         .title("foo")
         .body("bar")
-        .icon("gif")
-        .sound("pling")
-        .badge("12")
+        .action(WebNotificationAction::new("open", "Open").icon("open.png"))
+        .action(WebNotificationAction::new("dismiss", "Dismiss"))
+        .badge("badge.png")
+        .data(json!({"id": 42}))
+        .dir(Direction::Rtl)
+        .icon("icon.png")
+        .image("image.png")
+        .lang("he")
+        .renotify(true)
+        .require_interaction(true)
+        .silent(false)
         .tag("spook")
-        .color("#666666")
-        .click_action("spam")
-        .body_loc_key("PLAY")
-        .body_loc_args(&["foo", "bar"])
-        .title_loc_key("PAUSE")
-        .title_loc_args(&["omg", "lol"]);
+        .timestamp(1_600_000_000_000)
+        .vibrate(&[200, 100, 200]);
 
     let payload = serde_json::to_string(&builder.finalize()).unwrap();
 
     let expected_payload = json!({
-        "badge": "12",
+        "actions": [
+            {"action": "open", "icon": "open.png", "title": "Open"},
+            {"action": "dismiss", "title": "Dismiss"}
+        ],
+        "badge": "badge.png",
         "body": "bar",
-        "body_loc_args": ["foo", "bar"],
-        "body_loc_key": "PLAY",
-        "click_action": "spam",
-        "color": "#666666",
-        "icon": "gif",
-        "sound": "pling",
+        "data": {"id": 42},
+        "dir": "rtl",
+        "icon": "icon.png",
+        "image": "image.png",
+        "lang": "he",
+        "renotify": true,
+        "requireInteraction": true,
+        "silent": false,
         "tag": "spook",
+        "timestamp": 1_600_000_000_000u64,
         "title": "foo",
-        "title_loc_args": ["omg", "lol"],
-        "title_loc_key": "PAUSE"
+        "vibrate": [200, 100, 200]
     })
     .to_string();
 
@@ -42,153 +67,313 @@ fn should_be_able_to_render_a_full_notification_to_json() {
 }
 
 #[test]
-fn should_set_notification_title() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_leave_nones_out_of_the_web_notification() {
+    let payload = serde_json::to_string(&WebNotificationBuilder::new().finalize()).unwrap();
 
-    assert_eq!(nm.title, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.title("title");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.title, Some("title"));
+    assert_eq!("{}", payload);
 }
 
 #[test]
-fn should_set_notification_body() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_set_web_notification_actions() {
+    let nm = WebNotificationBuilder::new().finalize();
 
-    assert_eq!(nm.body, None);
+    assert_eq!(nm.actions, None);
 
-    let mut builder = NotificationBuilder::new();
-    builder.body("body");
+    let mut builder = WebNotificationBuilder::new();
+    builder.action(WebNotificationAction::new("like", "Like"));
     let nm = builder.finalize();
 
-    assert_eq!(nm.body, Some("body"));
+    assert_eq!(
+        nm.actions,
+        Some(vec![WebNotificationAction::new("like", "Like")])
+    );
 }
 
 #[test]
-fn should_set_notification_icon() {
-    let mut builder = NotificationBuilder::new();
-    builder.icon("newicon");
-    let nm = builder.finalize();
-
-    assert_eq!(nm.icon, Some("newicon"));
-}
+fn should_render_typed_webpush_headers_as_strings() {
+    let mut builder = WebpushConfigBuilder::new();
 
-#[test]
-fn should_set_notification_sound() {
-    let nm = NotificationBuilder::new().finalize();
+    builder
+        .ttl(Duration::from_secs(3600))
+        .urgency(Urgency::VeryLow)
+        .headers(json!({"X-Custom": "yes"}))
+        .unwrap()
+        .topic("score_update-1")
+        .unwrap();
 
-    assert_eq!(nm.sound, None);
+    let payload = serde_json::to_value(builder.finalize()).unwrap();
 
-    let mut builder = NotificationBuilder::new();
-    builder.sound("sound.wav");
-    let nm = builder.finalize();
+    let expected_payload = json!({
+        "headers": {
+            "TTL": "3600",
+            "Urgency": "very-low",
+            "Topic": "score_update-1",
+            "X-Custom": "yes"
+        }
+    });
 
-    assert_eq!(nm.sound, Some("sound.wav"));
+    assert_eq!(expected_payload, payload);
 }
 
 #[test]
-fn should_set_notification_badge() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_read_typed_webpush_headers_from_strings() {
+    let json = json!({
+        "headers": {
+            "TTL": "3600",
+            "Urgency": "very-low",
+            "X-Custom": "yes"
+        }
+    });
+
+    let config: crate::WebpushConfig = serde_json::from_value(json).unwrap();
+
+    let mut builder = WebpushConfigBuilder::new();
+    builder
+        .ttl(Duration::from_secs(3600))
+        .urgency(Urgency::VeryLow)
+        .headers(json!({"X-Custom": "yes"}))
+        .unwrap();
 
-    assert_eq!(nm.badge, None);
+    assert_eq!(builder.finalize(), config);
+}
 
-    let mut builder = NotificationBuilder::new();
-    builder.badge("1");
-    let nm = builder.finalize();
+#[test]
+fn should_reject_a_ttl_not_in_seconds() {
+    let json = json!({"headers": {"TTL": "1h"}});
 
-    assert_eq!(nm.badge, Some("1"));
+    assert!(serde_json::from_value::<crate::WebpushConfig>(json).is_err());
 }
 
 #[test]
-fn should_set_notification_tag() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_reject_typed_headers_among_the_other_headers() {
+    let mut builder = WebpushConfigBuilder::new();
 
-    assert_eq!(nm.tag, None);
+    assert_eq!(
+        Err(InvalidHeaders::TypedHeader("ttl".to_string())),
+        builder.headers(json!({"ttl": "60"})).map(|_| ())
+    );
+    assert_eq!(
+        Err(InvalidHeaders::NotAnObject),
+        builder.headers(json!(["TTL", "60"])).map(|_| ())
+    );
 
-    let mut builder = NotificationBuilder::new();
-    builder.tag("tag");
-    let nm = builder.finalize();
+    let json = json!({"headers": {"TTL": "60", "topic": "news"}});
 
-    assert_eq!(nm.tag, Some("tag"));
+    assert!(serde_json::from_value::<crate::WebpushConfig>(json).is_err());
 }
 
 #[test]
-fn should_set_notification_color() {
-    let nm = NotificationBuilder::new().finalize();
-
-    assert_eq!(nm.color, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.color("color");
-    let nm = builder.finalize();
+fn should_leave_headers_out_without_any_set() {
+    let payload = serde_json::to_string(&WebpushConfigBuilder::new().finalize()).unwrap();
 
-    assert_eq!(nm.color, Some("color"));
+    assert_eq!("{}", payload);
 }
 
 #[test]
-fn should_set_notification_click_action() {
-    let nm = NotificationBuilder::new().finalize();
+fn should_reject_invalid_webpush_topics() {
+    let mut builder = WebpushConfigBuilder::new();
 
-    assert_eq!(nm.click_action, None);
-
-    let mut builder = NotificationBuilder::new();
-    builder.click_action("action");
-    let nm = builder.finalize();
+    assert!(builder.topic("abcdefghijklmnopqrstuvwxyz012345").is_ok());
+    assert!(builder.topic("abcdefghijklmnopqrstuvwxyz0123456").is_err());
+    assert!(builder.topic("no spaces").is_err());
+    assert!(builder.topic("no+slash/").is_err());
+}
 
-    assert_eq!(nm.click_action, Some("action"));
+fn decrypt(ua_secret: &SecretKey, auth_secret: &[u8], body: &[u8]) -> Vec<u8> {
+    let salt = &body[..16];
+    assert_eq!(
+        4096,
+        u32::from_be_bytes([body[16], body[17], body[18], body[19]])
+    );
+    assert_eq!(65, body[20]);
+    let as_public = &body[21..86];
+
+    let as_public_key = PublicKey::from_sec1_bytes(as_public).unwrap();
+    let ecdh_secret =
+        p256::ecdh::diffie_hellman(ua_secret.to_nonzero_scalar(), as_public_key.as_affine());
+    let ua_public = ua_secret.public_key().to_encoded_point(false);
+
+    let (cek, nonce) = ece::derive_key_and_nonce(
+        ecdh_secret.raw_secret_bytes(),
+        auth_secret,
+        salt,
+        ua_public.as_bytes(),
+        as_public,
+    );
+
+    let mut record = Aes128Gcm::new_from_slice(&cek)
+        .unwrap()
+        .decrypt(&Nonce::from(nonce), &body[86..])
+        .unwrap();
+
+    assert_eq!(Some(2), record.pop());
+    record
 }
 
-#[test]
-fn should_set_notification_body_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
+fn verify_vapid(authorization: &str, audience: &str) {
+    let mut parts = authorization.trim_start_matches("vapid ").split(", ");
+    let token = parts.next().unwrap().trim_start_matches("t=");
+    let key = parts.next().unwrap().trim_start_matches("k=");
 
-    assert_eq!(nm.body_loc_key, None);
+    let verifying_key =
+        VerifyingKey::from_sec1_bytes(&URL_SAFE_NO_PAD.decode(key).unwrap()).unwrap();
 
-    let mut builder = NotificationBuilder::new();
-    builder.body_loc_key("key");
-    let nm = builder.finalize();
+    let (message, signature) = token.split_at(token.rfind('.').unwrap());
+    let signature =
+        Signature::from_slice(&URL_SAFE_NO_PAD.decode(&signature[1..]).unwrap()).unwrap();
+    verifying_key
+        .verify(message.as_bytes(), &signature)
+        .unwrap();
 
-    assert_eq!(nm.body_loc_key, Some("key"));
-}
+    let claims = message.split('.').nth(1).unwrap();
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap();
 
-#[test]
-fn should_set_notification_body_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
+    assert_eq!(audience, claims["aud"]);
+    assert_eq!("mailto:push@example.com", claims["sub"]);
+}
 
-    assert_eq!(nm.body_loc_args, None);
+fn subscription(endpoint: String, ua_secret: &SecretKey, auth_secret: &[u8]) -> PushSubscription {
+    PushSubscription {
+        endpoint,
+        keys: PushSubscriptionKeys {
+            p256dh: URL_SAFE_NO_PAD
+                .encode(ua_secret.public_key().to_encoded_point(false).as_bytes()),
+            auth: URL_SAFE_NO_PAD.encode(auth_secret),
+        },
+    }
+}
 
-    let mut builder = NotificationBuilder::new();
-    builder.body_loc_args(&["args"]);
-    let nm = builder.finalize();
+fn vapid_key() -> VapidKey {
+    let secret = SecretKey::random(&mut OsRng);
+    VapidKey::from_base64(
+        &URL_SAFE_NO_PAD.encode(secret.to_bytes()),
+        "mailto:push@example.com",
+    )
+    .unwrap()
+}
 
-    assert_eq!(nm.body_loc_args, Some(vec![Cow::from("args")]));
+#[tokio::test]
+async fn should_send_an_encrypted_payload_to_the_push_service() {
+    let ua_secret = SecretKey::random(&mut OsRng);
+    let auth_secret = [7u8; 16];
+    let received = Arc::new(Mutex::new(None));
+
+    let addr = {
+        let ua_secret = ua_secret.clone();
+        let received = received.clone();
+
+        testing::serve(move |parts, body| {
+            let headers = &parts.headers;
+            assert_eq!("aes128gcm", headers["content-encoding"]);
+            assert_eq!("60", headers["ttl"]);
+            assert_eq!("high", headers["urgency"]);
+            assert_eq!("scores", headers["topic"]);
+
+            let audience = format!("http://{}", headers["host"].to_str().unwrap());
+            verify_vapid(headers["authorization"].to_str().unwrap(), &audience);
+
+            *received.lock().unwrap() = Some((
+                parts.uri.path().to_string(),
+                decrypt(&ua_secret, &auth_secret, &body),
+            ));
+
+            Response::builder()
+                .status(StatusCode::CREATED)
+                .body(Body::empty())
+                .unwrap()
+        })
+    };
+
+    let subscription = subscription(
+        format!("http://{}/push/abc", addr),
+        &ua_secret,
+        &auth_secret,
+    );
+
+    let mut builder = WebpushConfigBuilder::new();
+    builder
+        .ttl(Duration::from_secs(60))
+        .urgency(Urgency::High)
+        .topic("scores")
+        .unwrap();
+
+    WebPushClient::new(vapid_key())
+        .send(&subscription, b"{\"title\":\"Hey!\"}", &builder.finalize())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Some(("/push/abc".to_string(), b"{\"title\":\"Hey!\"}".to_vec())),
+        *received.lock().unwrap()
+    );
 }
 
-#[test]
-fn should_set_notification_title_loc_key() {
-    let nm = NotificationBuilder::new().finalize();
+#[tokio::test]
+async fn should_report_expired_subscriptions() {
+    let ua_secret = SecretKey::random(&mut OsRng);
+    let auth_secret = [7u8; 16];
+
+    let addr = testing::serve(|_, _| {
+        Response::builder()
+            .status(StatusCode::GONE)
+            .body(Body::empty())
+            .unwrap()
+    });
+
+    let subscription = subscription(
+        format!("http://{}/push/abc", addr),
+        &ua_secret,
+        &auth_secret,
+    );
+
+    let result = WebPushClient::new(vapid_key())
+        .send(
+            &subscription,
+            b"hi",
+            &WebpushConfigBuilder::new().finalize(),
+        )
+        .await;
+
+    assert_eq!(Err(WebPushError::SubscriptionGone), result);
+}
 
-    assert_eq!(nm.title_loc_key, None);
+#[tokio::test]
+async fn should_reject_payloads_not_fitting_a_single_record() {
+    let ua_secret = SecretKey::random(&mut OsRng);
+    let subscription = subscription("http://localhost/push".to_string(), &ua_secret, &[7u8; 16]);
 
-    let mut builder = NotificationBuilder::new();
-    builder.title_loc_key("key");
-    let nm = builder.finalize();
+    let result = WebPushClient::new(vapid_key())
+        .send(
+            &subscription,
+            &[0u8; 4096],
+            &WebpushConfigBuilder::new().finalize(),
+        )
+        .await;
 
-    assert_eq!(nm.title_loc_key, Some("key"));
+    assert_eq!(Err(WebPushError::PayloadTooLarge), result);
 }
 
-#[test]
-fn should_set_notification_title_loc_args() {
-    let nm = NotificationBuilder::new().finalize();
+#[tokio::test]
+async fn should_name_invalid_keys_without_their_value() {
+    let ua_secret = SecretKey::random(&mut OsRng);
+    let mut subscription = subscription("http://localhost/push".to_string(), &ua_secret, &[7u8; 16]);
+    subscription.keys.auth = "secret*auth".to_string();
 
-    assert_eq!(nm.title_loc_args, None);
+    let result = WebPushClient::new(vapid_key())
+        .send(&subscription, b"hi", &WebpushConfigBuilder::new().finalize())
+        .await;
 
-    let mut builder = NotificationBuilder::new();
-    builder.title_loc_args(&["args"]);
-    let nm = builder.finalize();
+    assert_eq!(
+        Err(WebPushError::InvalidKey("subscription auth secret is not valid base64url".to_string())),
+        result
+    );
+
+    let error = VapidKey::from_base64("secret*key", "mailto:push@example.com").err();
 
-    assert_eq!(nm.title_loc_args, Some(vec![Cow::from("args")]));
+    assert_eq!(
+        Some(WebPushError::InvalidKey("VAPID private key is not valid base64url".to_string())),
+        error
+    );
+    assert_eq!("InvalidKey(<redacted>)", format!("{:?}", error.unwrap()));
 }